@@ -0,0 +1,70 @@
+//! Compares the protobuf encoder with the direct encoder used by the writer.
+//!
+//! Run with `cargo bench --bench encode`. Every iteration clones the message, as the writer
+//! receives owned messages; the `clone` line measures that cost alone.
+
+use std::hint::black_box;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use dnstap::*;
+use protobuf::Message;
+
+const ITERATIONS: u32 = 1_000_000;
+const BUFFER_SIZE: usize = 262_144;
+
+fn dns_message() -> DNSMessage {
+    let mut packet = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 1];
+    packet.extend_from_slice(b"\x03www\x07example\x03com\x00\x00\x01\x00\x01");
+    packet.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0x01, 0x2c, 0, 4, 192, 0, 2, 1]);
+    packet.extend_from_slice(&[0, 0, 41, 0x04, 0xd0, 0, 0, 0, 0, 0, 0]);
+    DNSMessage::builder(MessageType::RESOLVER_RESPONSE)
+        .identity(b"resolver.example")
+        .version(b"1.0")
+        .query_addr("192.0.2.10:53001".parse().unwrap())
+        .response_addr("192.0.2.53:53".parse().unwrap())
+        .protocol(SocketProtocol::UDP)
+        .query_time(Duration::new(1_700_000_000, 123_456_789))
+        .response_time(Duration::new(1_700_000_000, 223_456_789))
+        .response_packet(&packet)
+        .bailiwick("example.com.")
+        .build()
+        .unwrap()
+}
+
+fn run<F: FnMut(DNSMessage, &mut BufWriter<io::Sink>)>(name: &str, mut encode: F) {
+    let dns_message = dns_message();
+    let mut writer = BufWriter::with_capacity(BUFFER_SIZE, io::sink());
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        encode(black_box(dns_message.clone()), &mut writer);
+    }
+    writer.flush().unwrap();
+    let elapsed = start.elapsed();
+    println!(
+        "{:<10} {:>8.1} ns/message {:>10.0} messages/s",
+        name,
+        elapsed.as_nanos() as f64 / f64::from(ITERATIONS),
+        f64::from(ITERATIONS) / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    run("clone", |dns_message, _| {
+        black_box(dns_message);
+    });
+    run("protobuf", |dns_message, writer| {
+        let bytes = dns_message
+            .into_protobuf()
+            .unwrap()
+            .write_to_bytes()
+            .unwrap();
+        writer
+            .write_all(&(bytes.len() as u32).to_be_bytes())
+            .unwrap();
+        writer.write_all(&bytes).unwrap();
+    });
+    run("direct", |dns_message, writer| {
+        dns_message.encode_frame(writer).unwrap();
+    });
+}
@@ -0,0 +1,243 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+
+use crate::dns_message::DNSMessage;
+use crate::SocketFamily;
+
+/// How the query and response addresses of DNS messages are anonymized.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Anonymization {
+    /// Keeps the first `ipv4_prefix` bits of IPv4 addresses and the first `ipv6_prefix`
+    /// bits of IPv6 addresses, and clears the remaining bits.
+    Prefix { ipv4_prefix: u8, ipv6_prefix: u8 },
+    /// Crypto-PAn prefix-preserving encryption. Addresses sharing a prefix are mapped to
+    /// addresses sharing a prefix of the same length. The first half of the key is the AES
+    /// key, the second half is used to derive the padding.
+    CryptoPAn([u8; 32]),
+    /// ipcrypt-deterministic encryption: the address, as a 16-byte IPv6 or IPv4-mapped
+    /// address, is encrypted with AES-128. IPv4 addresses are usually mapped to IPv6
+    /// addresses, and can be recovered with the key.
+    IpCrypt([u8; 16]),
+}
+
+impl fmt::Debug for Anonymization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anonymization::Prefix {
+                ipv4_prefix,
+                ipv6_prefix,
+            } => f
+                .debug_struct("Prefix")
+                .field("ipv4_prefix", ipv4_prefix)
+                .field("ipv6_prefix", ipv6_prefix)
+                .finish(),
+            Anonymization::CryptoPAn(_) => f.write_str("CryptoPAn(..)"),
+            Anonymization::IpCrypt(_) => f.write_str("IpCrypt(..)"),
+        }
+    }
+}
+
+enum Method {
+    Prefix { ipv4_mask: u32, ipv6_mask: u128 },
+    CryptoPAn { cipher: Aes128, pad: u128 },
+    IpCrypt { cipher: Aes128 },
+}
+
+/// Rewrites the addresses of DNS messages according to an `Anonymization` method.
+///
+/// The key schedule is computed once, so an `Anonymizer` should be reused.
+pub struct Anonymizer {
+    method: Method,
+}
+
+impl Anonymizer {
+    /// Creates an `Anonymizer`, and computes the key schedule if a key is used.
+    pub fn new(anonymization: &Anonymization) -> Anonymizer {
+        let method = match *anonymization {
+            Anonymization::Prefix {
+                ipv4_prefix,
+                ipv6_prefix,
+            } => Method::Prefix {
+                ipv4_mask: u32::MAX
+                    .checked_shl(32 - u32::from(ipv4_prefix.min(32)))
+                    .unwrap_or(0),
+                ipv6_mask: u128::MAX
+                    .checked_shl(128 - u32::from(ipv6_prefix.min(128)))
+                    .unwrap_or(0),
+            },
+            Anonymization::CryptoPAn(ref key) => {
+                let cipher = Aes128::new(key[..16].into());
+                let mut pad = [0u8; 16];
+                pad.copy_from_slice(&key[16..]);
+                encrypt_block(&cipher, &mut pad);
+                Method::CryptoPAn {
+                    cipher,
+                    pad: u128::from_be_bytes(pad),
+                }
+            }
+            Anonymization::IpCrypt(ref key) => Method::IpCrypt {
+                cipher: Aes128::new(key.into()),
+            },
+        };
+        Anonymizer { method }
+    }
+
+    /// Returns the anonymized version of an address.
+    pub fn anonymize_ip(&self, ip: IpAddr) -> IpAddr {
+        match self.method {
+            Method::Prefix {
+                ipv4_mask,
+                ipv6_mask,
+            } => match ip {
+                IpAddr::V4(ip4) => IpAddr::V4(Ipv4Addr::from(u32::from(ip4) & ipv4_mask)),
+                IpAddr::V6(ip6) => IpAddr::V6(Ipv6Addr::from(u128::from(ip6) & ipv6_mask)),
+            },
+            Method::CryptoPAn { ref cipher, pad } => match ip {
+                IpAddr::V4(ip4) => {
+                    let addr = u128::from(u32::from(ip4)) << 96;
+                    IpAddr::V4(Ipv4Addr::from(
+                        (cryptopan(cipher, pad, addr, 32) >> 96) as u32,
+                    ))
+                }
+                IpAddr::V6(ip6) => {
+                    IpAddr::V6(Ipv6Addr::from(cryptopan(cipher, pad, u128::from(ip6), 128)))
+                }
+            },
+            Method::IpCrypt { ref cipher } => {
+                let mut block = match ip {
+                    IpAddr::V4(ip4) => ip4.to_ipv6_mapped().octets(),
+                    IpAddr::V6(ip6) => ip6.octets(),
+                };
+                encrypt_block(cipher, &mut block);
+                let ip6 = Ipv6Addr::from(block);
+                match ip6.to_ipv4_mapped() {
+                    Some(ip4) => IpAddr::V4(ip4),
+                    None => IpAddr::V6(ip6),
+                }
+            }
+        }
+    }
+
+    /// Anonymizes the query and response addresses of a DNS message. The socket family is
+    /// updated if the address family changed.
+    pub fn anonymize(&self, dns_message: &mut DNSMessage) {
+        dns_message.query_address = dns_message.query_address.map(|ip| self.anonymize_ip(ip));
+        dns_message.response_address = dns_message.response_address.map(|ip| self.anonymize_ip(ip));
+        if dns_message.socket_family.is_some() {
+            match dns_message.query_address.or(dns_message.response_address) {
+                Some(IpAddr::V4(_)) => dns_message.socket_family = Some(SocketFamily::INET),
+                Some(IpAddr::V6(_)) => dns_message.socket_family = Some(SocketFamily::INET6),
+                None => {}
+            }
+        }
+    }
+}
+
+fn encrypt_block(cipher: &Aes128, block: &mut [u8; 16]) {
+    cipher.encrypt_block(block.into());
+}
+
+/// Encrypts the first `bits` bits of `addr`, stored in the most significant bits.
+fn cryptopan(cipher: &Aes128, pad: u128, addr: u128, bits: u32) -> u128 {
+    let mut otp = 0u128;
+    for pos in 0..bits {
+        let mask = u128::MAX.checked_shl(128 - pos).unwrap_or(0);
+        let mut block = ((addr & mask) | (pad & !mask)).to_be_bytes();
+        encrypt_block(cipher, &mut block);
+        otp |= u128::from(block[0] >> 7) << (127 - pos);
+    }
+    addr ^ otp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use crate::{MessageType, SocketProtocol};
+
+    const IPCRYPT_KEY: [u8; 16] = [
+        0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f,
+        0x3c,
+    ];
+
+    /// Reference vectors from the original Crypto-PAn implementation and the ipcrypt
+    /// specification.
+    #[test]
+    fn reference_vectors() {
+        let key = [
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ];
+        let anonymizer = Anonymizer::new(&Anonymization::CryptoPAn(key));
+        for (input, output) in [
+            ("128.11.68.132", "135.242.180.132"),
+            ("129.118.74.4", "134.136.186.123"),
+            ("130.132.252.244", "133.68.164.234"),
+            ("192.102.249.13", "252.138.62.131"),
+            ("207.105.49.5", "241.118.205.138"),
+        ]
+        .iter()
+        {
+            assert_eq!(anonymizer.anonymize_ip(ip(input)), ip(output));
+        }
+        let anonymizer = Anonymizer::new(&Anonymization::IpCrypt(IPCRYPT_KEY));
+        assert_eq!(
+            anonymizer.anonymize_ip(ip("192.0.2.1")),
+            ip("1dbd:c1b9:fff1:7586:7d0b:67b4:e76e:4777")
+        );
+    }
+
+    #[test]
+    fn prefix() {
+        let anonymizer = Anonymizer::new(&Anonymization::Prefix {
+            ipv4_prefix: 24,
+            ipv6_prefix: 32,
+        });
+        assert_eq!(anonymizer.anonymize_ip(ip("192.0.2.123")), ip("192.0.2.0"));
+        assert_eq!(
+            anonymizer.anonymize_ip(ip("2001:db8:1:2::53")),
+            ip("2001:db8::")
+        );
+        let anonymizer = Anonymizer::new(&Anonymization::Prefix {
+            ipv4_prefix: 0,
+            ipv6_prefix: 200,
+        });
+        assert_eq!(anonymizer.anonymize_ip(ip("192.0.2.123")), ip("0.0.0.0"));
+        assert_eq!(
+            anonymizer.anonymize_ip(ip("2001:db8:1:2::53")),
+            ip("2001:db8:1:2::53")
+        );
+    }
+
+    #[test]
+    fn socket_family_follows_the_addresses() {
+        let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_QUERY);
+        dns_message.socket_family = Some(SocketFamily::INET);
+        dns_message.socket_protocol = Some(SocketProtocol::UDP);
+        dns_message.query_address = Some(ip("192.0.2.1"));
+        dns_message.query_port = Some(53000);
+        dns_message.response_address = Some(ip("192.0.2.53"));
+        dns_message.response_port = Some(53);
+        dns_message.query_time = Some(std::time::Duration::from_secs(1_700_000_000));
+        dns_message.query_packet = Some(dns_query(1, "example.com", 1));
+        Anonymizer::new(&Anonymization::IpCrypt(IPCRYPT_KEY)).anonymize(&mut dns_message);
+        assert_eq!(
+            dns_message.query_address,
+            Some(ip("1dbd:c1b9:fff1:7586:7d0b:67b4:e76e:4777"))
+        );
+        assert!(matches!(dns_message.response_address, Some(IpAddr::V6(_))));
+        assert_eq!(dns_message.socket_family, Some(SocketFamily::INET6));
+        assert_eq!(dns_message.validate(), Ok(()));
+        let mut encoded = vec![];
+        dns_message.encode(&mut encoded).unwrap();
+        assert_eq!(DNSMessage::from_bytes(&encoded).unwrap(), dns_message);
+
+        let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_QUERY);
+        dns_message.query_address = Some(ip("192.0.2.1"));
+        Anonymizer::new(&Anonymization::IpCrypt(IPCRYPT_KEY)).anonymize(&mut dns_message);
+        assert_eq!(dns_message.socket_family, None);
+    }
+}
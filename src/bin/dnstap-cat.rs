@@ -0,0 +1,158 @@
+use dnstap::{DNSMessage, DNSTapCollectorBuilder, DNSTapReader, PcapReaderBuilder, PcapWriter};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::process;
+use std::sync::Mutex;
+
+const USAGE: &str = "Usage: dnstap-cat [-q | -v | -y | -j [-d] | -p <pcap file>] \
+                     (-r <file> | -u <socket path> | -l <address:port> | -P <pcap file>)
+
+  -r <file>          read dnstap data from a file (\"-\" for standard input)
+  -u <socket path>   receive dnstap data on a UNIX socket
+  -l <address:port>  receive dnstap data on a TCP socket
+  -P <pcap file>     read DNS traffic from a pcap or pcapng capture
+  -q                 quiet text output (default)
+  -v                 full text output, with DNS packets in dig format
+  -y                 verbose YAML output
+  -j                 JSON Lines output (requires the `serde` feature)
+  -d                 include parsed DNS fields in JSON output
+  -p <pcap file>     convert to a pcap file instead of printing messages";
+
+enum Source {
+    File(String),
+    UnixSocket(String),
+    Tcp(String),
+    Pcap(String),
+}
+
+enum Output {
+    Quiet,
+    Full,
+    Yaml,
+    #[cfg(feature = "serde")]
+    Json {
+        dns_fields: bool,
+    },
+    Pcap(Mutex<PcapWriter<BufWriter<File>>>),
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1)
+}
+
+fn parse_args() -> io::Result<(Source, Output)> {
+    let mut source = None;
+    let mut output = Output::Quiet;
+    #[cfg(feature = "serde")]
+    let mut dns_fields = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "-r" => source = Some(Source::File(value())),
+            "-u" => source = Some(Source::UnixSocket(value())),
+            "-l" => source = Some(Source::Tcp(value())),
+            "-P" => source = Some(Source::Pcap(value())),
+            "-q" => output = Output::Quiet,
+            "-v" => output = Output::Full,
+            "-y" => output = Output::Yaml,
+            #[cfg(feature = "serde")]
+            "-j" => output = Output::Json { dns_fields: false },
+            #[cfg(feature = "serde")]
+            "-d" => dns_fields = true,
+            "-p" => output = Output::Pcap(Mutex::new(PcapWriter::create(value())?)),
+            _ => usage(),
+        }
+    }
+    // `-d` modifies the JSON output, whatever the order of the options
+    #[cfg(feature = "serde")]
+    if dns_fields {
+        match output {
+            Output::Json { .. } => output = Output::Json { dns_fields },
+            _ => usage(),
+        }
+    }
+    Ok((source.unwrap_or_else(|| usage()), output))
+}
+
+impl Output {
+    fn write(&self, dns_message: &DNSMessage) {
+        let res = match self {
+            Output::Quiet => writeln!(io::stdout().lock(), "{}", dns_message.quiet_text()),
+            Output::Full => writeln!(io::stdout().lock(), "{}", dns_message.full_text()),
+            Output::Yaml => writeln!(io::stdout().lock(), "{}", dns_message.yaml_text()),
+            #[cfg(feature = "serde")]
+            Output::Json { dns_fields: false } => {
+                writeln!(io::stdout().lock(), "{}", dns_message.json_text())
+            }
+            #[cfg(feature = "serde")]
+            Output::Json { dns_fields: true } => writeln!(
+                io::stdout().lock(),
+                "{}",
+                dns_message.json_text().with_dns_fields()
+            ),
+            Output::Pcap(pcap_writer) => {
+                let mut pcap_writer = pcap_writer.lock().unwrap();
+                if let Err(e) = pcap_writer.write_message(dns_message) {
+                    eprintln!("dnstap-cat: {}", e);
+                }
+                pcap_writer.flush()
+            }
+        };
+        if res.is_err() {
+            process::exit(0);
+        }
+    }
+
+    fn finish(&self) -> io::Result<()> {
+        match self {
+            Output::Pcap(pcap_writer) => pcap_writer.lock().unwrap().flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn read<I: Iterator<Item = io::Result<DNSMessage>>>(reader: I, output: Output) -> io::Result<()> {
+    for dns_message in reader {
+        output.write(&dns_message?);
+    }
+    output.finish()
+}
+
+fn collect(builder: DNSTapCollectorBuilder, output: Output) -> io::Result<()> {
+    let dnstap_collector = builder
+        .listen()?
+        .start(move |_, dns_message| output.write(&dns_message))?;
+    let _ = dnstap_collector.join();
+    Ok(())
+}
+
+fn run(source: Source, output: Output) -> io::Result<()> {
+    match source {
+        Source::File(path) if path == "-" => {
+            read(DNSTapReader::new(BufReader::new(io::stdin())), output)
+        }
+        Source::File(path) => read(DNSTapReader::open(path)?, output),
+        Source::UnixSocket(path) => collect(
+            DNSTapCollectorBuilder::default().unix_socket_path(path),
+            output,
+        ),
+        Source::Tcp(addr) => match addr.parse() {
+            Ok(addr) => collect(
+                DNSTapCollectorBuilder::default().tcp_socket_addr(addr),
+                output,
+            ),
+            Err(_) => usage(),
+        },
+        Source::Pcap(path) => read(PcapReaderBuilder::default().open(path)?, output),
+    }
+}
+
+fn main() {
+    if let Err(e) = parse_args().and_then(|(source, output)| run(source, output)) {
+        eprintln!("dnstap-cat: {}", e);
+        process::exit(1);
+    }
+}
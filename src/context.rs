@@ -1,13 +1,17 @@
 use crate::dns_message::*;
-use framestream::EncoderWriter;
+use crate::dnstap_encoder::Encoder;
+use crate::frame_stream::FrameWriter;
+use crate::transform::Transforms;
 use mio::deprecated::{UnixSocket, UnixStream};
+use mio::net::TcpStream;
 use mio::timer::Timeout;
 use mio::*;
-use protobuf::*;
-use std::io::{self, BufWriter, Write};
+use std::io::{self, Write};
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time;
-use std::usize;
 
 pub const BUFFER_SIZE: usize = 262_144;
 pub const CONTENT_TYPE: &str = "protobuf:dnstap.Dnstap";
@@ -15,7 +19,73 @@ pub const RETRY_DELAY_SECS: u64 = 1;
 
 pub const NOTIFY_TOK: Token = Token(usize::MAX - 1);
 pub const TIMER_TOK: Token = Token(usize::MAX - 2);
-pub const UNIX_SOCKET_TOK: Token = Token(usize::MAX - 3);
+pub const STREAM_TOK: Token = Token(usize::MAX - 3);
+
+/// A connection to a dnstap collector.
+pub enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Stream {
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Unix(unix_stream) => unix_stream.try_clone().map(Stream::Unix),
+            Stream::Tcp(tcp_stream) => tcp_stream.try_clone().map(Stream::Tcp),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Unix(unix_stream) => unix_stream.write(buf),
+            Stream::Tcp(tcp_stream) => tcp_stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Unix(unix_stream) => unix_stream.flush(),
+            Stream::Tcp(tcp_stream) => tcp_stream.flush(),
+        }
+    }
+}
+
+impl Evented for Stream {
+    fn register(
+        &self,
+        poll: &Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        match self {
+            Stream::Unix(unix_stream) => unix_stream.register(poll, token, interest, opts),
+            Stream::Tcp(tcp_stream) => tcp_stream.register(poll, token, interest, opts),
+        }
+    }
+
+    fn reregister(
+        &self,
+        poll: &Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        match self {
+            Stream::Unix(unix_stream) => unix_stream.reregister(poll, token, interest, opts),
+            Stream::Tcp(tcp_stream) => tcp_stream.reregister(poll, token, interest, opts),
+        }
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        match self {
+            Stream::Unix(unix_stream) => unix_stream.deregister(poll),
+            Stream::Tcp(tcp_stream) => tcp_stream.deregister(poll),
+        }
+    }
+}
 
 pub struct Context {
     pub mio_poll: Poll,
@@ -23,17 +93,21 @@ pub struct Context {
     pub retry_timeout: Option<Timeout>,
     pub dnstap_rx: channel::Receiver<DNSMessage>,
     pub unix_socket_path: Option<PathBuf>,
-    pub unix_stream: Option<UnixStream>,
-    pub frame_stream: Option<EncoderWriter<BufWriter<UnixStream>>>,
+    pub tcp_socket_addr: Option<SocketAddr>,
+    pub extra: Option<Vec<u8>>,
+    pub transforms: Transforms,
+    pub dropped_messages: Arc<AtomicUsize>,
+    pub stream: Option<Stream>,
+    pub frame_stream: Option<FrameWriter<Stream>>,
 }
 
 impl Context {
     pub fn message_cb(&mut self) {
-        if let Some(ref unix_stream) = self.unix_stream {
+        if let Some(ref stream) = self.stream {
             self.mio_poll
                 .reregister(
-                    unix_stream,
-                    UNIX_SOCKET_TOK,
+                    stream,
+                    STREAM_TOK,
                     Ready::writable(),
                     PollOpt::edge() | PollOpt::oneshot(),
                 )
@@ -43,11 +117,11 @@ impl Context {
 
     pub fn write_cb(&mut self, event: Event) {
         if self.frame_stream.is_none() {
-            debug_assert!(self.unix_stream.is_none());
+            debug_assert!(self.stream.is_none());
             return;
         }
         if event.kind().is_hup() || event.kind().is_error() {
-            self.unix_stream = None;
+            self.stream = None;
             self.frame_stream = None;
             self.retry_timeout
                 .take()
@@ -60,20 +134,29 @@ impl Context {
             return;
         }
         let frame_stream = self.frame_stream.as_mut().unwrap();
-        while let Ok(dns_message) = self.dnstap_rx.try_recv() {
-            let dns_message_bytes = dns_message.into_protobuf().write_to_bytes().unwrap();
-            match frame_stream.write_all(&dns_message_bytes).or_else(|_| {
-                let _ = frame_stream.flush();
-                frame_stream.write_all(&dns_message_bytes)
-            }) {
+        while let Ok(mut dns_message) = self.dnstap_rx.try_recv() {
+            if dns_message.extra.is_none() {
+                dns_message.extra = self.extra.clone();
+            }
+            self.transforms.apply(&mut dns_message);
+            // Messages were checked by the sender, but the transformations may have made
+            // them impossible to encode
+            let encoder = match Encoder::new(&dns_message) {
+                Ok(encoder) => encoder,
+                Err(_) => {
+                    self.dropped_messages.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            };
+            match frame_stream.write_frame(encoder.len(), |writer| encoder.write_to(writer)) {
                 Err(ref e)
                     if e.kind() == io::ErrorKind::WouldBlock
                         || e.kind() == io::ErrorKind::Interrupted =>
                 {
                     self.mio_poll
                         .reregister(
-                            self.unix_stream.as_ref().unwrap(),
-                            UNIX_SOCKET_TOK,
+                            self.stream.as_ref().unwrap(),
+                            STREAM_TOK,
                             Ready::writable(),
                             PollOpt::edge() | PollOpt::oneshot(),
                         )
@@ -100,13 +183,18 @@ impl Context {
 
     pub fn connect(&mut self) {
         if self.frame_stream.is_some() {
-            debug_assert!(self.unix_stream.is_some());
+            debug_assert!(self.stream.is_some());
             return;
         }
-        assert!(self.unix_socket_path.is_some());
-        let unix_socket = UnixSocket::stream().unwrap();
-        let unix_stream = match unix_socket.connect(&self.unix_socket_path.clone().unwrap()) {
-            Ok((unix_stream, _connected)) => unix_stream,
+        let stream = match (&self.unix_socket_path, &self.tcp_socket_addr) {
+            (Some(unix_socket_path), _) => UnixSocket::stream()
+                .and_then(|unix_socket| unix_socket.connect(unix_socket_path))
+                .map(|(unix_stream, _connected)| Stream::Unix(unix_stream)),
+            (None, Some(tcp_socket_addr)) => TcpStream::connect(tcp_socket_addr).map(Stream::Tcp),
+            (None, None) => unreachable!(),
+        };
+        let stream = match stream {
+            Ok(stream) => stream,
             Err(_) => {
                 self.retry_timeout
                     .take()
@@ -119,19 +207,16 @@ impl Context {
                 return;
             }
         };
-        let frame_stream = EncoderWriter::new(
-            BufWriter::with_capacity(BUFFER_SIZE, unix_stream.try_clone().unwrap()),
-            Some(CONTENT_TYPE.to_owned()),
-        );
+        let frame_stream = FrameWriter::new(stream.try_clone().unwrap(), BUFFER_SIZE, CONTENT_TYPE);
         self.mio_poll
             .register(
-                &unix_stream,
-                UNIX_SOCKET_TOK,
+                &stream,
+                STREAM_TOK,
                 Ready::writable(),
                 PollOpt::edge() | PollOpt::oneshot(),
             )
             .unwrap();
-        self.unix_stream = Some(unix_stream);
+        self.stream = Some(stream);
         self.frame_stream = Some(frame_stream);
     }
 }
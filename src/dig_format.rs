@@ -0,0 +1,1202 @@
+use std::convert::TryInto;
+use std::fmt::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::dns_packet::{class_name, rr_type_name, DNSName, DNSPacket};
+use crate::timestamp;
+
+const DNS_HEADER_SIZE: usize = 12;
+const TYPE_OPT: u16 = 41;
+const OPCODE_UPDATE: u8 = 5;
+/// Length of the chunks `dig` splits long hex and base64 fields into.
+const SPLIT_WIDTH: usize = 56;
+const TAB_WIDTH: usize = 8;
+
+const BASE32HEX_ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+
+/// Rendering of a DNS packet in `dig` presentation format: header, flags, OPT
+/// pseudo-section, and the question, answer, authority and additional sections.
+///
+/// Common record types are decoded, other types are rendered in the generic RFC 3597
+/// format. As with the default options of `dig`, records are aligned on tab stops and long
+/// hex and base64 fields are split into chunks. Truncated and malformed packets are
+/// rendered up to the point where parsing failed, followed by a warning.
+pub struct DigText<'a>(DNSPacket<'a>);
+
+impl<'a> DNSPacket<'a> {
+    /// Returns an object whose `Display` implementation renders the packet in `dig`
+    /// presentation format.
+    pub fn dig_text(&self) -> DigText<'a> {
+        DigText(*self)
+    }
+}
+
+struct Record<'a> {
+    name: DNSName<'a>,
+    rr_type: u16,
+    class: u16,
+    ttl: u32,
+    rdata: (usize, usize),
+}
+
+struct Question<'a> {
+    name: DNSName<'a>,
+    qtype: u16,
+    qclass: u16,
+}
+
+#[derive(Default)]
+struct Sections<'a> {
+    questions: Vec<Question<'a>>,
+    records: [Vec<Record<'a>>; 3],
+    malformed: bool,
+}
+
+fn parse_sections<'a>(dns_packet: &DNSPacket<'a>) -> Sections<'a> {
+    let packet = dns_packet.as_bytes();
+    let mut sections = Sections::default();
+    let mut offset = DNS_HEADER_SIZE;
+    for _ in 0..dns_packet.question_count() {
+        let question = DNSName::parse(packet, offset).and_then(|(name, offset)| {
+            let question = Question {
+                name,
+                qtype: be16(packet, offset)?,
+                qclass: be16(packet, offset + 2)?,
+            };
+            Some((question, offset + 4))
+        });
+        match question {
+            Some((question, next_offset)) => {
+                sections.questions.push(question);
+                offset = next_offset;
+            }
+            None => {
+                sections.malformed = true;
+                return sections;
+            }
+        }
+    }
+    let counts = [
+        dns_packet.answer_count(),
+        dns_packet.authority_count(),
+        dns_packet.additional_count(),
+    ];
+    for (section, &count) in counts.iter().enumerate() {
+        for _ in 0..count {
+            match parse_record(packet, offset) {
+                Some((record, next_offset)) => {
+                    sections.records[section].push(record);
+                    offset = next_offset;
+                }
+                None => {
+                    sections.malformed = true;
+                    return sections;
+                }
+            }
+        }
+    }
+    sections
+}
+
+fn parse_record(packet: &[u8], offset: usize) -> Option<(Record<'_>, usize)> {
+    let (name, offset) = DNSName::parse(packet, offset)?;
+    let rr_type = be16(packet, offset)?;
+    let class = be16(packet, offset + 2)?;
+    let ttl = be32(packet, offset + 4)?;
+    let rdlen = usize::from(be16(packet, offset + 8)?);
+    let start = offset + 10;
+    packet.get(start..start + rdlen)?;
+    let record = Record {
+        name,
+        rr_type,
+        class,
+        ttl,
+        rdata: (start, start + rdlen),
+    };
+    Some((record, start + rdlen))
+}
+
+/// Presentation styles of DNS packets.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Style {
+    /// BIND `dig`.
+    Dig,
+    /// `miekg/dns`, as used by `golang-dnstap`.
+    Go,
+}
+
+/// Rendering of a DNS packet in the style of `miekg/dns`.
+struct GoText<'a>(DNSPacket<'a>);
+
+impl<'a> fmt::Display for DigText<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_packet(f, &self.0, Style::Dig)
+    }
+}
+
+impl<'a> fmt::Display for GoText<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_packet(f, &self.0, Style::Go)
+    }
+}
+
+fn write_packet(
+    f: &mut fmt::Formatter<'_>,
+    dns_packet: &DNSPacket<'_>,
+    style: Style,
+) -> fmt::Result {
+    let packet = dns_packet.as_bytes();
+    let sections = parse_sections(dns_packet);
+    let opt = sections.records[2]
+        .iter()
+        .find(|record| record.rr_type == TYPE_OPT);
+    let mut rcode = u16::from(dns_packet.rcode());
+    if let Some(opt) = opt {
+        rcode |= ((opt.ttl >> 24) as u16) << 4;
+    }
+    if style == Style::Dig {
+        f.write_str(";; ->>HEADER<<- ")?;
+    } else {
+        f.write_str(";; ")?;
+    }
+    writeln!(
+        f,
+        "opcode: {}, status: {}, id: {}",
+        opcode_name(dns_packet.opcode()),
+        rcode_name(rcode),
+        dns_packet.id()
+    )?;
+    let flags = dns_packet.flags();
+    let flag_names: String = [
+        (0x8000, "qr"),
+        (0x0400, "aa"),
+        (0x0200, "tc"),
+        (0x0100, "rd"),
+        (0x0080, "ra"),
+        (0x0040, "z"),
+        (0x0020, "ad"),
+        (0x0010, "cd"),
+    ]
+    .iter()
+    .filter(|&&(bit, name)| flags & bit != 0 && (name != "z" || style == Style::Go))
+    .map(|&(_, name)| format!(" {}", name))
+    .collect();
+    let update = style == Style::Go && dns_packet.opcode() == OPCODE_UPDATE;
+    let count_names = if update {
+        ["ZONE", "PREREQ", "UPDATE"]
+    } else {
+        ["QUERY", "ANSWER", "AUTHORITY"]
+    };
+    writeln!(
+        f,
+        ";; flags:{}; {}: {}, {}: {}, {}: {}, ADDITIONAL: {}",
+        flag_names,
+        count_names[0],
+        dns_packet.question_count(),
+        count_names[1],
+        dns_packet.answer_count(),
+        count_names[2],
+        dns_packet.authority_count(),
+        dns_packet.additional_count()
+    )?;
+    if let Some(opt) = opt {
+        f.write_str("\n;; OPT PSEUDOSECTION:\n")?;
+        match style {
+            Style::Dig => write_opt(f, packet, opt)?,
+            Style::Go => write_go_opt(f, packet, opt)?,
+        }
+    }
+    if !sections.questions.is_empty() {
+        let name = if update { "ZONE" } else { "QUESTION" };
+        write!(f, "\n;; {} SECTION:\n", name)?;
+    }
+    for question in &sections.questions {
+        let qname = format!(";{}", question.name);
+        let (class, rr_type) = (class_name(question.qclass), rr_type_name(question.qtype));
+        match style {
+            Style::Dig => write_aligned(f, &[(0, &qname), (32, &class), (40, &rr_type)])?,
+            // miekg/dns separates the class from the type with a tab and a space
+            Style::Go => write!(f, "{}\t{}\t {}", qname, class, rr_type)?,
+        }
+        writeln!(f)?;
+    }
+    let names = if update {
+        ["PREREQUISITE", "UPDATE", "ADDITIONAL"]
+    } else {
+        ["ANSWER", "AUTHORITY", "ADDITIONAL"]
+    };
+    for (name, records) in names.iter().zip(sections.records.iter()) {
+        let mut records = records
+            .iter()
+            .filter(|record| record.rr_type != TYPE_OPT)
+            .peekable();
+        if records.peek().is_some() {
+            write!(f, "\n;; {} SECTION:\n", name)?;
+        }
+        for record in records {
+            let fields = [
+                (0, record.name.to_string()),
+                (24, record.ttl.to_string()),
+                (32, class_name(record.class)),
+                (40, rr_type_name(record.rr_type)),
+                (48, rdata_text(packet, record, style)),
+            ];
+            match style {
+                Style::Dig => {
+                    let fields: Vec<_> = fields.iter().map(|(c, s)| (*c, s.as_str())).collect();
+                    write_aligned(f, &fields)?;
+                }
+                Style::Go => {
+                    let fields: Vec<_> = fields.iter().map(|(_, s)| s.as_str()).collect();
+                    f.write_str(&fields.join("\t"))?;
+                }
+            }
+            writeln!(f)?;
+        }
+    }
+    if sections.malformed && style == Style::Dig {
+        f.write_str("\n;; WARNING: truncated or malformed packet\n")?;
+    }
+    Ok(())
+}
+
+/// Writes fields starting at the given columns, with tab stops every 8 characters. A field
+/// that cannot start at its column is separated from the previous one by a single space,
+/// or a tab if that reaches a tab stop. This is how `dig` aligns records.
+fn write_aligned(f: &mut fmt::Formatter<'_>, fields: &[(usize, &str)]) -> fmt::Result {
+    let mut current = 0;
+    for &(column, field) in fields {
+        if current > 0 {
+            let to = column.max(current + 1);
+            let tabs = to / TAB_WIDTH - current / TAB_WIDTH;
+            if tabs > 0 {
+                current = to / TAB_WIDTH * TAB_WIDTH;
+            }
+            for _ in 0..tabs {
+                f.write_char('\t')?;
+            }
+            for _ in current..to {
+                f.write_char(' ')?;
+            }
+            current = to;
+        }
+        f.write_str(field)?;
+        current += field.len();
+    }
+    Ok(())
+}
+
+/// Renders a DNS packet the way `miekg/dns` does, as found in the output of `golang-dnstap`.
+/// Returns `None` if the packet cannot be entirely parsed.
+pub fn render_go(packet: &[u8]) -> Option<String> {
+    let dns_packet = DNSPacket::new(packet)?;
+    if parse_sections(&dns_packet).malformed {
+        return None;
+    }
+    Some(GoText(dns_packet).to_string())
+}
+
+fn opcode_name(opcode: u8) -> String {
+    let name = match opcode {
+        0 => "QUERY",
+        1 => "IQUERY",
+        2 => "STATUS",
+        4 => "NOTIFY",
+        5 => "UPDATE",
+        6 => "DSO",
+        _ => return format!("RESERVED{}", opcode),
+    };
+    name.to_owned()
+}
+
+fn rcode_name(rcode: u16) -> String {
+    let name = match rcode {
+        0 => "NOERROR",
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        6 => "YXDOMAIN",
+        7 => "YXRRSET",
+        8 => "NXRRSET",
+        9 => "NOTAUTH",
+        10 => "NOTZONE",
+        11 => "DSOTYPENI",
+        16 => "BADVERS",
+        17 => "BADKEY",
+        18 => "BADTIME",
+        19 => "BADMODE",
+        20 => "BADNAME",
+        21 => "BADALG",
+        22 => "BADTRUNC",
+        23 => "BADCOOKIE",
+        _ => return format!("RESERVED{}", rcode),
+    };
+    name.to_owned()
+}
+
+fn ede_name(info_code: u16) -> Option<&'static str> {
+    let name = match info_code {
+        0 => "Other",
+        1 => "Unsupported DNSKEY Algorithm",
+        2 => "Unsupported DS Digest Type",
+        3 => "Stale Answer",
+        4 => "Forged Answer",
+        5 => "DNSSEC Indeterminate",
+        6 => "DNSSEC Bogus",
+        7 => "Signature Expired",
+        8 => "Signature Not Yet Valid",
+        9 => "DNSKEY Missing",
+        10 => "RRSIGs Missing",
+        11 => "No Zone Key Bit Set",
+        12 => "NSEC Missing",
+        13 => "Cached Error",
+        14 => "Not Ready",
+        15 => "Blocked",
+        16 => "Censored",
+        17 => "Filtered",
+        18 => "Prohibited",
+        19 => "Stale NXDOMAIN Answer",
+        20 => "Not Authoritative",
+        21 => "Not Supported",
+        22 => "No Reachable Authority",
+        23 => "Network Error",
+        24 => "Invalid Data",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn write_opt(f: &mut fmt::Formatter<'_>, packet: &[u8], opt: &Record<'_>) -> fmt::Result {
+    write!(f, "; EDNS: version: {}, flags:", (opt.ttl >> 16) & 0xff)?;
+    if opt.ttl & 0x8000 != 0 {
+        f.write_str(" do")?;
+    }
+    let mbz = opt.ttl & 0x7fff;
+    if mbz != 0 {
+        write!(f, "; MBZ: {:#06x},", mbz)?;
+    } else {
+        f.write_str(";")?;
+    }
+    writeln!(f, " udp: {}", opt.class)?;
+    let mut options = &packet[opt.rdata.0..opt.rdata.1];
+    while !options.is_empty() {
+        let (code, len) = match (be16(options, 0), be16(options, 2)) {
+            (Some(code), Some(len)) => (code, usize::from(len)),
+            _ => return f.write_str("; WARNING: malformed OPT record\n"),
+        };
+        let value = match options.get(4..4 + len) {
+            Some(value) => value,
+            None => return f.write_str("; WARNING: malformed OPT record\n"),
+        };
+        options = &options[4 + len..];
+        match code {
+            3 => {
+                f.write_str("; NSID: ")?;
+                write_option_data(f, value)?;
+            }
+            8 => match client_subnet(value) {
+                Some((address, source_prefix, scope_prefix)) => writeln!(
+                    f,
+                    "; CLIENT-SUBNET: {}/{}/{}",
+                    address, source_prefix, scope_prefix
+                )?,
+                None => writeln!(f, "; CLIENT-SUBNET: {}", hex(value))?,
+            },
+            10 => writeln!(f, "; COOKIE: {}", hex(value).to_lowercase())?,
+            11 => match be16(value, 0) {
+                Some(timeout) if value.len() == 2 => {
+                    writeln!(f, "; TCP-KEEPALIVE: {}.{} secs", timeout / 10, timeout % 10)?
+                }
+                _ => writeln!(f, "; TCP-KEEPALIVE")?,
+            },
+            12 => writeln!(f, "; PADDING: ({} bytes)", value.len())?,
+            15 => match be16(value, 0) {
+                Some(info_code) => {
+                    write!(f, "; EDE: {}", info_code)?;
+                    if let Some(name) = ede_name(info_code) {
+                        write!(f, " ({})", name)?;
+                    }
+                    let extra_text = &value[2..];
+                    if !extra_text.is_empty() {
+                        // The text is shown as is if it is valid UTF-8, and also in hex if not
+                        f.write_str(": ")?;
+                        match std::str::from_utf8(extra_text) {
+                            Ok(text) => write!(f, "({})", text)?,
+                            Err(_) => {
+                                for &c in extra_text {
+                                    write!(f, "{:02x} ", c)?;
+                                }
+                                write!(f, "({})", printable(extra_text))?;
+                            }
+                        }
+                    }
+                    writeln!(f)?;
+                }
+                None => writeln!(f, "; EDE")?,
+            },
+            _ => {
+                write!(f, "; OPT={}: ", code)?;
+                write_option_data(f, value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the value of an EDNS option as hex bytes, followed by its printable characters.
+fn write_option_data(f: &mut fmt::Formatter<'_>, value: &[u8]) -> fmt::Result {
+    for &c in value {
+        write!(f, "{:02x} ", c)?;
+    }
+    writeln!(f, "(\"{}\")", printable(value))
+}
+
+/// Replaces non-printable characters with dots.
+fn printable(s: &[u8]) -> String {
+    s.iter()
+        .map(|&c| match c {
+            0x20..=0x7e => char::from(c),
+            _ => '.',
+        })
+        .collect()
+}
+
+/// Writes the OPT pseudo-section the way `miekg/dns` does.
+fn write_go_opt(f: &mut fmt::Formatter<'_>, packet: &[u8], opt: &Record<'_>) -> fmt::Result {
+    write!(f, "; EDNS: version {}; ", (opt.ttl >> 16) & 0xff)?;
+    if opt.ttl & 0x8000 != 0 {
+        f.write_str("flags: do; ")?;
+    } else {
+        f.write_str("flags:; ")?;
+    }
+    let mbz = opt.ttl & 0x7fff;
+    if mbz != 0 {
+        write!(f, "MBZ: {:#06x}, ", mbz)?;
+    }
+    write!(f, "udp: {}", opt.class)?;
+    let mut options = &packet[opt.rdata.0..opt.rdata.1];
+    while let (Some(code), Some(len)) = (be16(options, 0), be16(options, 2)) {
+        let value = match options.get(4..4 + usize::from(len)) {
+            Some(value) => value,
+            None => break,
+        };
+        options = &options[4 + value.len()..];
+        match code {
+            3 => {
+                write!(f, "\n; NSID: {}  ", hex(value).to_lowercase())?;
+                for &c in value {
+                    write!(f, "({})", char::from(c))?;
+                }
+            }
+            8 => match client_subnet(value) {
+                Some((IpAddr::V4(address), source_prefix, scope_prefix)) => write!(
+                    f,
+                    "\n; SUBNET: {}/{}/{}",
+                    address, source_prefix, scope_prefix
+                )?,
+                Some((IpAddr::V6(address), source_prefix, scope_prefix)) => write!(
+                    f,
+                    "\n; SUBNET: [{}]/{}/{}",
+                    address, source_prefix, scope_prefix
+                )?,
+                None => write!(f, "\n; LOCAL OPT: {}:0x{}", code, hex(value).to_lowercase())?,
+            },
+            9 => match be32(value, 0) {
+                Some(expire) => write!(f, "\n; EXPIRE: {}", expire)?,
+                None => f.write_str("\n; EXPIRE: ")?,
+            },
+            10 => write!(f, "\n; COOKIE: {}", hex(value).to_lowercase())?,
+            11 => {
+                f.write_str("\n; KEEPALIVE: use tcp keep-alive")?;
+                match be16(value, 0) {
+                    Some(timeout) if timeout != 0 => {
+                        write!(f, ", timeout {}", go_duration(timeout))?
+                    }
+                    _ => {}
+                }
+            }
+            12 => write!(f, "\n; PADDING: {}", hex(value).to_lowercase())?,
+            15 => {
+                let info_code = be16(value, 0).unwrap_or(0);
+                write!(f, "\n; EDE: {}", info_code)?;
+                if let Some(name) = ede_name(info_code) {
+                    write!(f, " ({})", name)?;
+                }
+                let extra_text = value.get(2..).unwrap_or_default();
+                write!(f, ": ({})", String::from_utf8_lossy(extra_text))?;
+            }
+            _ => write!(f, "\n; LOCAL OPT: {}:0x{}", code, hex(value).to_lowercase())?,
+        }
+    }
+    writeln!(f)
+}
+
+/// Formats a TCP keepalive timeout, in units of 100 milliseconds, like a Go duration.
+fn go_duration(timeout: u16) -> String {
+    if timeout < 10 {
+        return format!("{}ms", u32::from(timeout) * 100);
+    }
+    let (secs, tenths) = (timeout / 10, timeout % 10);
+    let mut out = String::new();
+    if secs >= 3600 {
+        let _ = write!(out, "{}h", secs / 3600);
+    }
+    if secs >= 60 {
+        let _ = write!(out, "{}m", secs / 60 % 60);
+    }
+    let _ = write!(out, "{}", secs % 60);
+    if tenths != 0 {
+        let _ = write!(out, ".{}", tenths);
+    }
+    out.push('s');
+    out
+}
+
+fn client_subnet(value: &[u8]) -> Option<(IpAddr, u8, u8)> {
+    let family = be16(value, 0)?;
+    let source_prefix = *value.get(2)?;
+    let scope_prefix = *value.get(3)?;
+    let address = &value[4..];
+    let address = match family {
+        1 if address.len() <= 4 => {
+            let mut octets = [0u8; 4];
+            octets[..address.len()].copy_from_slice(address);
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        2 if address.len() <= 16 => {
+            let mut octets = [0u8; 16];
+            octets[..address.len()].copy_from_slice(address);
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return None,
+    };
+    Some((address, source_prefix, scope_prefix))
+}
+
+fn rdata_text(packet: &[u8], record: &Record<'_>, style: Style) -> String {
+    let mut out = String::new();
+    if render_rdata(&mut out, packet, record, style).is_none() {
+        // RFC 3597 generic representation
+        let rdata = &packet[record.rdata.0..record.rdata.1];
+        out = format!("\\# {}", rdata.len());
+        if !rdata.is_empty() {
+            let _ = write!(out, " {}", split(hex(rdata), style));
+        }
+    }
+    out
+}
+
+/// Decodes the data of common record types. Returns `None` if the type is not supported,
+/// or if the data doesn't match the expected format.
+fn render_rdata(out: &mut String, packet: &[u8], record: &Record<'_>, style: Style) -> Option<()> {
+    let (start, end) = record.rdata;
+    let mut r = RdataReader {
+        packet,
+        offset: start,
+        end,
+    };
+    match record.rr_type {
+        // A
+        1 => {
+            let octets: [u8; 4] = r.bytes(4)?.try_into().ok()?;
+            let _ = write!(out, "{}", Ipv4Addr::from(octets));
+        }
+        // AAAA
+        28 => {
+            let octets: [u8; 16] = r.bytes(16)?.try_into().ok()?;
+            let _ = write!(out, "{}", Ipv6Addr::from(octets));
+        }
+        // NS, MD, MF, CNAME, MB, MG, MR, PTR, DNAME
+        2..=5 | 7..=9 | 12 | 39 => {
+            let _ = write!(out, "{}", r.name()?);
+        }
+        // SOA
+        6 => {
+            let _ = write!(
+                out,
+                "{} {} {} {} {} {} {}",
+                r.name()?,
+                r.name()?,
+                r.u32()?,
+                r.u32()?,
+                r.u32()?,
+                r.u32()?,
+                r.u32()?
+            );
+        }
+        // HINFO
+        13 => {
+            let cpu = r.character_string()?;
+            let os = r.character_string()?;
+            let _ = write!(out, "{} {}", cpu, os);
+        }
+        // MX, AFSDB, KX
+        15 | 18 | 36 => {
+            let _ = write!(out, "{} {}", r.u16()?, r.name()?);
+        }
+        // TXT, SPF
+        16 | 99 => {
+            let mut strings = vec![r.character_string()?];
+            while !r.is_empty() {
+                strings.push(r.character_string()?);
+            }
+            out.push_str(&strings.join(" "));
+        }
+        // RP
+        17 => {
+            let _ = write!(out, "{} {}", r.name()?, r.name()?);
+        }
+        // SRV
+        33 => {
+            let _ = write!(out, "{} {} {} {}", r.u16()?, r.u16()?, r.u16()?, r.name()?);
+        }
+        // NAPTR
+        35 => {
+            let _ = write!(
+                out,
+                "{} {} {} {} {} {}",
+                r.u16()?,
+                r.u16()?,
+                r.character_string()?,
+                r.character_string()?,
+                r.character_string()?,
+                r.name()?
+            );
+        }
+        // DS, CDS, DLV
+        43 | 59 | 32769 => {
+            let _ = write!(out, "{} {} {} ", r.u16()?, r.u8()?, r.u8()?);
+            out.push_str(&split(hex(r.rest()), style));
+        }
+        // SSHFP
+        44 => {
+            let _ = write!(out, "{} {} ", r.u8()?, r.u8()?);
+            out.push_str(&split(hex(r.rest()), style));
+        }
+        // RRSIG
+        46 => {
+            let type_covered = rr_type_name(r.u16()?);
+            let _ = write!(
+                out,
+                "{} {} {} {} {} {} {} {} ",
+                type_covered,
+                r.u8()?,
+                r.u8()?,
+                r.u32()?,
+                signature_time(r.u32()?),
+                signature_time(r.u32()?),
+                r.u16()?,
+                r.name()?
+            );
+            out.push_str(&split(STANDARD.encode(r.rest()), style));
+        }
+        // NSEC
+        47 => {
+            let _ = write!(out, "{}", r.name()?);
+            out.push_str(&type_bitmap(r.rest())?);
+        }
+        // DNSKEY, CDNSKEY
+        48 | 60 => {
+            let _ = write!(out, "{} {} {} ", r.u16()?, r.u8()?, r.u8()?);
+            out.push_str(&split(STANDARD.encode(r.rest()), style));
+        }
+        // NSEC3
+        50 => {
+            let _ = write!(out, "{} {} {} ", r.u8()?, r.u8()?, r.u16()?);
+            let salt_len = usize::from(r.u8()?);
+            out.push_str(&salt(r.bytes(salt_len)?));
+            let hash_len = usize::from(r.u8()?);
+            out.push(' ');
+            out.push_str(&base32hex(r.bytes(hash_len)?));
+            out.push_str(&type_bitmap(r.rest())?);
+        }
+        // NSEC3PARAM
+        51 => {
+            let _ = write!(out, "{} {} {} ", r.u8()?, r.u8()?, r.u16()?);
+            let salt_len = usize::from(r.u8()?);
+            out.push_str(&salt(r.bytes(salt_len)?));
+        }
+        // TLSA, SMIMEA
+        52 | 53 => {
+            let _ = write!(out, "{} {} {} ", r.u8()?, r.u8()?, r.u8()?);
+            out.push_str(&split(hex(r.rest()), style));
+        }
+        // URI
+        256 => {
+            let _ = write!(out, "{} {} ", r.u16()?, r.u16()?);
+            out.push_str(&quoted(r.rest()));
+        }
+        // CAA
+        257 => {
+            let flags = r.u8()?;
+            let tag_len = usize::from(r.u8()?);
+            let tag = r.bytes(tag_len)?;
+            if tag.is_empty() || !tag.iter().all(u8::is_ascii_alphanumeric) {
+                return None;
+            }
+            let _ = write!(out, "{} {} ", flags, String::from_utf8_lossy(tag));
+            out.push_str(&quoted(r.rest()));
+        }
+        _ => return None,
+    }
+    if !r.is_empty() {
+        return None;
+    }
+    Some(())
+}
+
+struct RdataReader<'a> {
+    packet: &'a [u8],
+    offset: usize,
+    end: usize,
+}
+
+impl<'a> RdataReader<'a> {
+    fn is_empty(&self) -> bool {
+        self.offset >= self.end
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.offset + len > self.end {
+            return None;
+        }
+        let bytes = &self.packet[self.offset..self.offset + len];
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.packet[self.offset..self.end];
+        self.offset = self.end;
+        rest
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|x| x[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|x| u16::from_be_bytes([x[0], x[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|x| u32::from_be_bytes([x[0], x[1], x[2], x[3]]))
+    }
+
+    fn name(&mut self) -> Option<DNSName<'a>> {
+        // Compression pointers may refer to the rest of the packet, but the name itself
+        // must fit in the record data
+        let (name, next_offset) = DNSName::parse(&self.packet[..self.end], self.offset)?;
+        self.offset = next_offset;
+        Some(name)
+    }
+
+    fn character_string(&mut self) -> Option<String> {
+        let len = usize::from(self.u8()?);
+        Some(quoted(self.bytes(len)?))
+    }
+}
+
+fn write_character_string<W: Write>(out: &mut W, s: &[u8]) -> fmt::Result {
+    for &c in s {
+        match c {
+            b'"' | b'\\' => {
+                out.write_char('\\')?;
+                out.write_char(c as char)?;
+            }
+            0x20..=0x7e => out.write_char(c as char)?,
+            _ => write!(out, "\\{:03}", c)?,
+        }
+    }
+    Ok(())
+}
+
+fn quoted(s: &[u8]) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    let _ = write_character_string(&mut out, s);
+    out.push('"');
+    out
+}
+
+fn type_bitmap(mut bitmap: &[u8]) -> Option<String> {
+    let mut out = String::new();
+    while !bitmap.is_empty() {
+        let window = u16::from(*bitmap.first()?);
+        let len = usize::from(*bitmap.get(1)?);
+        if len == 0 || len > 32 {
+            return None;
+        }
+        let bits = bitmap.get(2..2 + len)?;
+        for (i, &byte) in bits.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    let rr_type = (window << 8) | (i as u16 * 8 + bit);
+                    out.push(' ');
+                    out.push_str(&rr_type_name(rr_type));
+                }
+            }
+        }
+        bitmap = &bitmap[2 + len..];
+    }
+    Some(out)
+}
+
+fn signature_time(t: u32) -> String {
+    let (year, month, day, hour, min, sec) =
+        timestamp::civil(time::Duration::from_secs(u64::from(t)));
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year, month, day, hour, min, sec
+    )
+}
+
+fn salt(salt: &[u8]) -> String {
+    if salt.is_empty() {
+        "-".to_owned()
+    } else {
+        hex(salt)
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() * 2);
+    for &c in data {
+        let _ = write!(out, "{:02X}", c);
+    }
+    out
+}
+
+/// Splits a long hex or base64 field into space-separated chunks, like `dig` does.
+fn split(encoded: String, style: Style) -> String {
+    if style != Style::Dig || encoded.len() <= SPLIT_WIDTH {
+        return encoded;
+    }
+    let chunks: Vec<_> = encoded.as_bytes().chunks(SPLIT_WIDTH).collect();
+    String::from_utf8(chunks.join(&b' ')).unwrap()
+}
+
+fn base32hex(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() * 8).div_ceil(5));
+    let (mut acc, mut bits) = (0u32, 0);
+    for &c in data {
+        acc = (acc << 8) | u32::from(c);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32HEX_ALPHABET[(acc >> bits) as usize & 0x1f] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32HEX_ALPHABET[(acc << (5 - bits)) as usize & 0x1f] as char);
+    }
+    out
+}
+
+#[inline]
+fn be16(x: &[u8], offset: usize) -> Option<u16> {
+    let x = x.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([x[0], x[1]]))
+}
+
+#[inline]
+fn be32(x: &[u8], offset: usize) -> Option<u32> {
+    let x = x.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([x[0], x[1], x[2], x[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    fn rr(owner: &str, rr_type: u16, ttl: u32, rdata: &[u8]) -> Vec<u8> {
+        let mut rr = name(owner);
+        rr.extend_from_slice(&rr_type.to_be_bytes());
+        rr.extend_from_slice(&[0, 1]);
+        rr.extend_from_slice(&ttl.to_be_bytes());
+        rr.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        rr.extend_from_slice(rdata);
+        rr
+    }
+
+    fn opt(udp_size: u16, ttl: u32, options: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut rdata = vec![];
+        for &(code, value) in options {
+            rdata.extend_from_slice(&code.to_be_bytes());
+            rdata.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            rdata.extend_from_slice(value);
+        }
+        let mut rr = vec![0];
+        rr.extend_from_slice(&TYPE_OPT.to_be_bytes());
+        rr.extend_from_slice(&udp_size.to_be_bytes());
+        rr.extend_from_slice(&ttl.to_be_bytes());
+        rr.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        rr.extend_from_slice(&rdata);
+        rr
+    }
+
+    fn dns_packet(flags: u16, qname: &str, answers: &[Vec<u8>], additional: &[Vec<u8>]) -> Vec<u8> {
+        let mut packet = vec![0xbe, 0xef];
+        packet.extend_from_slice(&flags.to_be_bytes());
+        packet.extend_from_slice(&[0, 1]);
+        packet.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&(additional.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&name(qname));
+        packet.extend_from_slice(&[0, 1, 0, 1]);
+        for rr in answers.iter().chain(additional) {
+            packet.extend_from_slice(rr);
+        }
+        packet
+    }
+
+    fn dig_text(packet: &[u8]) -> String {
+        DNSPacket::new(packet).unwrap().dig_text().to_string()
+    }
+
+    /// The expected output follows the `dig` presentation format of BIND 9.18, with default
+    /// options.
+    #[test]
+    fn opt_pseudosection() {
+        let options: &[(u16, &[u8])] = &[
+            (8, &[0, 1, 24, 24, 192, 0, 2]),
+            (
+                10,
+                &[
+                    1, 2, 3, 4, 5, 6, 7, 8, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8,
+                ],
+            ),
+            (15, b"\x00\x0fblocked by policy"),
+            (15, &[0, 18]),
+            (15, &[0, 99, b'o', 0xff]),
+            (3, b"ns1"),
+            (11, &[1, 44]),
+            (12, &[0, 0, 0, 0]),
+            (65001, &[0, b'x', 0xff]),
+        ];
+        let answer = rr("example.com", 1, 300, &[192, 0, 2, 1]);
+        let packet = dns_packet(
+            0x8180,
+            "example.com",
+            &[answer],
+            &[opt(1232, 0x8000, options)],
+        );
+        assert_eq!(
+            dig_text(&packet),
+            ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 48879
+;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 1
+
+;; OPT PSEUDOSECTION:
+; EDNS: version: 0, flags: do; udp: 1232
+; CLIENT-SUBNET: 192.0.2.0/24/24
+; COOKIE: 0102030405060708a1a2a3a4a5a6a7a8
+; EDE: 15 (Blocked): (blocked by policy)
+; EDE: 18 (Prohibited)
+; EDE: 99: 6f ff (o.)
+; NSID: 6e 73 31 (\"ns1\")
+; TCP-KEEPALIVE: 30.0 secs
+; PADDING: (4 bytes)
+; OPT=65001: 00 78 ff (\".x.\")
+
+;; QUESTION SECTION:
+;example.com.\t\t\tIN\tA
+
+;; ANSWER SECTION:
+example.com.\t\t300\tIN\tA\t192.0.2.1
+"
+        );
+    }
+
+    #[test]
+    fn edns_header() {
+        let options: &[(u16, &[u8])] = &[(8, &[0, 2, 48, 0, 0x20, 0x01, 0x0d, 0xb8, 0, 0])];
+        // No flags, and an extended response code
+        let packet = dns_packet(
+            0x8100,
+            "example.com",
+            &[],
+            &[opt(4096, 0x0100_0000, options)],
+        );
+        assert_eq!(
+            dig_text(&packet),
+            ";; ->>HEADER<<- opcode: QUERY, status: BADVERS, id: 48879
+;; flags: qr rd; QUERY: 1, ANSWER: 0, AUTHORITY: 0, ADDITIONAL: 1
+
+;; OPT PSEUDOSECTION:
+; EDNS: version: 0, flags:; udp: 4096
+; CLIENT-SUBNET: 2001:db8::/48/0
+
+;; QUESTION SECTION:
+;example.com.\t\t\tIN\tA
+"
+        );
+
+        // Unknown flags and version
+        let packet = dns_packet(0x0000, "example.com", &[], &[opt(512, 0x0001_8001, &[])]);
+        assert!(
+            dig_text(&packet).contains("\n; EDNS: version: 1, flags: do; MBZ: 0x0001, udp: 512\n")
+        );
+
+        // Truncated option
+        let mut packet = dns_packet(0x0000, "example.com", &[], &[opt(512, 0, &[(10, &[1; 8])])]);
+        let len = packet.len();
+        packet[len - 9] = 9;
+        assert!(dig_text(&packet).contains("\n; WARNING: malformed OPT record\n"));
+    }
+
+    /// The expected output follows the `dig` presentation format of BIND 9.18, with default
+    /// options: records are aligned on columns 24, 32, 40 and 48, and long hex and base64
+    /// fields are split into chunks of 56 characters.
+    #[test]
+    fn record_types() {
+        let mut ds = vec![0x30, 0x39, 13, 2];
+        ds.extend(0..32);
+        let mut dnskey = vec![1, 1, 3, 13];
+        dnskey.extend(0..64);
+        let mut rrsig = vec![0, 1, 13, 2, 0, 0, 1, 0x2c];
+        rrsig.extend_from_slice(&1_706_745_600u32.to_be_bytes());
+        rrsig.extend_from_slice(&1_704_067_200u32.to_be_bytes());
+        rrsig.extend_from_slice(&[0x30, 0x39]);
+        rrsig.extend_from_slice(&name("example.com"));
+        rrsig.extend(64..128);
+        let mut nsec = name("www.example.com");
+        nsec.extend_from_slice(&[0, 7, 0x62, 0x01, 0x80, 0x08, 0x00, 0x03, 0x80]);
+        let mut soa = name("ns1.example.com");
+        soa.extend_from_slice(&name("hostmaster.example.com"));
+        for x in [2_024_010_101u32, 7200, 3600, 1_209_600, 300].iter() {
+            soa.extend_from_slice(&x.to_be_bytes());
+        }
+        let mut mx = vec![0, 10];
+        mx.extend_from_slice(&name("mail.example.com"));
+        let mut srv = vec![0, 10, 0, 60, 0x13, 0xc4];
+        srv.extend_from_slice(&name("sip.example.com"));
+        let generic: Vec<u8> = (0..40).collect();
+
+        let answers = [
+            rr("example.com", 1, 300, &[192, 0, 2, 1]),
+            rr("example.com", 28, 300, &ip6("2001:db8::1")),
+            rr("www.example.com", 5, 3600, &name("example.com")),
+            rr("example.com", 15, 300, &mx),
+            rr("example.com", 16, 300, b"\x0bv=spf1 -all\x06a\"b\\c\x01"),
+            rr("example.com", 6, 3600, &soa),
+            rr("_sip._tcp.example.com", 33, 86400, &srv),
+            rr("example.com", 257, 300, b"\x00\x05issueletsencrypt.org"),
+            rr("example.com", 43, 3600, &ds),
+            rr("example.com", 48, 3600, &dnskey),
+            rr("example.com", 46, 300, &rrsig),
+            rr("example.com", 47, 300, &nsec),
+            rr(
+                "a-rather-long-name-for-alignment.example.com",
+                1,
+                300,
+                &[192, 0, 2, 2],
+            ),
+            rr("example.com", 65280, 300, &[10, 11, 12, 13]),
+            rr("example.com", 65280, 300, &[]),
+            rr("example.com", 65280, 300, &generic),
+            // Malformed records use the generic format
+            rr("example.com", 1, 300, &[192, 0, 2]),
+            rr("example.com", 257, 300, b"\x00\x00"),
+        ];
+        assert_eq!(
+            dig_text(&dns_packet(0x8500, "example.com", &answers, &[])),
+            ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 48879
+;; flags: qr aa rd; QUERY: 1, ANSWER: 18, AUTHORITY: 0, ADDITIONAL: 0
+
+;; QUESTION SECTION:
+;example.com.\t\t\tIN\tA
+
+;; ANSWER SECTION:
+example.com.\t\t300\tIN\tA\t192.0.2.1
+example.com.\t\t300\tIN\tAAAA\t2001:db8::1
+www.example.com.\t3600\tIN\tCNAME\texample.com.
+example.com.\t\t300\tIN\tMX\t10 mail.example.com.
+example.com.\t\t300\tIN\tTXT\t\"v=spf1 -all\" \"a\\\"b\\\\c\\001\"
+example.com.\t\t3600\tIN\tSOA\tns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300
+_sip._tcp.example.com.\t86400\tIN\tSRV\t10 60 5060 sip.example.com.
+example.com.\t\t300\tIN\tCAA\t0 issue \"letsencrypt.org\"
+example.com.\t\t3600\tIN\tDS\t12345 13 2 000102030405060708090A0B0C0D0E0F101112131415161718191A1B 1C1D1E1F
+example.com.\t\t3600\tIN\tDNSKEY\t257 3 13 AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygp KissLS4vMDEyMzQ1Njc4OTo7PD0+Pw==
+example.com.\t\t300\tIN\tRRSIG\tA 13 2 300 20240201000000 20240101000000 12345 example.com. QEFCQ0RFRkdISUpLTE1OT1BRUlNUVVZXWFlaW1xdXl9gYWJjZGVmZ2hp amtsbW5vcHFyc3R1dnd4eXp7fH1+fw==
+example.com.\t\t300\tIN\tNSEC\twww.example.com. A NS SOA MX TXT AAAA RRSIG NSEC DNSKEY
+a-rather-long-name-for-alignment.example.com. 300 IN A 192.0.2.2
+example.com.\t\t300\tIN\tTYPE65280 \\# 4 0A0B0C0D
+example.com.\t\t300\tIN\tTYPE65280 \\# 0
+example.com.\t\t300\tIN\tTYPE65280 \\# 40 000102030405060708090A0B0C0D0E0F101112131415161718191A1B 1C1D1E1F2021222324252627
+example.com.\t\t300\tIN\tA\t\\# 3 C00002
+example.com.\t\t300\tIN\tCAA\t\\# 2 0000
+"
+        );
+    }
+
+    #[test]
+    fn aligned_columns() {
+        struct Aligned<'a>(&'a [(usize, &'a str)]);
+        impl fmt::Display for Aligned<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write_aligned(f, self.0)
+            }
+        }
+        let aligned = |fields: &[(usize, &str)]| Aligned(fields).to_string();
+        assert_eq!(aligned(&[(0, "a"), (24, "b")]), "a\t\t\tb");
+        assert_eq!(aligned(&[(0, "abcdefg"), (8, "b")]), "abcdefg\tb");
+        assert_eq!(aligned(&[(0, "abcdefgh"), (8, "b")]), "abcdefgh b");
+        // Reaching a tab stop with a single character
+        assert_eq!(
+            aligned(&[(0, "abcdefghijklmno"), (8, "b")]),
+            "abcdefghijklmno\tb"
+        );
+        assert_eq!(
+            aligned(&[(0, "abcdefghij"), (8, "b"), (16, "c")]),
+            "abcdefghij b\tc"
+        );
+    }
+
+    #[test]
+    fn truncated_packet() {
+        let answers = [rr("example.com", 1, 300, &[192, 0, 2, 1])];
+        let packet = dns_packet(0x8180, "example.com", &answers, &[]);
+        assert_eq!(
+            dig_text(&packet[..packet.len() - 1]),
+            ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 48879
+;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 0
+
+;; QUESTION SECTION:
+;example.com.\t\t\tIN\tA
+
+;; WARNING: truncated or malformed packet
+"
+        );
+    }
+
+    #[test]
+    fn split_fields() {
+        let field = "0123456789".repeat(12);
+        assert_eq!(split(field.clone(), Style::Go), field);
+        assert_eq!(split(field[..56].to_owned(), Style::Dig), field[..56]);
+        assert_eq!(
+            split(field, Style::Dig),
+            [
+                &"0123456789".repeat(12)[..56],
+                &"0123456789".repeat(12)[56..112],
+                "23456789"
+            ]
+            .join(" ")
+        );
+    }
+
+    fn ip6(s: &str) -> Vec<u8> {
+        match ip(s) {
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+            IpAddr::V4(_) => unreachable!(),
+        }
+    }
+}
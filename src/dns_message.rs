@@ -1,8 +1,17 @@
-use std::net::IpAddr;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::time;
 
+use protobuf::Message;
+
+use crate::dns_packet;
+use crate::dnstap_builder::DNSMessageBuilder;
+use crate::dnstap_encoder::Encoder;
 use crate::dnstap_pb;
-use crate::{MessageType, SocketFamily, SocketProtocol};
+use crate::dnstap_text::is_query;
+use crate::frame_stream::invalid_data;
+use crate::timestamp;
+use crate::{HttpProtocol, MessageType, PolicyAction, PolicyMatch, SocketFamily, SocketProtocol};
 
 /// A DNS message.
 ///
@@ -10,22 +19,210 @@ use crate::{MessageType, SocketFamily, SocketProtocol};
 ///
 /// Although `socket_family` can be explicitly set, it can also be automatically
 /// inferred from `query_address` or `response_address` if these are present.
-#[derive(Clone, Hash)]
+///
+/// `extra` is an opaque annotation, such as deployment metadata, carried along with the message.
+///
+/// `bailiwick` is the zone a resolver or forwarder query was sent for, in presentation format. It is
+/// sent as the dnstap `query_zone` field, and must be a valid domain name.
+///
+/// `http_protocol` is the HTTP version a DNS-over-HTTPS message was carried over, and can only be
+/// set if `socket_protocol` is `DOH`.
+///
+/// `summary` holds the question and response code of packets that were removed by
+/// `PacketMinimization::Drop`. It is not part of the dnstap encoding.
+///
+/// `policy` records the operator policy, such as a Response Policy Zone rule, that was applied
+/// to the message.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSMessage {
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "crate::serde_support::lossy_string"
+        )
+    )]
     pub identity: Option<Vec<u8>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "crate::serde_support::lossy_string"
+        )
+    )]
     pub version: Option<Vec<u8>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "crate::serde_support::base64_bytes"
+        )
+    )]
+    pub extra: Option<Vec<u8>>,
     pub message_type: MessageType,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub socket_family: Option<SocketFamily>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub socket_protocol: Option<SocketProtocol>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub http_protocol: Option<HttpProtocol>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub query_address: Option<IpAddr>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub query_port: Option<u16>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "crate::serde_support::time_rfc3339"
+        )
+    )]
     pub query_time: Option<time::Duration>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "crate::serde_support::base64_bytes"
+        )
+    )]
     pub query_packet: Option<Vec<u8>>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub response_address: Option<IpAddr>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub response_port: Option<u16>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "crate::serde_support::time_rfc3339"
+        )
+    )]
     pub response_time: Option<time::Duration>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "crate::serde_support::base64_bytes"
+        )
+    )]
     pub response_packet: Option<Vec<u8>>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub bailiwick: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub policy: Option<Policy>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub summary: Option<PacketSummary>,
+}
+
+/// Fields parsed from the packets of a DNS message, kept after the packets were removed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PacketSummary {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub qname: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub qtype: Option<u16>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub qclass: Option<u16>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub rcode: Option<u8>,
+}
+
+/// Operator policy applied to the processing of a DNS message.
+///
+/// `policy_type` is the kind of policy, e.g. `RPZ` for a Response Policy Zone. For RPZ,
+/// `rule` is the owner name of the matching rule, in wire format. The format of `value`
+/// depends on `policy_match`: a wire format name for `QNAME` and `NS_NAME`, and the
+/// 4 or 16 bytes of an address for `CLIENT_IP`, `RESPONSE_IP` and `NS_IP`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Policy {
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "type", default, skip_serializing_if = "Option::is_none")
+    )]
+    pub policy_type: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "crate::serde_support::base64_bytes"
+        )
+    )]
+    pub rule: Option<Vec<u8>>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub action: Option<PolicyAction>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "match", default, skip_serializing_if = "Option::is_none")
+    )]
+    pub policy_match: Option<PolicyMatch>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "crate::serde_support::base64_bytes"
+        )
+    )]
+    pub value: Option<Vec<u8>>,
+}
+
+impl Policy {
+    fn into_protobuf(self) -> dnstap_pb::Policy {
+        let mut p = dnstap_pb::Policy::new();
+        if let Some(policy_type) = self.policy_type {
+            p.set_type(policy_type);
+        }
+        if let Some(rule) = self.rule {
+            p.set_rule(rule);
+        }
+        if let Some(action) = self.action {
+            p.set_action(action);
+        }
+        if let Some(policy_match) = self.policy_match {
+            p.set_match(policy_match);
+        }
+        if let Some(value) = self.value {
+            p.set_value(value);
+        }
+        p
+    }
+
+    fn from_protobuf(mut p: dnstap_pb::Policy) -> Result<Policy, &'static str> {
+        let action = match p.action {
+            Some(action) => Some(
+                action
+                    .enum_value()
+                    .map_err(|_| "Unsupported policy action")?,
+            ),
+            None => None,
+        };
+        let policy_match = match p.match_ {
+            Some(policy_match) => Some(
+                policy_match
+                    .enum_value()
+                    .map_err(|_| "Unsupported policy match")?,
+            ),
+            None => None,
+        };
+        Ok(Policy {
+            policy_type: p.type_.take(),
+            rule: p.rule.take(),
+            action,
+            policy_match,
+            value: p.value.take(),
+        })
+    }
 }
 
 impl DNSMessage {
@@ -38,9 +235,11 @@ impl DNSMessage {
         DNSMessage {
             identity,
             version,
+            extra: None,
             message_type,
             socket_family: None,
             socket_protocol: None,
+            http_protocol: None,
             query_address: None,
             query_port: None,
             query_time: None,
@@ -50,11 +249,132 @@ impl DNSMessage {
             response_time: None,
             response_packet: None,
             bailiwick: None,
+            policy: None,
+            summary: None,
+        }
+    }
+
+    /// Returns a `DNSMessageBuilder` for a message of the given type.
+    pub fn builder(message_type: MessageType) -> DNSMessageBuilder {
+        DNSMessageBuilder::new(message_type)
+    }
+
+    /// Sets the time the query was sent or received.
+    pub fn set_query_time(&mut self, query_time: time::SystemTime) {
+        self.query_time = Some(timestamp::since_epoch(query_time));
+    }
+
+    /// Sets the time the response was sent or received.
+    pub fn set_response_time(&mut self, response_time: time::SystemTime) {
+        self.response_time = Some(timestamp::since_epoch(response_time));
+    }
+
+    /// Returns the time the query was sent or received.
+    pub fn query_system_time(&self) -> Option<time::SystemTime> {
+        self.query_time
+            .map(|query_time| time::UNIX_EPOCH + query_time)
+    }
+
+    /// Returns the time the response was sent or received.
+    pub fn response_system_time(&self) -> Option<time::SystemTime> {
+        self.response_time
+            .map(|response_time| time::UNIX_EPOCH + response_time)
+    }
+
+    /// Checks that the message is consistent with the dnstap specification.
+    ///
+    /// The query and response addresses must belong to the same family, which must match
+    /// `socket_family` if it is set. Query messages must include the query packet, and
+    /// response messages the response packet. Ports cannot be zero, `http_protocol` requires
+    /// the DoH protocol, and `bailiwick` must be a valid name.
+    ///
+    /// The fields that `dnstap.proto` recommends for each type of message must be set: the
+    /// socket family and protocol and the query time for all messages, the response time for
+    /// responses, the query address and port for `AUTH_*` messages, and the response address
+    /// and port and the bailiwick for `RESOLVER_*` and `FORWARDER_*` messages.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let mut socket_family = self.socket_family;
+        for (ip, error) in [
+            (
+                self.query_address,
+                "Query address does not match the socket family",
+            ),
+            (
+                self.response_address,
+                "Response address does not match the socket family",
+            ),
+        ] {
+            let ip_family = match ip {
+                Some(IpAddr::V4(_)) => SocketFamily::INET,
+                Some(IpAddr::V6(_)) => SocketFamily::INET6,
+                None => continue,
+            };
+            if *socket_family.get_or_insert(ip_family) != ip_family {
+                return Err(error);
+            }
+        }
+        if is_query(self.message_type) {
+            if self.query_packet.is_none() {
+                return Err("Query message without a query packet");
+            }
+        } else if self.response_packet.is_none() {
+            return Err("Response message without a response packet");
+        }
+        if self.query_port == Some(0) {
+            return Err("Invalid query port");
+        }
+        if self.response_port == Some(0) {
+            return Err("Invalid response port");
+        }
+        if self.http_protocol.is_some() && self.socket_protocol != Some(SocketProtocol::DOH) {
+            return Err("HTTP protocol set on a non-DoH message");
+        }
+        if let Some(ref bailiwick) = self.bailiwick {
+            dns_packet::name_to_wire(bailiwick)?;
+        }
+        if socket_family.is_none() {
+            return Err("Message without a socket family");
+        }
+        if self.socket_protocol.is_none() {
+            return Err("Message without a socket protocol");
+        }
+        if self.query_time.is_none() {
+            return Err("Message without a query time");
         }
+        if !is_query(self.message_type) && self.response_time.is_none() {
+            return Err("Response message without a response time");
+        }
+        match self.message_type {
+            MessageType::AUTH_QUERY | MessageType::AUTH_RESPONSE => {
+                if self.query_address.is_none() {
+                    return Err("Authoritative message without a query address");
+                }
+                if self.query_port.is_none() {
+                    return Err("Authoritative message without a query port");
+                }
+            }
+            MessageType::RESOLVER_QUERY
+            | MessageType::RESOLVER_RESPONSE
+            | MessageType::FORWARDER_QUERY
+            | MessageType::FORWARDER_RESPONSE => {
+                if self.response_address.is_none() {
+                    return Err("Resolver or forwarder message without a response address");
+                }
+                if self.response_port.is_none() {
+                    return Err("Resolver or forwarder message without a response port");
+                }
+                if self.bailiwick.is_none() {
+                    return Err("Resolver or forwarder message without a bailiwick");
+                }
+            }
+            _ => {}
+        }
+        Ok(())
     }
 
     #[doc(hidden)]
-    pub fn into_protobuf(self) -> dnstap_pb::Dnstap {
+    pub fn into_protobuf(self) -> Result<dnstap_pb::Dnstap, &'static str> {
+        let socket_family = self.encoded_socket_family()?;
         let mut d = dnstap_pb::Dnstap::new();
         if let Some(identity) = self.identity {
             d.set_identity(identity);
@@ -62,46 +382,30 @@ impl DNSMessage {
         if let Some(version) = self.version {
             d.set_version(version);
         }
+        if let Some(extra) = self.extra {
+            d.set_extra(extra);
+        }
         d.set_type(dnstap_pb::dnstap::Type::MESSAGE);
         let mut msg = dnstap_pb::Message::new();
         msg.set_type(self.message_type);
-        let mut socket_family = self.socket_family;
-        socket_family = match self.query_address {
-            Some(IpAddr::V4(ip4)) => {
-                msg.set_query_address(ip4.octets().to_vec());
-                Some(SocketFamily::INET)
-            }
-            Some(IpAddr::V6(ip6)) => {
-                msg.set_query_address(ip6.octets().to_vec());
-                Some(SocketFamily::INET6)
-            }
-            None => socket_family,
-        };
-        socket_family = match self.response_address {
-            Some(IpAddr::V4(ip4)) => {
-                msg.set_response_address(ip4.octets().to_vec());
-                debug_assert_eq!(
-                    socket_family.unwrap_or(SocketFamily::INET),
-                    SocketFamily::INET
-                );
-                Some(SocketFamily::INET)
-            }
-            Some(IpAddr::V6(ip6)) => {
-                msg.set_response_address(ip6.octets().to_vec());
-                debug_assert_eq!(
-                    socket_family.unwrap_or(SocketFamily::INET6),
-                    SocketFamily::INET6
-                );
-                Some(SocketFamily::INET6)
-            }
-            None => socket_family,
-        };
         if let Some(socket_family) = socket_family {
             msg.set_socket_family(socket_family);
         }
         if let Some(socket_protocol) = self.socket_protocol {
             msg.set_socket_protocol(socket_protocol);
         }
+        if let Some(query_address) = self.query_address {
+            msg.set_query_address(ip_to_bytes(query_address));
+        }
+        if let Some(response_address) = self.response_address {
+            msg.set_response_address(ip_to_bytes(response_address));
+        }
+        if let Some(http_protocol) = self.http_protocol {
+            if self.socket_protocol != Some(SocketProtocol::DOH) {
+                return Err("HTTP protocol set on a non-DoH message");
+            }
+            msg.set_http_protocol(http_protocol);
+        }
         if let Some(query_port) = self.query_port {
             msg.set_query_port(u32::from(query_port));
         }
@@ -112,7 +416,7 @@ impl DNSMessage {
             msg.set_query_message(query_packet);
         }
         if let Some(response_packet) = self.response_packet {
-            msg.set_query_message(response_packet);
+            msg.set_response_message(response_packet);
         }
         if let Some(query_time) = self.query_time {
             msg.set_query_time_sec(query_time.as_secs());
@@ -122,8 +426,176 @@ impl DNSMessage {
             msg.set_response_time_sec(response_time.as_secs());
             msg.set_response_time_nsec(response_time.subsec_nanos());
         }
+        if let Some(bailiwick) = self.bailiwick {
+            msg.set_query_zone(dns_packet::name_to_wire(&bailiwick)?);
+        }
+        if let Some(policy) = self.policy {
+            msg.policy = Some(policy.into_protobuf()).into();
+        }
         d.message = Some(msg).into();
-        d
+        Ok(d)
+    }
+
+    /// Checks the fields that prevent the message from being encoded, without encoding it.
+    pub(crate) fn check_encoding(&self) -> Result<(), &'static str> {
+        self.encoded_socket_family()?;
+        if self.http_protocol.is_some() && self.socket_protocol != Some(SocketProtocol::DOH) {
+            return Err("HTTP protocol set on a non-DoH message");
+        }
+        if let Some(ref bailiwick) = self.bailiwick {
+            dns_packet::name_to_wire_buf(bailiwick, &mut [0u8; dns_packet::MAX_NAME_LEN])?;
+        }
+        Ok(())
+    }
+
+    /// Returns the socket family to encode. The family of the query address takes
+    /// precedence, and the response address must belong to the same family.
+    pub(crate) fn encoded_socket_family(&self) -> Result<Option<SocketFamily>, &'static str> {
+        let socket_family = self.query_address.map(ip_family).or(self.socket_family);
+        match self.response_address.map(ip_family) {
+            Some(ip_family) if socket_family.unwrap_or(ip_family) != ip_family => {
+                Err("Response address does not match the socket family")
+            }
+            Some(ip_family) => Ok(Some(ip_family)),
+            None => Ok(socket_family),
+        }
+    }
+
+    /// Returns the size of the serialized dnstap payload.
+    pub fn encoded_len(&self) -> Result<usize, &'static str> {
+        Ok(Encoder::new(self)?.len())
+    }
+
+    /// Serializes the message as a dnstap payload into `writer`, without intermediate
+    /// allocations.
+    pub fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        Encoder::new(self).map_err(invalid_data)?.write_to(writer)
+    }
+
+    /// Serializes the message as a Frame Streams data frame, made of the length of the
+    /// payload followed by the payload, into `writer`. Returns the length of the payload.
+    pub fn encode_frame<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        let encoder = Encoder::new(self).map_err(invalid_data)?;
+        writer.write_all(&(encoder.len() as u32).to_be_bytes())?;
+        encoder.write_to(writer)?;
+        Ok(encoder.len())
+    }
+
+    #[doc(hidden)]
+    pub fn from_protobuf(d: dnstap_pb::Dnstap) -> Result<DNSMessage, &'static str> {
+        if d.type_.and_then(|t| t.enum_value().ok()) != Some(dnstap_pb::dnstap::Type::MESSAGE) {
+            return Err("Unsupported dnstap payload type");
+        }
+        let mut msg = d.message.into_option().ok_or("Missing message")?;
+        let message_type = msg
+            .type_
+            .and_then(|t| t.enum_value().ok())
+            .ok_or("Unsupported message type")?;
+        let mut dns_message = DNSMessage::new(d.identity, d.version, message_type);
+        dns_message.extra = d.extra;
+        dns_message.socket_family = match msg.socket_family {
+            Some(socket_family) => Some(
+                socket_family
+                    .enum_value()
+                    .map_err(|_| "Unsupported socket family")?,
+            ),
+            None => None,
+        };
+        dns_message.socket_protocol = match msg.socket_protocol {
+            Some(socket_protocol) => Some(
+                socket_protocol
+                    .enum_value()
+                    .map_err(|_| "Unsupported socket protocol")?,
+            ),
+            None => None,
+        };
+        dns_message.http_protocol = match msg.http_protocol {
+            Some(http_protocol) => Some(
+                http_protocol
+                    .enum_value()
+                    .map_err(|_| "Unsupported HTTP protocol")?,
+            ),
+            None => None,
+        };
+        dns_message.query_address = match msg.query_address.take() {
+            Some(ip) => Some(ip_from_bytes(&ip).ok_or("Invalid query address")?),
+            None => None,
+        };
+        dns_message.response_address = match msg.response_address.take() {
+            Some(ip) => Some(ip_from_bytes(&ip).ok_or("Invalid response address")?),
+            None => None,
+        };
+        dns_message.query_port = match msg.query_port {
+            Some(port) => Some(port_from_u32(port).ok_or("Invalid query port")?),
+            None => None,
+        };
+        dns_message.response_port = match msg.response_port {
+            Some(port) => Some(port_from_u32(port).ok_or("Invalid response port")?),
+            None => None,
+        };
+        dns_message.query_time = msg.query_time_sec.map(|secs| {
+            time::Duration::new(secs, msg.query_time_nsec.unwrap_or(0) % 1_000_000_000)
+        });
+        dns_message.response_time = msg.response_time_sec.map(|secs| {
+            time::Duration::new(secs, msg.response_time_nsec.unwrap_or(0) % 1_000_000_000)
+        });
+        dns_message.query_packet = msg.query_message.take();
+        dns_message.response_packet = msg.response_message.take();
+        dns_message.bailiwick = match msg.query_zone.take() {
+            Some(query_zone) => {
+                Some(dns_packet::name_from_wire(&query_zone).map_err(|_| "Invalid query zone")?)
+            }
+            None => None,
+        };
+        dns_message.policy = match msg.policy.take() {
+            Some(policy) => Some(Policy::from_protobuf(policy)?),
+            None => None,
+        };
+        Ok(dns_message)
+    }
+
+    /// Decodes a serialized dnstap payload, as found in a Frame Streams data frame.
+    pub fn from_bytes(bytes: &[u8]) -> Result<DNSMessage, &'static str> {
+        let d = dnstap_pb::Dnstap::parse_from_bytes(bytes).map_err(|_| "Invalid dnstap payload")?;
+        DNSMessage::from_protobuf(d)
+    }
+}
+
+fn ip_family(ip: IpAddr) -> SocketFamily {
+    match ip {
+        IpAddr::V4(_) => SocketFamily::INET,
+        IpAddr::V6(_) => SocketFamily::INET6,
+    }
+}
+
+fn ip_to_bytes(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(ip4) => ip4.octets().to_vec(),
+        IpAddr::V6(ip6) => ip6.octets().to_vec(),
+    }
+}
+
+pub(crate) fn ip_from_bytes(ip: &[u8]) -> Option<IpAddr> {
+    match ip.len() {
+        4 => {
+            let mut octets = [0u8; 4];
+            octets.copy_from_slice(ip);
+            Some(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        16 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(ip);
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+fn port_from_u32(port: u32) -> Option<u16> {
+    if port > u32::from(u16::MAX) {
+        None
+    } else {
+        Some(port as u16)
     }
 }
 
@@ -131,6 +603,7 @@ impl DNSMessage {
 pub struct AuthQuery {
     pub identity: Option<Vec<u8>>,
     pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
     pub socket_protocol: SocketProtocol,
     pub query_address: IpAddr,
     pub query_port: u16,
@@ -138,9 +611,11 @@ pub struct AuthQuery {
     pub query_packet: Vec<u8>,
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<DNSMessage> for AuthQuery {
     fn into(self) -> DNSMessage {
         let mut dns_message = DNSMessage::new(self.identity, self.version, MessageType::AUTH_QUERY);
+        dns_message.extra = self.extra;
         dns_message.socket_protocol = Some(self.socket_protocol);
         dns_message.query_address = Some(self.query_address);
         dns_message.query_port = Some(self.query_port);
@@ -154,22 +629,85 @@ impl Into<DNSMessage> for AuthQuery {
 pub struct AuthResponse {
     pub identity: Option<Vec<u8>>,
     pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
     pub message_type: MessageType,
     pub socket_protocol: SocketProtocol,
     pub query_address: IpAddr,
     pub query_port: u16,
     pub query_time: time::Duration,
+    pub response_time: time::Duration,
     pub response_packet: Vec<u8>,
+    pub policy: Option<Policy>,
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<DNSMessage> for AuthResponse {
     fn into(self) -> DNSMessage {
         let mut dns_message =
             DNSMessage::new(self.identity, self.version, MessageType::AUTH_RESPONSE);
+        dns_message.extra = self.extra;
+        dns_message.socket_protocol = Some(self.socket_protocol);
+        dns_message.query_address = Some(self.query_address);
+        dns_message.query_port = Some(self.query_port);
+        dns_message.query_time = Some(self.query_time);
+        dns_message.response_time = Some(self.response_time);
+        dns_message.response_packet = Some(self.response_packet);
+        dns_message.policy = self.policy;
+        dns_message
+    }
+}
+
+#[derive(Clone, Hash)]
+pub struct UpdateQuery {
+    pub identity: Option<Vec<u8>>,
+    pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
+    pub socket_protocol: SocketProtocol,
+    pub query_address: IpAddr,
+    pub query_port: u16,
+    pub query_time: time::Duration,
+    pub query_packet: Vec<u8>,
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<DNSMessage> for UpdateQuery {
+    fn into(self) -> DNSMessage {
+        let mut dns_message =
+            DNSMessage::new(self.identity, self.version, MessageType::UPDATE_QUERY);
+        dns_message.extra = self.extra;
+        dns_message.socket_protocol = Some(self.socket_protocol);
+        dns_message.query_address = Some(self.query_address);
+        dns_message.query_port = Some(self.query_port);
+        dns_message.query_time = Some(self.query_time);
+        dns_message.query_packet = Some(self.query_packet);
+        dns_message
+    }
+}
+
+#[derive(Clone, Hash)]
+pub struct UpdateResponse {
+    pub identity: Option<Vec<u8>>,
+    pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
+    pub socket_protocol: SocketProtocol,
+    pub query_address: IpAddr,
+    pub query_port: u16,
+    pub query_time: time::Duration,
+    pub response_time: time::Duration,
+    pub response_packet: Vec<u8>,
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<DNSMessage> for UpdateResponse {
+    fn into(self) -> DNSMessage {
+        let mut dns_message =
+            DNSMessage::new(self.identity, self.version, MessageType::UPDATE_RESPONSE);
+        dns_message.extra = self.extra;
         dns_message.socket_protocol = Some(self.socket_protocol);
         dns_message.query_address = Some(self.query_address);
         dns_message.query_port = Some(self.query_port);
         dns_message.query_time = Some(self.query_time);
+        dns_message.response_time = Some(self.response_time);
         dns_message.response_packet = Some(self.response_packet);
         dns_message
     }
@@ -179,6 +717,7 @@ impl Into<DNSMessage> for AuthResponse {
 pub struct ResolverQuery {
     pub identity: Option<Vec<u8>>,
     pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
     pub socket_protocol: SocketProtocol,
     pub query_time: time::Duration,
     pub query_packet: Vec<u8>,
@@ -187,10 +726,12 @@ pub struct ResolverQuery {
     pub bailiwick: String,
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<DNSMessage> for ResolverQuery {
     fn into(self) -> DNSMessage {
         let mut dns_message =
             DNSMessage::new(self.identity, self.version, MessageType::RESOLVER_QUERY);
+        dns_message.extra = self.extra;
         dns_message.socket_protocol = Some(self.socket_protocol);
         dns_message.query_time = Some(self.query_time);
         dns_message.query_packet = Some(self.query_packet);
@@ -205,6 +746,7 @@ impl Into<DNSMessage> for ResolverQuery {
 pub struct ResolverResponse {
     pub identity: Option<Vec<u8>>,
     pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
     pub socket_protocol: SocketProtocol,
     pub query_time: time::Duration,
     pub response_address: IpAddr,
@@ -212,12 +754,15 @@ pub struct ResolverResponse {
     pub response_packet: Vec<u8>,
     pub response_time: time::Duration,
     pub bailiwick: String,
+    pub policy: Option<Policy>,
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<DNSMessage> for ResolverResponse {
     fn into(self) -> DNSMessage {
         let mut dns_message =
             DNSMessage::new(self.identity, self.version, MessageType::RESOLVER_RESPONSE);
+        dns_message.extra = self.extra;
         dns_message.socket_protocol = Some(self.socket_protocol);
         dns_message.query_time = Some(self.query_time);
         dns_message.response_address = Some(self.response_address);
@@ -225,6 +770,7 @@ impl Into<DNSMessage> for ResolverResponse {
         dns_message.response_packet = Some(self.response_packet);
         dns_message.response_time = Some(self.response_time);
         dns_message.bailiwick = Some(self.bailiwick);
+        dns_message.policy = self.policy;
         dns_message
     }
 }
@@ -233,16 +779,19 @@ impl Into<DNSMessage> for ResolverResponse {
 pub struct ClientQuery {
     pub identity: Option<Vec<u8>>,
     pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
     pub socket_family: SocketFamily,
     pub socket_protocol: SocketProtocol,
     pub query_time: time::Duration,
     pub query_packet: Vec<u8>,
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<DNSMessage> for ClientQuery {
     fn into(self) -> DNSMessage {
         let mut dns_message =
             DNSMessage::new(self.identity, self.version, MessageType::CLIENT_QUERY);
+        dns_message.extra = self.extra;
         dns_message.socket_family = Some(self.socket_family);
         dns_message.socket_protocol = Some(self.socket_protocol);
         dns_message.query_time = Some(self.query_time);
@@ -255,20 +804,312 @@ impl Into<DNSMessage> for ClientQuery {
 pub struct ClientResponse {
     pub identity: Option<Vec<u8>>,
     pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
     pub socket_family: SocketFamily,
     pub socket_protocol: SocketProtocol,
+    pub query_time: time::Duration,
     pub response_time: time::Duration,
     pub response_packet: Vec<u8>,
+    pub policy: Option<Policy>,
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<DNSMessage> for ClientResponse {
     fn into(self) -> DNSMessage {
         let mut dns_message =
             DNSMessage::new(self.identity, self.version, MessageType::CLIENT_RESPONSE);
+        dns_message.extra = self.extra;
         dns_message.socket_family = Some(self.socket_family);
         dns_message.socket_protocol = Some(self.socket_protocol);
+        dns_message.query_time = Some(self.query_time);
+        dns_message.response_time = Some(self.response_time);
+        dns_message.response_packet = Some(self.response_packet);
+        dns_message.policy = self.policy;
+        dns_message
+    }
+}
+
+#[derive(Clone, Hash)]
+pub struct ForwarderQuery {
+    pub identity: Option<Vec<u8>>,
+    pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
+    pub socket_protocol: SocketProtocol,
+    pub query_time: time::Duration,
+    pub query_packet: Vec<u8>,
+    pub response_address: IpAddr,
+    pub response_port: u16,
+    pub bailiwick: String,
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<DNSMessage> for ForwarderQuery {
+    fn into(self) -> DNSMessage {
+        let mut dns_message =
+            DNSMessage::new(self.identity, self.version, MessageType::FORWARDER_QUERY);
+        dns_message.extra = self.extra;
+        dns_message.socket_protocol = Some(self.socket_protocol);
+        dns_message.query_time = Some(self.query_time);
+        dns_message.query_packet = Some(self.query_packet);
+        dns_message.response_address = Some(self.response_address);
+        dns_message.response_port = Some(self.response_port);
+        dns_message.bailiwick = Some(self.bailiwick);
+        dns_message
+    }
+}
+
+#[derive(Clone, Hash)]
+pub struct ForwarderResponse {
+    pub identity: Option<Vec<u8>>,
+    pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
+    pub socket_protocol: SocketProtocol,
+    pub query_time: time::Duration,
+    pub response_address: IpAddr,
+    pub response_port: u16,
+    pub response_packet: Vec<u8>,
+    pub response_time: time::Duration,
+    pub bailiwick: String,
+    pub policy: Option<Policy>,
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<DNSMessage> for ForwarderResponse {
+    fn into(self) -> DNSMessage {
+        let mut dns_message =
+            DNSMessage::new(self.identity, self.version, MessageType::FORWARDER_RESPONSE);
+        dns_message.extra = self.extra;
+        dns_message.socket_protocol = Some(self.socket_protocol);
+        dns_message.query_time = Some(self.query_time);
+        dns_message.response_address = Some(self.response_address);
+        dns_message.response_port = Some(self.response_port);
+        dns_message.response_packet = Some(self.response_packet);
+        dns_message.response_time = Some(self.response_time);
+        dns_message.bailiwick = Some(self.bailiwick);
+        dns_message.policy = self.policy;
+        dns_message
+    }
+}
+
+#[derive(Clone, Hash)]
+pub struct StubQuery {
+    pub identity: Option<Vec<u8>>,
+    pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
+    pub socket_protocol: SocketProtocol,
+    pub query_time: time::Duration,
+    pub query_packet: Vec<u8>,
+    pub response_address: IpAddr,
+    pub response_port: u16,
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<DNSMessage> for StubQuery {
+    fn into(self) -> DNSMessage {
+        let mut dns_message = DNSMessage::new(self.identity, self.version, MessageType::STUB_QUERY);
+        dns_message.extra = self.extra;
+        dns_message.socket_protocol = Some(self.socket_protocol);
+        dns_message.query_time = Some(self.query_time);
+        dns_message.query_packet = Some(self.query_packet);
+        dns_message.response_address = Some(self.response_address);
+        dns_message.response_port = Some(self.response_port);
+        dns_message
+    }
+}
+
+#[derive(Clone, Hash)]
+pub struct StubResponse {
+    pub identity: Option<Vec<u8>>,
+    pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
+    pub socket_protocol: SocketProtocol,
+    pub query_time: time::Duration,
+    pub response_address: IpAddr,
+    pub response_port: u16,
+    pub response_packet: Vec<u8>,
+    pub response_time: time::Duration,
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<DNSMessage> for StubResponse {
+    fn into(self) -> DNSMessage {
+        let mut dns_message =
+            DNSMessage::new(self.identity, self.version, MessageType::STUB_RESPONSE);
+        dns_message.extra = self.extra;
+        dns_message.socket_protocol = Some(self.socket_protocol);
+        dns_message.query_time = Some(self.query_time);
+        dns_message.response_address = Some(self.response_address);
+        dns_message.response_port = Some(self.response_port);
+        dns_message.response_packet = Some(self.response_packet);
         dns_message.response_time = Some(self.response_time);
+        dns_message
+    }
+}
+
+#[derive(Clone, Hash)]
+pub struct ToolQuery {
+    pub identity: Option<Vec<u8>>,
+    pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
+    pub socket_protocol: SocketProtocol,
+    pub query_time: time::Duration,
+    pub query_packet: Vec<u8>,
+    pub response_address: IpAddr,
+    pub response_port: u16,
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<DNSMessage> for ToolQuery {
+    fn into(self) -> DNSMessage {
+        let mut dns_message = DNSMessage::new(self.identity, self.version, MessageType::TOOL_QUERY);
+        dns_message.extra = self.extra;
+        dns_message.socket_protocol = Some(self.socket_protocol);
+        dns_message.query_time = Some(self.query_time);
+        dns_message.query_packet = Some(self.query_packet);
+        dns_message.response_address = Some(self.response_address);
+        dns_message.response_port = Some(self.response_port);
+        dns_message
+    }
+}
+
+#[derive(Clone, Hash)]
+pub struct ToolResponse {
+    pub identity: Option<Vec<u8>>,
+    pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
+    pub socket_protocol: SocketProtocol,
+    pub query_time: time::Duration,
+    pub response_address: IpAddr,
+    pub response_port: u16,
+    pub response_packet: Vec<u8>,
+    pub response_time: time::Duration,
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<DNSMessage> for ToolResponse {
+    fn into(self) -> DNSMessage {
+        let mut dns_message =
+            DNSMessage::new(self.identity, self.version, MessageType::TOOL_RESPONSE);
+        dns_message.extra = self.extra;
+        dns_message.socket_protocol = Some(self.socket_protocol);
+        dns_message.query_time = Some(self.query_time);
+        dns_message.response_address = Some(self.response_address);
+        dns_message.response_port = Some(self.response_port);
         dns_message.response_packet = Some(self.response_packet);
+        dns_message.response_time = Some(self.response_time);
         dns_message
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn validation() {
+        for dns_message in reference_messages() {
+            assert_eq!(dns_message.validate(), Ok(()));
+        }
+
+        let mut dns_message = DNSMessage::new(None, None, MessageType::AUTH_RESPONSE);
+        assert_eq!(
+            dns_message.validate(),
+            Err("Response message without a response packet")
+        );
+        dns_message.response_packet = Some(dns_response(1, "example.com", 1, &[192, 0, 2, 1]));
+        assert_eq!(
+            dns_message.validate(),
+            Err("Message without a socket family")
+        );
+
+        dns_message.query_address = Some(ip("192.0.2.1"));
+        dns_message.response_address = Some(ip("2001:db8::1"));
+        assert_eq!(
+            dns_message.validate(),
+            Err("Response address does not match the socket family")
+        );
+        assert!(dns_message.clone().into_protobuf().is_err());
+        dns_message.response_address = None;
+        dns_message.socket_family = Some(SocketFamily::INET6);
+        assert_eq!(
+            dns_message.validate(),
+            Err("Query address does not match the socket family")
+        );
+        dns_message.socket_family = Some(SocketFamily::INET);
+        dns_message.query_port = Some(0);
+        assert_eq!(dns_message.validate(), Err("Invalid query port"));
+        dns_message.query_port = None;
+        assert_eq!(
+            dns_message.validate(),
+            Err("Message without a socket protocol")
+        );
+        dns_message.socket_protocol = Some(SocketProtocol::UDP);
+        assert_eq!(dns_message.validate(), Err("Message without a query time"));
+        dns_message.query_time = Some(time::Duration::from_secs(1_700_000_000));
+        assert_eq!(
+            dns_message.validate(),
+            Err("Response message without a response time")
+        );
+        dns_message.response_time = Some(time::Duration::from_secs(1_700_000_001));
+        assert_eq!(
+            dns_message.validate(),
+            Err("Authoritative message without a query port")
+        );
+        dns_message.query_port = Some(53000);
+        assert_eq!(dns_message.validate(), Ok(()));
+        dns_message.query_address = None;
+        assert_eq!(
+            dns_message.validate(),
+            Err("Authoritative message without a query address")
+        );
+
+        let mut dns_message = DNSMessage::new(None, None, MessageType::FORWARDER_QUERY);
+        dns_message.socket_protocol = Some(SocketProtocol::TCP);
+        dns_message.query_time = Some(time::Duration::from_secs(1_700_000_000));
+        dns_message.query_packet = Some(dns_query(1, "example.com", 1));
+        dns_message.socket_family = Some(SocketFamily::INET6);
+        assert_eq!(
+            dns_message.validate(),
+            Err("Resolver or forwarder message without a response address")
+        );
+        dns_message.response_address = Some(ip("2001:db8::53"));
+        assert_eq!(
+            dns_message.validate(),
+            Err("Resolver or forwarder message without a response port")
+        );
+        dns_message.response_port = Some(53);
+        assert_eq!(
+            dns_message.validate(),
+            Err("Resolver or forwarder message without a bailiwick")
+        );
+        dns_message.bailiwick = Some("example..com".to_owned());
+        assert!(dns_message.validate().is_err());
+        dns_message.bailiwick = Some("example.com.".to_owned());
+        assert_eq!(dns_message.validate(), Ok(()));
+
+        let mut dns_message = DNSMessage::new(None, None, MessageType::STUB_QUERY);
+        dns_message.socket_family = Some(SocketFamily::INET);
+        dns_message.socket_protocol = Some(SocketProtocol::DOT);
+        dns_message.query_time = Some(time::Duration::from_secs(1_700_000_000));
+        dns_message.query_packet = Some(dns_query(1, "example.com", 1));
+        assert_eq!(dns_message.validate(), Ok(()));
+    }
+
+    #[test]
+    fn system_time_helpers() {
+        let dns_messages = reference_messages();
+        let query_time = time::UNIX_EPOCH + time::Duration::new(1_700_000_000, 123_456_789);
+        assert_eq!(dns_messages[0].query_system_time(), Some(query_time));
+        assert_eq!(dns_messages[0].response_system_time(), None);
+
+        let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_RESPONSE);
+        dns_message.set_response_time(query_time);
+        assert_eq!(
+            dns_message.response_time,
+            Some(time::Duration::new(1_700_000_000, 123_456_789))
+        );
+        dns_message.set_query_time(time::UNIX_EPOCH - time::Duration::from_secs(1));
+        assert_eq!(dns_message.query_time, Some(time::Duration::new(0, 0)));
+    }
+}
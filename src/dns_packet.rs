@@ -0,0 +1,771 @@
+use std::fmt::{self, Write};
+
+use crate::dns_message::*;
+use crate::dnstap_text::is_query;
+
+const DNS_HEADER_SIZE: usize = 12;
+const MAX_LABEL_LEN: usize = 63;
+pub(crate) const MAX_NAME_LEN: usize = 255;
+const MAX_POINTERS: usize = 64;
+
+/// A zero-copy view of a DNS packet.
+///
+/// Only the header is required to be present. Everything else is decoded on demand, so
+/// that truncated and malformed packets still expose whatever can be read from them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DNSPacket<'a> {
+    packet: &'a [u8],
+}
+
+impl<'a> DNSPacket<'a> {
+    /// Creates a view of a DNS packet. Returns `None` if the packet is shorter than a DNS
+    /// header.
+    pub fn new(packet: &'a [u8]) -> Option<DNSPacket<'a>> {
+        if packet.len() < DNS_HEADER_SIZE {
+            return None;
+        }
+        Some(DNSPacket { packet })
+    }
+
+    /// Returns the raw packet.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.packet
+    }
+
+    /// Message identifier.
+    pub fn id(&self) -> u16 {
+        be16(self.packet, 0).unwrap()
+    }
+
+    /// Raw header flags, including the opcode and the response code.
+    pub fn flags(&self) -> u16 {
+        be16(self.packet, 2).unwrap()
+    }
+
+    /// Whether the QR bit is set.
+    pub fn is_response(&self) -> bool {
+        self.flags() & 0x8000 != 0
+    }
+
+    /// Operation code.
+    pub fn opcode(&self) -> u8 {
+        ((self.flags() >> 11) & 0xf) as u8
+    }
+
+    /// Response code from the header. Extended response codes carried in an OPT record
+    /// are not included.
+    pub fn rcode(&self) -> u8 {
+        (self.flags() & 0xf) as u8
+    }
+
+    /// Number of entries in the question section, according to the header.
+    pub fn question_count(&self) -> u16 {
+        be16(self.packet, 4).unwrap()
+    }
+
+    /// Number of records in the answer section, according to the header.
+    pub fn answer_count(&self) -> u16 {
+        be16(self.packet, 6).unwrap()
+    }
+
+    /// Number of records in the authority section, according to the header.
+    pub fn authority_count(&self) -> u16 {
+        be16(self.packet, 8).unwrap()
+    }
+
+    /// Number of records in the additional section, according to the header.
+    pub fn additional_count(&self) -> u16 {
+        be16(self.packet, 10).unwrap()
+    }
+
+    /// Returns the first question, or `None` if there is none or if it cannot be parsed.
+    pub fn question(&self) -> Option<DNSQuestion<'a>> {
+        if self.question_count() == 0 {
+            return None;
+        }
+        let (qname, offset) = DNSName::parse(self.packet, DNS_HEADER_SIZE)?;
+        Some(DNSQuestion {
+            qname,
+            qtype: be16(self.packet, offset)?,
+            qclass: be16(self.packet, offset + 2)?,
+        })
+    }
+}
+
+/// The name, type and class of a question.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DNSQuestion<'a> {
+    pub qname: DNSName<'a>,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+impl<'a> fmt::Display for DNSQuestion<'a> {
+    /// Formats the question as `name CLASS TYPE`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.qname,
+            class_name(self.qclass),
+            rr_type_name(self.qtype)
+        )
+    }
+}
+
+/// A zero-copy view of a name stored in a DNS packet, possibly compressed.
+///
+/// Names are validated when parsed, and are displayed in presentation format, with special
+/// characters escaped. Comparisons are case-insensitive.
+#[derive(Clone, Copy, Debug)]
+pub struct DNSName<'a> {
+    packet: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> DNSName<'a> {
+    /// Parses the name starting at `offset` in `packet`, and returns it along with the
+    /// offset right after the name.
+    pub fn parse(packet: &'a [u8], offset: usize) -> Option<(DNSName<'a>, usize)> {
+        let name = DNSName { packet, offset };
+        let mut offset = offset;
+        let mut next_offset = None;
+        let mut name_len = 0;
+        let mut pointers = 0;
+        loop {
+            let label_len = *packet.get(offset)? as usize;
+            match label_len & 0xc0 {
+                0x00 => {}
+                0xc0 => {
+                    pointers += 1;
+                    if pointers > MAX_POINTERS {
+                        return None;
+                    }
+                    let ptr = ((label_len & 0x3f) << 8) | *packet.get(offset + 1)? as usize;
+                    if next_offset.is_none() {
+                        next_offset = Some(offset + 2);
+                    }
+                    offset = ptr;
+                    continue;
+                }
+                _ => return None,
+            }
+            if label_len == 0 {
+                return Some((name, next_offset.unwrap_or(offset + 1)));
+            }
+            // The total length includes the root label
+            name_len += label_len + 1;
+            if name_len + 1 > MAX_NAME_LEN {
+                return None;
+            }
+            packet.get(offset + 1..offset + 1 + label_len)?;
+            offset += 1 + label_len;
+        }
+    }
+
+    /// Returns an iterator over the labels of the name, excluding the root label.
+    pub fn labels(&self) -> Labels<'a> {
+        Labels {
+            packet: self.packet,
+            offset: Some(self.offset),
+        }
+    }
+
+    /// Whether this is the root name.
+    pub fn is_root(&self) -> bool {
+        self.labels().next().is_none()
+    }
+}
+
+impl<'a> PartialEq for DNSName<'a> {
+    fn eq(&self, other: &DNSName<'_>) -> bool {
+        let (mut a, mut b) = (self.labels(), other.labels());
+        loop {
+            match (a.next(), b.next()) {
+                (None, None) => return true,
+                (Some(x), Some(y)) if x.eq_ignore_ascii_case(y) => {}
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl<'a> Eq for DNSName<'a> {}
+
+impl<'a> fmt::Display for DNSName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_root() {
+            return f.write_str(".");
+        }
+        for label in self.labels() {
+            write_label(f, label)?;
+            f.write_str(".")?;
+        }
+        Ok(())
+    }
+}
+
+/// An iterator over the labels of a `DNSName`.
+pub struct Labels<'a> {
+    packet: &'a [u8],
+    offset: Option<usize>,
+}
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let mut offset = self.offset?;
+        while let Some(&label_len) = self.packet.get(offset) {
+            let label_len = label_len as usize;
+            if label_len & 0xc0 == 0xc0 {
+                match self.packet.get(offset + 1) {
+                    Some(&lo) => offset = ((label_len & 0x3f) << 8) | lo as usize,
+                    None => break,
+                }
+                continue;
+            }
+            if label_len == 0 {
+                break;
+            }
+            let label = self.packet.get(offset + 1..offset + 1 + label_len);
+            self.offset = label.map(|_| offset + 1 + label_len);
+            return label;
+        }
+        self.offset = None;
+        None
+    }
+}
+
+/// Converts a name in presentation format, such as `example.com.`, to DNS wire format.
+/// Escaped characters (`\\.` and `\\DDD`) are supported, and the trailing dot is optional.
+pub fn name_to_wire(name: &str) -> Result<Vec<u8>, &'static str> {
+    let mut wire = [0u8; MAX_NAME_LEN];
+    let len = name_to_wire_buf(name, &mut wire)?;
+    Ok(wire[..len].to_vec())
+}
+
+/// Converts a name in presentation format to DNS wire format into `wire`, and returns the
+/// length of the wire format name.
+pub(crate) fn name_to_wire_buf(
+    name: &str,
+    wire: &mut [u8; MAX_NAME_LEN],
+) -> Result<usize, &'static str> {
+    let name = name.as_bytes();
+    if name.is_empty() {
+        return Err("Empty name");
+    }
+    let mut label_offset = 0;
+    let mut label_len = 0;
+    if name != b"." {
+        let mut i = 0;
+        while i < name.len() {
+            let c = name[i];
+            i += 1;
+            let c = match c {
+                b'.' => {
+                    close_wire_label(wire, label_offset, label_len)?;
+                    label_offset += 1 + label_len;
+                    label_len = 0;
+                    continue;
+                }
+                b'\\' => match name.get(i..i + 3) {
+                    Some(digits) if digits.iter().all(u8::is_ascii_digit) => {
+                        let value = digits
+                            .iter()
+                            .fold(0u16, |acc, &d| acc * 10 + u16::from(d - b'0'));
+                        if value > 255 {
+                            return Err("Invalid escape sequence");
+                        }
+                        i += 3;
+                        value as u8
+                    }
+                    _ => {
+                        let c = *name.get(i).ok_or("Invalid escape sequence")?;
+                        i += 1;
+                        c
+                    }
+                },
+                _ => c,
+            };
+            if label_len == MAX_LABEL_LEN {
+                return Err("Label too long");
+            }
+            // The root label must still fit after this byte
+            let offset = label_offset + 1 + label_len;
+            if offset + 1 >= MAX_NAME_LEN {
+                return Err("Name too long");
+            }
+            wire[offset] = c;
+            label_len += 1;
+        }
+        if label_len > 0 {
+            close_wire_label(wire, label_offset, label_len)?;
+            label_offset += 1 + label_len;
+        }
+    }
+    wire[label_offset] = 0;
+    Ok(label_offset + 1)
+}
+
+fn close_wire_label(
+    wire: &mut [u8; MAX_NAME_LEN],
+    label_offset: usize,
+    label_len: usize,
+) -> Result<(), &'static str> {
+    if label_len == 0 {
+        return Err("Empty label");
+    }
+    wire[label_offset] = label_len as u8;
+    Ok(())
+}
+
+/// Converts a name in DNS wire format to presentation format. The name must not be
+/// compressed, and must not be followed by any other data.
+pub fn name_from_wire(wire: &[u8]) -> Result<String, &'static str> {
+    let mut offset = 0;
+    loop {
+        let label_len = usize::from(*wire.get(offset).ok_or("Truncated name")?);
+        if label_len == 0 {
+            break;
+        }
+        if label_len > MAX_LABEL_LEN {
+            return Err("Invalid label length");
+        }
+        offset += 1 + label_len;
+    }
+    if offset + 1 != wire.len() {
+        return Err("Trailing data after name");
+    }
+    if wire.len() > MAX_NAME_LEN {
+        return Err("Name too long");
+    }
+    let (name, _) = DNSName::parse(wire, 0).ok_or("Invalid name")?;
+    Ok(name.to_string())
+}
+
+fn write_label<W: Write>(out: &mut W, label: &[u8]) -> fmt::Result {
+    for &c in label {
+        match c {
+            b'.' | b'\\' | b'"' | b'(' | b')' | b';' | b'@' | b'$' => {
+                out.write_char('\\')?;
+                out.write_char(c as char)?;
+            }
+            0x21..=0x7e => out.write_char(c as char)?,
+            _ => write!(out, "\\{:03}", c)?,
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+fn be16(packet: &[u8], offset: usize) -> Option<u16> {
+    let x = packet.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([x[0], x[1]]))
+}
+
+impl DNSMessage {
+    /// Returns a view of the query packet, if present.
+    pub fn query_dns_packet(&self) -> Option<DNSPacket<'_>> {
+        DNSPacket::new(self.query_packet.as_deref()?)
+    }
+
+    /// Returns a view of the response packet, if present.
+    pub fn response_dns_packet(&self) -> Option<DNSPacket<'_>> {
+        DNSPacket::new(self.response_packet.as_deref()?)
+    }
+
+    /// Returns a view of the packet matching the message type: the response packet for
+    /// responses, the query packet for queries. Falls back to the other packet if the
+    /// expected one is missing.
+    pub fn dns_packet(&self) -> Option<DNSPacket<'_>> {
+        let [first, second] = self.dns_packets();
+        first.or(second)
+    }
+
+    fn dns_packets(&self) -> [Option<DNSPacket<'_>>; 2] {
+        if is_query(self.message_type) {
+            [self.query_dns_packet(), self.response_dns_packet()]
+        } else {
+            [self.response_dns_packet(), self.query_dns_packet()]
+        }
+    }
+
+    /// Returns the first question of the query or response packet.
+    pub fn question(&self) -> Option<DNSQuestion<'_>> {
+        self.dns_packets()
+            .iter()
+            .flatten()
+            .find_map(|dns_packet| dns_packet.question())
+    }
+
+    /// Returns the queried name, in presentation format. Falls back to the summary if the
+    /// packets were removed.
+    pub fn qname(&self) -> Option<String> {
+        match self.question() {
+            Some(question) => Some(question.qname.to_string()),
+            None => self.summary.as_ref()?.qname.clone(),
+        }
+    }
+
+    /// Returns the queried type.
+    pub fn qtype(&self) -> Option<u16> {
+        match self.question() {
+            Some(question) => Some(question.qtype),
+            None => self.summary.as_ref()?.qtype,
+        }
+    }
+
+    /// Returns the queried class.
+    pub fn qclass(&self) -> Option<u16> {
+        match self.question() {
+            Some(question) => Some(question.qclass),
+            None => self.summary.as_ref()?.qclass,
+        }
+    }
+
+    /// Returns the message identifier.
+    pub fn id(&self) -> Option<u16> {
+        self.dns_packet().map(|dns_packet| dns_packet.id())
+    }
+
+    /// Returns the header flags.
+    pub fn flags(&self) -> Option<u16> {
+        self.dns_packet().map(|dns_packet| dns_packet.flags())
+    }
+
+    /// Returns the response code of the response packet, or from the summary if the
+    /// packets were removed.
+    pub fn rcode(&self) -> Option<u8> {
+        match self.response_dns_packet() {
+            Some(dns_packet) => Some(dns_packet.rcode()),
+            None => self.summary.as_ref()?.rcode,
+        }
+    }
+
+    /// Returns the number of records in the answer section of the response packet.
+    pub fn answer_count(&self) -> Option<u16> {
+        self.response_dns_packet()
+            .map(|dns_packet| dns_packet.answer_count())
+    }
+
+    /// Returns the number of records in the authority section of the response packet.
+    pub fn authority_count(&self) -> Option<u16> {
+        self.response_dns_packet()
+            .map(|dns_packet| dns_packet.authority_count())
+    }
+
+    /// Returns the number of records in the additional section of the response packet.
+    pub fn additional_count(&self) -> Option<u16> {
+        self.response_dns_packet()
+            .map(|dns_packet| dns_packet.additional_count())
+    }
+}
+
+/// Returns the mnemonic of a record type, or its RFC 3597 generic representation.
+pub fn rr_type_name(rr_type: u16) -> String {
+    let name = match rr_type {
+        1 => "A",
+        2 => "NS",
+        5 => "CNAME",
+        6 => "SOA",
+        12 => "PTR",
+        13 => "HINFO",
+        15 => "MX",
+        16 => "TXT",
+        17 => "RP",
+        18 => "AFSDB",
+        24 => "SIG",
+        25 => "KEY",
+        28 => "AAAA",
+        29 => "LOC",
+        33 => "SRV",
+        35 => "NAPTR",
+        36 => "KX",
+        37 => "CERT",
+        39 => "DNAME",
+        41 => "OPT",
+        42 => "APL",
+        43 => "DS",
+        44 => "SSHFP",
+        45 => "IPSECKEY",
+        46 => "RRSIG",
+        47 => "NSEC",
+        48 => "DNSKEY",
+        49 => "DHCID",
+        50 => "NSEC3",
+        51 => "NSEC3PARAM",
+        52 => "TLSA",
+        53 => "SMIMEA",
+        55 => "HIP",
+        59 => "CDS",
+        60 => "CDNSKEY",
+        61 => "OPENPGPKEY",
+        62 => "CSYNC",
+        63 => "ZONEMD",
+        64 => "SVCB",
+        65 => "HTTPS",
+        99 => "SPF",
+        108 => "EUI48",
+        109 => "EUI64",
+        249 => "TKEY",
+        250 => "TSIG",
+        251 => "IXFR",
+        252 => "AXFR",
+        255 => "ANY",
+        256 => "URI",
+        257 => "CAA",
+        _ => return format!("TYPE{}", rr_type),
+    };
+    name.to_owned()
+}
+
+/// Returns the mnemonic of a class, or its RFC 3597 generic representation.
+pub fn class_name(class: u16) -> String {
+    let name = match class {
+        1 => "IN",
+        3 => "CH",
+        4 => "HS",
+        254 => "NONE",
+        255 => "ANY",
+        _ => return format!("CLASS{}", class),
+    };
+    name.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    fn header(qdcount: u16) -> Vec<u8> {
+        let mut packet = vec![0x12, 0x34, 0x81, 0x83];
+        packet.extend_from_slice(&qdcount.to_be_bytes());
+        packet.extend_from_slice(&[0, 2, 0, 3, 0, 4]);
+        packet
+    }
+
+    fn parse(packet: &[u8], offset: usize) -> Option<(String, usize)> {
+        DNSName::parse(packet, offset).map(|(name, next)| (name.to_string(), next))
+    }
+
+    #[test]
+    fn header_fields() {
+        let packet = header(1);
+        assert!(DNSPacket::new(&packet[..11]).is_none());
+        let dns_packet = DNSPacket::new(&packet).unwrap();
+        assert_eq!(dns_packet.id(), 0x1234);
+        assert_eq!(dns_packet.flags(), 0x8183);
+        assert!(dns_packet.is_response());
+        assert_eq!(dns_packet.opcode(), 0);
+        assert_eq!(dns_packet.rcode(), 3);
+        assert_eq!(dns_packet.question_count(), 1);
+        assert_eq!(dns_packet.answer_count(), 2);
+        assert_eq!(dns_packet.authority_count(), 3);
+        assert_eq!(dns_packet.additional_count(), 4);
+
+        let packet = [0, 0, 0x28, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let dns_packet = DNSPacket::new(&packet).unwrap();
+        assert!(!dns_packet.is_response());
+        assert_eq!(dns_packet.opcode(), 5);
+    }
+
+    #[test]
+    fn truncated_question() {
+        let packet = dns_query(1, "www.example.com", 28);
+        let question = DNSPacket::new(&packet).unwrap().question().unwrap();
+        assert_eq!(question.to_string(), "www.example.com. IN AAAA");
+
+        // Questions are ignored if the header says there are none
+        let mut packet = header(0);
+        packet.extend_from_slice(&name("example.com"));
+        packet.extend_from_slice(&[0, 1, 0, 1]);
+        assert!(DNSPacket::new(&packet).unwrap().question().is_none());
+
+        let mut packet = header(1);
+        assert!(DNSPacket::new(&packet).unwrap().question().is_none());
+        packet.extend_from_slice(&name("example.com"));
+        let name_end = packet.len();
+        packet.extend_from_slice(&[0, 1, 0, 1]);
+        for len in DNS_HEADER_SIZE..packet.len() {
+            assert!(DNSPacket::new(&packet[..len]).unwrap().question().is_none());
+        }
+        assert!(DNSPacket::new(&packet).unwrap().question().is_some());
+        assert_eq!(
+            parse(&packet[..name_end], DNS_HEADER_SIZE).unwrap().1,
+            name_end
+        );
+    }
+
+    #[test]
+    fn compressed_names() {
+        let mut packet = header(1);
+        packet.extend_from_slice(&name("example.com"));
+        // www.example.com, then a pointer to the root label of example.com
+        packet.extend_from_slice(&[3, b'w', b'w', b'w', 0xc0, 12]);
+        packet.extend_from_slice(&[0xc0, 12 + 12]);
+        assert_eq!(
+            parse(&packet, DNS_HEADER_SIZE),
+            Some(("example.com.".to_owned(), 25))
+        );
+        assert_eq!(
+            parse(&packet, 25),
+            Some(("www.example.com.".to_owned(), 31))
+        );
+        assert_eq!(parse(&packet, 31), Some((".".to_owned(), 33)));
+        let (name, _) = DNSName::parse(&packet, 25).unwrap();
+        assert_eq!(
+            name.labels().collect::<Vec<_>>(),
+            vec![&b"www"[..], b"example", b"com"]
+        );
+        assert!(DNSName::parse(&packet, 31).unwrap().0.is_root());
+    }
+
+    #[test]
+    fn pointer_loops() {
+        let mut packet = header(1);
+        // Pointer to itself
+        packet.extend_from_slice(&[0xc0, 12]);
+        assert!(parse(&packet, 12).is_none());
+
+        // Two pointers to each other, after a label
+        let mut packet = header(1);
+        packet.extend_from_slice(&[1, b'a', 0xc0, 16, 0xc0, 12]);
+        assert!(parse(&packet, 12).is_none());
+        assert!(parse(&packet, 16).is_none());
+
+        // A long chain of pointers that ends is accepted
+        let mut packet = header(1);
+        packet.push(0);
+        let mut target = 12;
+        for _ in 0..MAX_POINTERS {
+            packet.extend_from_slice(&[0xc0, target as u8]);
+            target = packet.len() - 2;
+        }
+        assert_eq!(parse(&packet, packet.len() - 2).unwrap().0, ".");
+        packet.extend_from_slice(&[0xc0, (packet.len() - 2) as u8]);
+        assert!(parse(&packet, packet.len() - 2).is_none());
+    }
+
+    #[test]
+    fn truncated_and_malformed_names() {
+        let mut packet = header(1);
+        packet.extend_from_slice(&name("example.com"));
+        let end = packet.len();
+        // Missing root label, and truncated label
+        assert!(parse(&packet[..end - 1], 12).is_none());
+        assert!(parse(&packet[..end - 3], 12).is_none());
+        // Offset past the end
+        assert!(parse(&packet, end).is_none());
+
+        // Pointer past the end
+        packet.extend_from_slice(&[0xc0, 0xff]);
+        assert!(parse(&packet, end).is_none());
+        packet.truncate(end);
+        packet.extend_from_slice(&[0xc1, 0x00]);
+        assert!(parse(&packet, end).is_none());
+        // Truncated pointer
+        packet.truncate(end);
+        packet.push(0xc0);
+        assert!(parse(&packet, end).is_none());
+
+        // Reserved label types
+        for &label_type in [0x40, 0x80].iter() {
+            let mut packet = header(1);
+            packet.extend_from_slice(&[label_type | 1, b'a', 0]);
+            assert!(parse(&packet, 12).is_none());
+        }
+    }
+
+    #[test]
+    fn long_names() {
+        let label = |len: usize| vec!["a"; len].concat();
+        let longest = [label(63), label(63), label(63), label(61)].join(".");
+        assert_eq!(name(&longest).len(), MAX_NAME_LEN);
+        let mut packet = header(1);
+        packet.extend_from_slice(&name(&longest));
+        assert_eq!(parse(&packet, 12).unwrap().0, longest.clone() + ".");
+
+        let too_long = [label(63), label(63), label(63), label(62)].join(".");
+        let mut packet = header(1);
+        packet.extend_from_slice(&name(&too_long));
+        assert!(parse(&packet, 12).is_none());
+
+        // The limit also applies to names split by compression
+        let compressed = |prefix: &str| {
+            let mut packet = header(1);
+            packet.extend_from_slice(&name(&[label(63), label(62)].join(".")));
+            let mut prefix = name(prefix);
+            prefix.pop();
+            packet.extend_from_slice(&prefix);
+            packet.extend_from_slice(&[0xc0, 12]);
+            parse(&packet, 12 + 128)
+        };
+        assert!(compressed(&[label(63), label(62)].join(".")).is_some());
+        assert!(compressed(&[label(63), label(63)].join(".")).is_none());
+    }
+
+    #[test]
+    fn case_insensitive_names() {
+        let mut packet = header(1);
+        packet.extend_from_slice(&name("Example.COM"));
+        packet.extend_from_slice(&name("www.example.com"));
+        packet.extend_from_slice(&[3, b'W', b'w', b'W', 0xc0, 12]);
+        packet.extend_from_slice(&name("example.org"));
+        packet.extend_from_slice(&name("example.com.org"));
+        let name_at = |offset| DNSName::parse(&packet, offset).unwrap().0;
+        let example_com = name_at(12);
+        let www = name_at(25);
+        let compressed_www = name_at(42);
+        assert_eq!(www, compressed_www);
+        assert_eq!(compressed_www.to_string(), "WwW.Example.COM.");
+        assert_ne!(example_com, www);
+        assert_ne!(example_com, name_at(48));
+        assert_ne!(example_com, name_at(61));
+
+        let other = name("EXAMPLE.com");
+        assert_eq!(example_com, DNSName::parse(&other, 0).unwrap().0);
+    }
+
+    #[test]
+    fn presentation_format() {
+        let wire = [
+            3, b'a', b'.', b'b', 2, b'\\', b'"', 3, 0, b' ', 0xff, 2, b'@', b'$', 0,
+        ];
+        let name = DNSName::parse(&wire, 0).unwrap().0;
+        assert_eq!(name.to_string(), "a\\.b.\\\\\\\".\\000\\032\\255.\\@\\$.");
+        assert_eq!(name_from_wire(&wire).unwrap(), name.to_string());
+        assert_eq!(name_to_wire(&name.to_string()).unwrap(), wire);
+
+        assert_eq!(name_to_wire(".").unwrap(), [0]);
+        assert_eq!(
+            name_to_wire("example.com").unwrap(),
+            name_to_wire("example.com.").unwrap()
+        );
+        assert_eq!(name_to_wire(""), Err("Empty name"));
+        assert_eq!(name_to_wire("a..b"), Err("Empty label"));
+        assert_eq!(name_to_wire("a\\256"), Err("Invalid escape sequence"));
+        assert_eq!(name_to_wire("a\\"), Err("Invalid escape sequence"));
+        assert_eq!(name_to_wire(&vec!["a"; 64].concat()), Err("Label too long"));
+        assert_eq!(
+            name_to_wire(&vec!["a."; 128].concat()),
+            Err("Name too long")
+        );
+
+        assert_eq!(name_from_wire(&[1, b'a']), Err("Truncated name"));
+        assert_eq!(
+            name_from_wire(&[1, b'a', 0, 0]),
+            Err("Trailing data after name")
+        );
+        assert_eq!(name_from_wire(&[0xc0, 0]), Err("Invalid label length"));
+    }
+
+    #[test]
+    fn type_and_class_names() {
+        assert_eq!(rr_type_name(28), "AAAA");
+        assert_eq!(rr_type_name(65), "HTTPS");
+        assert_eq!(rr_type_name(65_280), "TYPE65280");
+        assert_eq!(class_name(3), "CH");
+        assert_eq!(class_name(42), "CLASS42");
+    }
+}
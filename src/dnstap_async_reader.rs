@@ -0,0 +1,301 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::anonymizer::Anonymization;
+use crate::dns_message::*;
+use crate::dnstap_reader::Segment;
+use crate::frame_stream::*;
+use crate::transform::{EdnsRedaction, PacketMinimization, Transforms};
+
+const READ_SIZE: usize = 65_536;
+
+/// `AsyncDNSTapReader` is the asynchronous counterpart of `DNSTapReader`: a `Stream` of
+/// DNS messages read from a unidirectional Frame Streams source.
+///
+/// Partial reads are buffered until a complete frame is available, so the reader never
+/// blocks the runtime. The stream ends after an I/O or framing error. Messages that cannot
+/// be decoded are returned as errors, and the following messages are still read.
+///
+/// # Example
+/// ```no_run
+/// use dnstap::AsyncDNSTapReader;
+/// use futures::StreamExt;
+///
+/// # async fn example() -> std::io::Result<()> {
+/// let file = tokio::fs::File::open("/tmp/dnstap.log").await?;
+/// let mut reader = AsyncDNSTapReader::new(file);
+/// while let Some(dns_message) = reader.next().await {
+///     println!("{:?}", dns_message?.message_type);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncDNSTapReader<R: AsyncRead + Unpin> {
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+    end: usize,
+    eof: bool,
+    failed: bool,
+    segment: Segment,
+    transforms: Transforms,
+}
+
+impl<R: AsyncRead + Unpin> AsyncDNSTapReader<R> {
+    /// Creates an `AsyncDNSTapReader` object reading from `reader`.
+    pub fn new(reader: R) -> AsyncDNSTapReader<R> {
+        AsyncDNSTapReader {
+            reader,
+            buf: Vec::new(),
+            pos: 0,
+            end: 0,
+            eof: false,
+            failed: false,
+            segment: Segment::default(),
+            transforms: Transforms::default(),
+        }
+    }
+
+    /// Anonymizes the query and response addresses of the messages read.
+    pub fn anonymization(mut self, anonymization: Anonymization) -> Self {
+        self.transforms.set_anonymization(&anonymization);
+        self
+    }
+
+    /// Redacts EDNS options in the packets of the messages read.
+    pub fn edns_redaction(mut self, edns_redaction: EdnsRedaction) -> Self {
+        self.transforms.set_edns_redaction(edns_redaction);
+        self
+    }
+
+    /// Minimizes the packets of the messages read.
+    pub fn packet_minimization(mut self, packet_minimization: PacketMinimization) -> Self {
+        self.transforms.set_packet_minimization(packet_minimization);
+        self
+    }
+
+    /// Returns the underlying reader. Buffered data that hasn't been decoded yet is lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn poll_frame(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Vec<u8>>>> {
+        loop {
+            if let Some((frame, len)) = Frame::decode(&self.buf[self.pos..self.end])? {
+                self.pos += len;
+                match self.segment.process(frame)? {
+                    Some(payload) => return Poll::Ready(Ok(Some(payload))),
+                    None => continue,
+                }
+            }
+            if self.eof {
+                if self.pos < self.end {
+                    self.pos = self.end;
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+                return Poll::Ready(Ok(None));
+            }
+            // Move the partial frame to the start of the buffer, and only grow the buffer
+            // if that frame fills it
+            self.buf.copy_within(self.pos..self.end, 0);
+            self.end -= self.pos;
+            self.pos = 0;
+            if self.buf.len() - self.end < READ_SIZE / 2 {
+                let len = self.buf.len() + READ_SIZE;
+                self.buf.resize(len, 0);
+            }
+            let mut read_buf = ReadBuf::new(&mut self.buf[self.end..]);
+            let res = Pin::new(&mut self.reader).poll_read(cx, &mut read_buf);
+            let read_len = read_buf.filled().len();
+            self.end += read_len;
+            match res {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Ready(Ok(())) => self.eof = read_len == 0,
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for AsyncDNSTapReader<R> {
+    type Item = io::Result<DNSMessage>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.failed {
+            return Poll::Ready(None);
+        }
+        match this.poll_frame(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => {
+                this.failed = true;
+                Poll::Ready(Some(Err(e)))
+            }
+            Poll::Ready(Ok(None)) => Poll::Ready(None),
+            Poll::Ready(Ok(Some(payload))) => {
+                let mut dns_message = match DNSMessage::from_bytes(&payload) {
+                    Ok(dns_message) => dns_message,
+                    Err(e) => return Poll::Ready(Some(Err(invalid_data(e)))),
+                };
+                this.transforms.apply(&mut dns_message);
+                Poll::Ready(Some(Ok(dns_message)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::context::CONTENT_TYPE;
+    use crate::MessageType;
+
+    /// A reader returning at most `chunk_size` bytes per read, and every other read as
+    /// pending.
+    struct ChunkedReader {
+        data: Vec<u8>,
+        pos: usize,
+        chunk_size: usize,
+        pending: bool,
+    }
+
+    impl AsyncRead for ChunkedReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let len = self
+                .chunk_size
+                .min(buf.remaining())
+                .min(self.data.len() - self.pos);
+            buf.put_slice(&self.data[self.pos..self.pos + len]);
+            self.pos += len;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn chunked(data: Vec<u8>, chunk_size: usize) -> AsyncDNSTapReader<ChunkedReader> {
+        AsyncDNSTapReader::new(ChunkedReader {
+            data,
+            pos: 0,
+            chunk_size,
+            pending: false,
+        })
+    }
+
+    fn message(id: u8) -> DNSMessage {
+        let mut dns_message = DNSMessage::new(Some(vec![id]), None, MessageType::CLIENT_QUERY);
+        dns_message.query_packet = Some(vec![id; 12]);
+        dns_message
+    }
+
+    fn stream(messages: &[DNSMessage]) -> Vec<u8> {
+        let mut writer = FrameWriter::new(vec![], 4096, CONTENT_TYPE);
+        for dns_message in messages {
+            let len = dns_message.encoded_len().unwrap();
+            writer.write_frame(len, |w| dns_message.encode(w)).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn frames_split_across_reads() {
+        let messages: Vec<_> = (1..=5).map(message).collect();
+        for chunk_size in [1, 3, 7, 4096] {
+            let read: Vec<_> = chunked(stream(&messages), chunk_size)
+                .map(Result::unwrap)
+                .collect()
+                .await;
+            assert_eq!(read, messages);
+        }
+    }
+
+    #[tokio::test]
+    async fn partial_frame_at_eof() {
+        let mut data = stream(&[message(1), message(2)]);
+        // Drop the STOP frame and the last byte of the second message
+        data.truncate(data.len() - 13);
+        let mut reader = chunked(data, 5);
+        assert_eq!(reader.next().await.unwrap().unwrap(), message(1));
+        let err = reader.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(reader.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn unterminated_segment_at_eof() {
+        let mut data = stream(&[message(1)]);
+        data.truncate(data.len() - 12);
+        let read: Vec<_> = chunked(data, 2).map(Result::unwrap).collect().await;
+        assert_eq!(read, vec![message(1)]);
+    }
+
+    #[tokio::test]
+    async fn invalid_frames_are_rejected() {
+        let mut data = stream(&[]);
+        data.extend_from_slice(&3u32.to_be_bytes());
+        data.extend_from_slice(b"abc");
+        let mut reader = chunked(data, 1);
+        let err = reader.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(reader.next().await.is_none());
+
+        // A control frame too short for its type
+        let mut data = stream(&[message(1)]);
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 2, 0, 0]);
+        let mut reader = chunked(data, 3);
+        assert_eq!(reader.next().await.unwrap().unwrap(), message(1));
+        let err = reader.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(reader.next().await.is_none());
+
+        let mut writer = FrameWriter::new(vec![], 64, "protobuf:other");
+        writer.write_frame(1, |w| w.write_all(b"x")).unwrap();
+        let mut reader = chunked(writer.finish().unwrap(), 1);
+        let err = reader.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(reader.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn invalid_messages_are_skipped() {
+        let mut writer = FrameWriter::new(vec![], 4096, CONTENT_TYPE);
+        writer
+            .write_frame(2, |w| w.write_all(&[0xff, 0xff]))
+            .unwrap();
+        let dns_message = message(2);
+        let len = dns_message.encoded_len().unwrap();
+        writer.write_frame(len, |w| dns_message.encode(w)).unwrap();
+        let mut reader = chunked(writer.finish().unwrap(), 4);
+        let err = reader.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(reader.next().await.unwrap().unwrap(), message(2));
+        assert!(reader.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn large_frames() {
+        let mut dns_message = message(1);
+        dns_message.query_packet = Some(vec![0x5a; 200_000]);
+        let messages = vec![dns_message, message(2), message(3)];
+        let read: Vec<_> = chunked(stream(&messages), 50_000)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(read, messages);
+    }
+}
@@ -1,24 +1,74 @@
+use std::fmt;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, Read};
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
-use std::usize;
+use std::sync::Arc;
+use std::time;
 
+use crate::anonymizer::Anonymization;
+use crate::dns_message::{DNSMessage, Policy};
+use crate::dnstap_collector::DNSTapPendingCollector;
 use crate::dnstap_writer::DNSTapPendingWriter;
+use crate::pcap_reader::PcapReader;
+use crate::timestamp::{Clock, SystemClock};
+use crate::transform::{EdnsRedaction, PacketMinimization};
+use crate::{HttpProtocol, MessageType, SocketFamily, SocketProtocol};
 
 const DEFAULT_BACKLOG: usize = 4096;
+const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+const DEFAULT_DNS_PORT: u16 = 53;
 
 /// Builds a `DNSTapPendingWriter` object, to eventually start a
 /// `DNSTapWriter` service.
-#[derive(Clone, Hash)]
+#[derive(Clone)]
 pub struct DNSTapBuilder {
     pub backlog: usize,
     pub unix_socket_path: Option<PathBuf>,
+    pub tcp_socket_addr: Option<SocketAddr>,
+    pub extra: Option<Vec<u8>>,
+    pub anonymization: Option<Anonymization>,
+    pub edns_redaction: Option<EdnsRedaction>,
+    pub packet_minimization: Option<PacketMinimization>,
+    pub strict: bool,
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Hash for DNSTapBuilder {
+    /// Hashes the settings, except the clock.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.backlog.hash(state);
+        self.unix_socket_path.hash(state);
+        self.tcp_socket_addr.hash(state);
+        self.extra.hash(state);
+        self.anonymization.hash(state);
+        self.edns_redaction.hash(state);
+        self.packet_minimization.hash(state);
+        self.strict.hash(state);
+    }
+}
+
+impl Default for DNSTapBuilder {
+    fn default() -> DNSTapBuilder {
+        DNSTapBuilder::default()
+    }
 }
 
 impl DNSTapBuilder {
     /// Returns a `DNSTapBuilder` boilerplate
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> DNSTapBuilder {
         DNSTapBuilder {
             backlog: DEFAULT_BACKLOG,
             unix_socket_path: None,
+            tcp_socket_addr: None,
+            extra: None,
+            anonymization: None,
+            edns_redaction: None,
+            packet_minimization: None,
+            strict: false,
+            clock: Arc::new(SystemClock),
         }
     }
 
@@ -37,9 +87,526 @@ impl DNSTapBuilder {
         self
     }
 
+    /// Address of a TCP collector to send dnstap data to, as an alternative to a UNIX socket.
+    pub fn tcp_socket_addr(mut self, addr: SocketAddr) -> Self {
+        self.tcp_socket_addr = Some(addr);
+        self
+    }
+
+    /// Extra data attached to every message that doesn't already have some.
+    pub fn extra(mut self, extra: &[u8]) -> Self {
+        self.extra = Some(extra.to_vec());
+        self
+    }
+
+    /// Anonymizes the query and response addresses before messages are sent.
+    pub fn anonymization(mut self, anonymization: Anonymization) -> Self {
+        self.anonymization = Some(anonymization);
+        self
+    }
+
+    /// Redacts EDNS options in the query and response packets before messages are sent.
+    pub fn edns_redaction(mut self, edns_redaction: EdnsRedaction) -> Self {
+        self.edns_redaction = Some(edns_redaction);
+        self
+    }
+
+    /// Minimizes the query and response packets before messages are sent.
+    pub fn packet_minimization(mut self, packet_minimization: PacketMinimization) -> Self {
+        self.packet_minimization = Some(packet_minimization);
+        self
+    }
+
+    /// Rejects messages that don't pass `DNSMessage::validate()` in `Sender::send()`.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Clock used to timestamp queries sent without a query time, and responses sent
+    /// without a response time.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     /// Creates a DNSTapPendingWriter object. The communication channel is established at this
     /// point, and the `sender()` function can be used in order to get `Sender` objects.
     pub fn listen(self) -> Result<DNSTapPendingWriter, &'static str> {
         DNSTapPendingWriter::listen(self)
     }
 }
+
+/// Builds a `DNSTapPendingCollector` object, to eventually start a
+/// `DNSTapCollector` service.
+///
+/// A collector can listen on a UNIX socket, a TCP socket and a TLS socket at the same time.
+#[derive(Clone)]
+pub struct DNSTapCollectorBuilder {
+    pub unix_socket_path: Option<PathBuf>,
+    pub tcp_socket_addr: Option<SocketAddr>,
+    #[cfg(feature = "tls")]
+    pub tls_socket_addr: Option<SocketAddr>,
+    #[cfg(feature = "tls")]
+    pub tls_config: Option<Arc<rustls::ServerConfig>>,
+    pub max_connections: usize,
+    pub idle_timeout: Option<time::Duration>,
+    pub anonymization: Option<Anonymization>,
+    pub edns_redaction: Option<EdnsRedaction>,
+    pub packet_minimization: Option<PacketMinimization>,
+}
+
+impl Default for DNSTapCollectorBuilder {
+    /// Returns a `DNSTapCollectorBuilder` boilerplate
+    fn default() -> DNSTapCollectorBuilder {
+        DNSTapCollectorBuilder {
+            unix_socket_path: None,
+            tcp_socket_addr: None,
+            #[cfg(feature = "tls")]
+            tls_socket_addr: None,
+            #[cfg(feature = "tls")]
+            tls_config: None,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            idle_timeout: None,
+            anonymization: None,
+            edns_redaction: None,
+            packet_minimization: None,
+        }
+    }
+}
+
+impl DNSTapCollectorBuilder {
+    /// Path to the UNIX socket to receive dnstap data on.
+    pub fn unix_socket_path<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.unix_socket_path = Some(PathBuf::from(path.as_ref()));
+        self
+    }
+
+    /// Address to receive dnstap data on over TCP.
+    pub fn tcp_socket_addr(mut self, addr: SocketAddr) -> Self {
+        self.tcp_socket_addr = Some(addr);
+        self
+    }
+
+    /// Address to receive dnstap data on over TLS. Requires `tls_config()`.
+    #[cfg(feature = "tls")]
+    pub fn tls_socket_addr(mut self, addr: SocketAddr) -> Self {
+        self.tls_socket_addr = Some(addr);
+        self
+    }
+
+    /// TLS server configuration. Client certificates are verified if the configuration
+    /// includes a client certificate verifier.
+    #[cfg(feature = "tls")]
+    pub fn tls_config(mut self, tls_config: Arc<rustls::ServerConfig>) -> Self {
+        self.tls_config = Some(tls_config);
+        self
+    }
+
+    /// Maximum number of concurrent connections. Additional connections are closed
+    /// immediately.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Closes connections that didn't send any data for `idle_timeout`.
+    pub fn idle_timeout(mut self, idle_timeout: time::Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Anonymizes the query and response addresses before messages are passed to the
+    /// handler.
+    pub fn anonymization(mut self, anonymization: Anonymization) -> Self {
+        self.anonymization = Some(anonymization);
+        self
+    }
+
+    /// Redacts EDNS options in the query and response packets before messages are passed to
+    /// the handler.
+    pub fn edns_redaction(mut self, edns_redaction: EdnsRedaction) -> Self {
+        self.edns_redaction = Some(edns_redaction);
+        self
+    }
+
+    /// Minimizes the query and response packets before messages are passed to the handler.
+    pub fn packet_minimization(mut self, packet_minimization: PacketMinimization) -> Self {
+        self.packet_minimization = Some(packet_minimization);
+        self
+    }
+
+    /// Creates a `DNSTapPendingCollector` object. The sockets are bound at this point, so
+    /// that producers can connect before the collector is started.
+    pub fn listen(self) -> io::Result<DNSTapPendingCollector> {
+        DNSTapPendingCollector::listen(self)
+    }
+}
+
+/// Builds a `PcapReader` object, to import DNS traffic from a pcap or pcapng capture.
+///
+/// Packets are classified as queries or responses relative to the server addresses. If no
+/// server address is set, any host using the server port is considered to be a server.
+#[derive(Clone, Debug)]
+pub struct PcapReaderBuilder {
+    pub server_addresses: Vec<IpAddr>,
+    pub server_port: u16,
+    pub query_type: MessageType,
+    pub response_type: MessageType,
+    pub identity: Option<Vec<u8>>,
+    pub version: Option<Vec<u8>>,
+    pub extra: Option<Vec<u8>>,
+    pub anonymization: Option<Anonymization>,
+    pub edns_redaction: Option<EdnsRedaction>,
+    pub packet_minimization: Option<PacketMinimization>,
+}
+
+impl Default for PcapReaderBuilder {
+    /// Returns a `PcapReaderBuilder` boilerplate
+    fn default() -> PcapReaderBuilder {
+        PcapReaderBuilder {
+            server_addresses: vec![],
+            server_port: DEFAULT_DNS_PORT,
+            query_type: MessageType::CLIENT_QUERY,
+            response_type: MessageType::CLIENT_RESPONSE,
+            identity: None,
+            version: None,
+            extra: None,
+            anonymization: None,
+            edns_redaction: None,
+            packet_minimization: None,
+        }
+    }
+}
+
+impl PcapReaderBuilder {
+    /// Adds an address of the DNS server whose traffic was captured.
+    pub fn server_addr(mut self, addr: IpAddr) -> Self {
+        self.server_addresses.push(addr);
+        self
+    }
+
+    /// Port the DNS server listens on (default: 53).
+    pub fn server_port(mut self, port: u16) -> Self {
+        self.server_port = port;
+        self
+    }
+
+    /// Message types given to queries and responses, for example `AUTH_QUERY` and
+    /// `AUTH_RESPONSE` for a capture taken on an authoritative server.
+    pub fn message_types(mut self, query_type: MessageType, response_type: MessageType) -> Self {
+        self.query_type = query_type;
+        self.response_type = response_type;
+        self
+    }
+
+    /// Identity of the server, set on every message.
+    pub fn identity(mut self, identity: &[u8]) -> Self {
+        self.identity = Some(identity.to_vec());
+        self
+    }
+
+    /// Version of the server, set on every message.
+    pub fn version(mut self, version: &[u8]) -> Self {
+        self.version = Some(version.to_vec());
+        self
+    }
+
+    /// Extra data, set on every message.
+    pub fn extra(mut self, extra: &[u8]) -> Self {
+        self.extra = Some(extra.to_vec());
+        self
+    }
+
+    /// Anonymizes the client and server addresses of every message.
+    pub fn anonymization(mut self, anonymization: Anonymization) -> Self {
+        self.anonymization = Some(anonymization);
+        self
+    }
+
+    /// Redacts EDNS options in the packets of every message.
+    pub fn edns_redaction(mut self, edns_redaction: EdnsRedaction) -> Self {
+        self.edns_redaction = Some(edns_redaction);
+        self
+    }
+
+    /// Minimizes the packets of every message.
+    pub fn packet_minimization(mut self, packet_minimization: PacketMinimization) -> Self {
+        self.packet_minimization = Some(packet_minimization);
+        self
+    }
+
+    /// Opens a pcap or pcapng file.
+    pub fn open<P: AsRef<Path>>(self, path: P) -> io::Result<PcapReader<BufReader<File>>> {
+        PcapReader::open(path, self)
+    }
+
+    /// Creates a `PcapReader` object reading a capture from `reader`.
+    pub fn reader<R: Read>(self, reader: R) -> io::Result<PcapReader<R>> {
+        PcapReader::new(reader, self)
+    }
+}
+
+/// Builds a `DNSMessage`, see `DNSMessage::builder()`.
+///
+/// The socket family is inferred from the addresses, and the message is validated when it
+/// is built.
+///
+/// # Example
+/// ```
+/// use dnstap::{DNSMessage, MessageType, SocketProtocol};
+///
+/// let dns_message = DNSMessage::builder(MessageType::CLIENT_QUERY)
+///     .query_addr("192.0.2.1:53000".parse().unwrap())
+///     .response_addr("192.0.2.53:53".parse().unwrap())
+///     .protocol(SocketProtocol::UDP)
+///     .query_now()
+///     .query_packet(&[0x12, 0x34, 0x01, 0x00, 0, 0, 0, 0, 0, 0, 0, 0])
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct DNSMessageBuilder {
+    dns_message: DNSMessage,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for DNSMessageBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DNSMessageBuilder")
+            .field("dns_message", &self.dns_message)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DNSMessageBuilder {
+    /// Creates a builder for a message of the given type.
+    pub fn new(message_type: MessageType) -> Self {
+        DNSMessageBuilder {
+            dns_message: DNSMessage::new(None, None, message_type),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Clock used by `query_now()` and `response_now()`. The system clock is used by
+    /// default.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Identity of the server.
+    pub fn identity(mut self, identity: &[u8]) -> Self {
+        self.dns_message.identity = Some(identity.to_vec());
+        self
+    }
+
+    /// Version of the server.
+    pub fn version(mut self, version: &[u8]) -> Self {
+        self.dns_message.version = Some(version.to_vec());
+        self
+    }
+
+    /// Extra data attached to the message.
+    pub fn extra(mut self, extra: &[u8]) -> Self {
+        self.dns_message.extra = Some(extra.to_vec());
+        self
+    }
+
+    /// Address and port of the message initiator.
+    pub fn query_addr(mut self, addr: SocketAddr) -> Self {
+        self.dns_message.query_address = Some(addr.ip());
+        self.dns_message.query_port = Some(addr.port());
+        self
+    }
+
+    /// Address and port of the message responder.
+    pub fn response_addr(mut self, addr: SocketAddr) -> Self {
+        self.dns_message.response_address = Some(addr.ip());
+        self.dns_message.response_port = Some(addr.port());
+        self
+    }
+
+    /// Socket family, for messages without addresses.
+    pub fn family(mut self, socket_family: SocketFamily) -> Self {
+        self.dns_message.socket_family = Some(socket_family);
+        self
+    }
+
+    /// Transport protocol.
+    pub fn protocol(mut self, socket_protocol: SocketProtocol) -> Self {
+        self.dns_message.socket_protocol = Some(socket_protocol);
+        self
+    }
+
+    /// HTTP version of a DNS-over-HTTPS message.
+    pub fn http_protocol(mut self, http_protocol: HttpProtocol) -> Self {
+        self.dns_message.http_protocol = Some(http_protocol);
+        self
+    }
+
+    /// Time the query was sent or received, since the UNIX epoch.
+    pub fn query_time(mut self, query_time: time::Duration) -> Self {
+        self.dns_message.query_time = Some(query_time);
+        self
+    }
+
+    /// Time the query was sent or received.
+    pub fn query_at(mut self, query_time: time::SystemTime) -> Self {
+        self.dns_message.set_query_time(query_time);
+        self
+    }
+
+    /// Sets the query time to the current time of the clock.
+    pub fn query_now(self) -> Self {
+        let now = self.clock.now();
+        self.query_at(now)
+    }
+
+    /// Query packet.
+    pub fn query_packet(mut self, query_packet: &[u8]) -> Self {
+        self.dns_message.query_packet = Some(query_packet.to_vec());
+        self
+    }
+
+    /// Time the response was sent or received, since the UNIX epoch.
+    pub fn response_time(mut self, response_time: time::Duration) -> Self {
+        self.dns_message.response_time = Some(response_time);
+        self
+    }
+
+    /// Time the response was sent or received.
+    pub fn response_at(mut self, response_time: time::SystemTime) -> Self {
+        self.dns_message.set_response_time(response_time);
+        self
+    }
+
+    /// Sets the response time to the current time of the clock.
+    pub fn response_now(self) -> Self {
+        let now = self.clock.now();
+        self.response_at(now)
+    }
+
+    /// Response packet.
+    pub fn response_packet(mut self, response_packet: &[u8]) -> Self {
+        self.dns_message.response_packet = Some(response_packet.to_vec());
+        self
+    }
+
+    /// Zone the query was sent for, in presentation format.
+    pub fn bailiwick(mut self, bailiwick: &str) -> Self {
+        self.dns_message.bailiwick = Some(bailiwick.to_owned());
+        self
+    }
+
+    /// Operator policy applied to the message.
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.dns_message.policy = Some(policy);
+        self
+    }
+
+    /// Returns the message, or an error if it doesn't pass `DNSMessage::validate()`.
+    pub fn build(mut self) -> Result<DNSMessage, &'static str> {
+        let dns_message = &mut self.dns_message;
+        if dns_message.socket_family.is_none() {
+            dns_message.socket_family =
+                match dns_message.query_address.or(dns_message.response_address) {
+                    Some(IpAddr::V4(_)) => Some(SocketFamily::INET),
+                    Some(IpAddr::V6(_)) => Some(SocketFamily::INET6),
+                    None => None,
+                };
+        }
+        self.dns_message.validate()?;
+        Ok(self.dns_message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+
+    use super::*;
+    use crate::test_support::*;
+
+    fn hash(builder: &DNSTapBuilder) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        builder.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn writer_builder_hash() {
+        let builder = DNSTapBuilder::default();
+        assert_eq!(
+            hash(&builder),
+            hash(&builder.clone().clock(Arc::new(SystemClock)))
+        );
+        assert_ne!(hash(&builder), hash(&builder.clone().strict(true)));
+    }
+
+    #[test]
+    fn message_builder() {
+        let query_packet = dns_query(0x1234, "www.example.com", 1);
+        let dns_message = DNSMessage::builder(MessageType::AUTH_QUERY)
+            .identity(b"ns1.example")
+            .version(b"knot 3.3")
+            .extra(b"pop=ams1;cluster=b")
+            .query_addr("192.0.2.10:53001".parse().unwrap())
+            .protocol(SocketProtocol::UDP)
+            .query_time(time::Duration::new(1_700_000_000, 123_456_789))
+            .query_packet(&query_packet)
+            .build()
+            .unwrap();
+        assert_eq!(dns_message, reference_messages()[0]);
+
+        let dns_message = DNSMessage::builder(MessageType::STUB_QUERY)
+            .response_addr("[2001:db8::53]:853".parse().unwrap())
+            .protocol(SocketProtocol::DOT)
+            .query_now()
+            .query_packet(&query_packet)
+            .build()
+            .unwrap();
+        assert_eq!(dns_message.socket_family, Some(SocketFamily::INET6));
+        assert_eq!(dns_message.response_port, Some(853));
+        assert!(dns_message.query_time.unwrap() > time::Duration::new(1_700_000_000, 0));
+
+        let result = DNSMessage::builder(MessageType::STUB_QUERY)
+            .query_addr("192.0.2.1:53000".parse().unwrap())
+            .response_addr("[2001:db8::53]:53".parse().unwrap())
+            .query_packet(&query_packet)
+            .build();
+        assert_eq!(
+            result,
+            Err("Response address does not match the socket family")
+        );
+        let result = DNSMessage::builder(MessageType::STUB_RESPONSE).build();
+        assert_eq!(result, Err("Response message without a response packet"));
+    }
+
+    struct FixedClock(time::SystemTime);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> time::SystemTime {
+            self.0
+        }
+    }
+
+    #[test]
+    fn message_builder_clock() {
+        let now = time::UNIX_EPOCH + time::Duration::new(1_800_000_000, 42);
+        let dns_message = DNSMessage::builder(MessageType::STUB_RESPONSE)
+            .clock(Arc::new(FixedClock(now)))
+            .response_addr("[2001:db8::53]:853".parse().unwrap())
+            .protocol(SocketProtocol::DOT)
+            .query_now()
+            .response_now()
+            .response_packet(&dns_response(1, "example.com", 1, &[192, 0, 2, 1]))
+            .build()
+            .unwrap();
+        assert_eq!(dns_message.query_system_time(), Some(now));
+        assert_eq!(dns_message.response_system_time(), Some(now));
+    }
+}
@@ -0,0 +1,553 @@
+use crate::context::CONTENT_TYPE;
+use crate::dns_message::*;
+use crate::dnstap_builder::*;
+use crate::frame_stream::*;
+use crate::transform::Transforms;
+use mio::unix::EventedFd;
+use mio::*;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time;
+
+const SHUTDOWN_TOK: Token = Token(0);
+const UNIX_LISTENER_TOK: Token = Token(1);
+const TCP_LISTENER_TOK: Token = Token(2);
+#[cfg(feature = "tls")]
+const TLS_LISTENER_TOK: Token = Token(3);
+
+type Handler = dyn Fn(&ConnectionInfo, DNSMessage) + Send + Sync;
+type ErrorHandler = dyn Fn(&ConnectionInfo, io::Error) + Send + Sync;
+
+/// Address of a dnstap producer connected to a collector.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PeerAddress {
+    /// A UNIX socket peer. Producers usually connect from unnamed sockets.
+    Unix(Option<PathBuf>),
+    /// A TCP peer, possibly using TLS.
+    Tcp(SocketAddr),
+}
+
+/// Metadata about the connection a DNS message was received from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ConnectionInfo {
+    /// Unique identifier of the connection, for the lifetime of the collector.
+    pub id: u64,
+    /// Address of the producer.
+    pub peer_address: PeerAddress,
+    /// `true` if the producer performed a bidirectional (READY/ACCEPT) handshake.
+    pub bidirectional: bool,
+    /// `true` if the connection is using TLS.
+    pub tls: bool,
+    /// DER-encoded certificate presented by a TLS client, if any.
+    pub peer_certificate: Option<Vec<u8>>,
+}
+
+/// A socket accepted by the collector, kept around to close it on shutdown.
+enum Socket {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Socket {
+    fn shutdown(&self, how: Shutdown) {
+        let _ = match self {
+            Socket::Unix(unix_stream) => unix_stream.shutdown(how),
+            Socket::Tcp(tcp_stream) => tcp_stream.shutdown(how),
+        };
+    }
+}
+
+#[derive(Default)]
+struct Connections {
+    next_id: u64,
+    active: HashMap<u64, (Socket, Option<thread::JoinHandle<()>>)>,
+}
+
+struct Listeners {
+    unix_listener: Option<(UnixListener, PathBuf)>,
+    tcp_listener: Option<TcpListener>,
+    #[cfg(feature = "tls")]
+    tls_listener: Option<(TcpListener, Arc<rustls::ServerConfig>)>,
+}
+
+pub struct DNSTapPendingCollector {
+    listeners: Listeners,
+    max_connections: usize,
+    idle_timeout: Option<time::Duration>,
+    transforms: Transforms,
+    error_handler: Option<Arc<ErrorHandler>>,
+}
+
+impl DNSTapPendingCollector {
+    /// Creates a `DNSTapPendingCollector` object, bound to the configured sockets. A stale
+    /// UNIX socket left at the same path is removed first.
+    pub fn listen(builder: DNSTapCollectorBuilder) -> io::Result<DNSTapPendingCollector> {
+        let unix_listener = match builder.unix_socket_path {
+            Some(unix_socket_path) => {
+                if let Ok(metadata) = fs::symlink_metadata(&unix_socket_path) {
+                    if metadata.file_type().is_socket() {
+                        fs::remove_file(&unix_socket_path)?;
+                    }
+                }
+                let unix_listener = UnixListener::bind(&unix_socket_path)?;
+                unix_listener.set_nonblocking(true)?;
+                Some((unix_listener, unix_socket_path))
+            }
+            None => None,
+        };
+        let tcp_listener = match builder.tcp_socket_addr {
+            Some(tcp_socket_addr) => Some(bind_tcp(tcp_socket_addr)?),
+            None => None,
+        };
+        #[cfg(feature = "tls")]
+        let tls_listener = match (builder.tls_socket_addr, builder.tls_config) {
+            (Some(tls_socket_addr), Some(tls_config)) => {
+                Some((bind_tcp(tls_socket_addr)?, tls_config))
+            }
+            (Some(_), None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "TLS configuration not set",
+                ))
+            }
+            (None, _) => None,
+        };
+        let listeners = Listeners {
+            unix_listener,
+            tcp_listener,
+            #[cfg(feature = "tls")]
+            tls_listener,
+        };
+        if listeners.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No socket to listen to",
+            ));
+        }
+        Ok(DNSTapPendingCollector {
+            listeners,
+            max_connections: builder.max_connections,
+            idle_timeout: builder.idle_timeout,
+            transforms: Transforms::new(
+                builder.anonymization.as_ref(),
+                builder.edns_redaction.clone(),
+                builder.packet_minimization,
+            ),
+            error_handler: None,
+        })
+    }
+
+    /// Returns the local address of the TCP listener.
+    pub fn tcp_local_addr(&self) -> Option<SocketAddr> {
+        self.listeners
+            .tcp_listener
+            .as_ref()
+            .and_then(|tcp_listener| tcp_listener.local_addr().ok())
+    }
+
+    /// Returns the local address of the TLS listener.
+    #[cfg(feature = "tls")]
+    pub fn tls_local_addr(&self) -> Option<SocketAddr> {
+        self.listeners
+            .tls_listener
+            .as_ref()
+            .and_then(|(tls_listener, _)| tls_listener.local_addr().ok())
+    }
+
+    /// Calls `error_handler` for every payload that cannot be decoded, and for every
+    /// connection closed because of an error, such as a protocol violation or an idle
+    /// timeout.
+    pub fn error_handler<F>(mut self, error_handler: F) -> Self
+    where
+        F: Fn(&ConnectionInfo, io::Error) + Send + Sync + 'static,
+    {
+        self.error_handler = Some(Arc::new(error_handler));
+        self
+    }
+
+    /// Spawns a new task accepting connections, and calls `handler` for every DNS message
+    /// received.
+    pub fn start<F>(self, handler: F) -> io::Result<DNSTapCollector>
+    where
+        F: Fn(&ConnectionInfo, DNSMessage) + Send + Sync + 'static,
+    {
+        DNSTapCollector::start(self, handler)
+    }
+}
+
+impl Listeners {
+    fn is_empty(&self) -> bool {
+        #[cfg(feature = "tls")]
+        {
+            if self.tls_listener.is_some() {
+                return false;
+            }
+        }
+        self.unix_listener.is_none() && self.tcp_listener.is_none()
+    }
+
+    fn register(&self, mio_poll: &Poll) -> io::Result<()> {
+        if let Some((ref unix_listener, _)) = self.unix_listener {
+            mio_poll.register(
+                &EventedFd(&unix_listener.as_raw_fd()),
+                UNIX_LISTENER_TOK,
+                Ready::readable(),
+                PollOpt::level(),
+            )?;
+        }
+        if let Some(ref tcp_listener) = self.tcp_listener {
+            mio_poll.register(
+                &EventedFd(&tcp_listener.as_raw_fd()),
+                TCP_LISTENER_TOK,
+                Ready::readable(),
+                PollOpt::level(),
+            )?;
+        }
+        #[cfg(feature = "tls")]
+        {
+            if let Some((ref tls_listener, _)) = self.tls_listener {
+                mio_poll.register(
+                    &EventedFd(&tls_listener.as_raw_fd()),
+                    TLS_LISTENER_TOK,
+                    Ready::readable(),
+                    PollOpt::level(),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `DNSTapCollector` is the receiving side of the dnstap protocol. It accepts connections
+/// from any number of concurrent producers on UNIX, TCP and TLS sockets, performs the
+/// unidirectional or bidirectional Frame Streams handshake, and passes decoded DNS messages,
+/// along with metadata about the connection they were received from, to a handler.
+///
+/// # Example
+/// ```no_run
+/// use dnstap::DNSTapCollectorBuilder;
+/// use std::sync::mpsc;
+/// use std::time::Duration;
+///
+/// let (tx, rx) = mpsc::channel();
+/// let dnstap_collector = DNSTapCollectorBuilder::default()
+///     .unix_socket_path("/tmp/dnstap.sock")
+///     .tcp_socket_addr("0.0.0.0:6000".parse().unwrap())
+///     .max_connections(500)
+///     .idle_timeout(Duration::from_secs(300))
+///     .listen().unwrap()
+///     .error_handler(|connection_info, e| {
+///         eprintln!("{}: {}", connection_info.id, e);
+///     })
+///     .start(move |connection_info, dns_message| {
+///         let _ = tx.send((connection_info.id, dns_message));
+///     }).unwrap();
+///
+/// for (connection_id, dns_message) in rx.iter().take(1000) {
+///     println!("{}: {:?}", connection_id, dns_message.message_type);
+/// }
+/// dnstap_collector.shutdown().unwrap();
+/// ```
+pub struct DNSTapCollector {
+    shutdown_readiness: SetReadiness,
+    tid: thread::JoinHandle<()>,
+}
+
+impl DNSTapCollector {
+    /// Spawns a new task accepting connections.
+    pub fn start<F>(
+        dnstap_pending_collector: DNSTapPendingCollector,
+        handler: F,
+    ) -> io::Result<DNSTapCollector>
+    where
+        F: Fn(&ConnectionInfo, DNSMessage) + Send + Sync + 'static,
+    {
+        let mio_poll = Poll::new()?;
+        let (shutdown_registration, shutdown_readiness) = Registration::new2();
+        mio_poll.register(
+            &shutdown_registration,
+            SHUTDOWN_TOK,
+            Ready::readable(),
+            PollOpt::edge(),
+        )?;
+        dnstap_pending_collector.listeners.register(&mio_poll)?;
+        let transforms = dnstap_pending_collector.transforms;
+        let handler: Arc<Handler> = if transforms.is_empty() {
+            Arc::new(handler)
+        } else {
+            Arc::new(move |connection_info: &ConnectionInfo, mut dns_message| {
+                transforms.apply(&mut dns_message);
+                handler(connection_info, dns_message)
+            })
+        };
+        let acceptor = Acceptor {
+            handler,
+            error_handler: dnstap_pending_collector.error_handler,
+            connections: Arc::new(Mutex::new(Connections::default())),
+            max_connections: dnstap_pending_collector.max_connections,
+            idle_timeout: dnstap_pending_collector.idle_timeout,
+        };
+        let listeners = dnstap_pending_collector.listeners;
+        let tid = thread::Builder::new()
+            .name("dnstap-collector".to_owned())
+            .spawn(move || {
+                let _shutdown_registration = shutdown_registration;
+                let mut events = Events::with_capacity(16);
+                'poll: while mio_poll.poll(&mut events, None).is_ok() {
+                    for event in events.iter() {
+                        match event.token() {
+                            SHUTDOWN_TOK => break 'poll,
+                            UNIX_LISTENER_TOK => {
+                                let (unix_listener, _) = listeners.unix_listener.as_ref().unwrap();
+                                if let Ok((unix_stream, _)) = unix_listener.accept() {
+                                    acceptor.accept_unix(unix_stream);
+                                }
+                            }
+                            TCP_LISTENER_TOK => {
+                                let tcp_listener = listeners.tcp_listener.as_ref().unwrap();
+                                if let Ok((tcp_stream, _)) = tcp_listener.accept() {
+                                    acceptor.accept_tcp(tcp_stream);
+                                }
+                            }
+                            #[cfg(feature = "tls")]
+                            TLS_LISTENER_TOK => {
+                                let (tls_listener, tls_config) =
+                                    listeners.tls_listener.as_ref().unwrap();
+                                if let Ok((tcp_stream, _)) = tls_listener.accept() {
+                                    acceptor.accept_tls(tcp_stream, tls_config.clone());
+                                }
+                            }
+                            _ => unreachable!(),
+                        }
+                    }
+                }
+                if let Some((_, ref unix_socket_path)) = listeners.unix_listener {
+                    let _ = fs::remove_file(unix_socket_path);
+                }
+                acceptor.close_all();
+            })?;
+        Ok(DNSTapCollector {
+            shutdown_readiness,
+            tid,
+        })
+    }
+
+    /// Stops accepting connections, stops reading from the active ones, and waits for all
+    /// the messages already received to be passed to the handler.
+    pub fn shutdown(self) -> Result<(), Box<dyn Any + Send + 'static>> {
+        let _ = self.shutdown_readiness.set_readiness(Ready::readable());
+        self.tid.join()
+    }
+
+    pub fn join(self) -> Result<(), Box<dyn Any + Send + 'static>> {
+        self.tid.join()
+    }
+}
+
+struct Acceptor {
+    handler: Arc<Handler>,
+    error_handler: Option<Arc<ErrorHandler>>,
+    connections: Arc<Mutex<Connections>>,
+    max_connections: usize,
+    idle_timeout: Option<time::Duration>,
+}
+
+impl Acceptor {
+    fn accept_unix(&self, unix_stream: UnixStream) {
+        if unix_stream.set_nonblocking(false).is_err()
+            || unix_stream.set_read_timeout(self.idle_timeout).is_err()
+        {
+            return;
+        }
+        let peer_address = unix_stream
+            .peer_addr()
+            .ok()
+            .and_then(|addr| addr.as_pathname().map(PathBuf::from));
+        let socket = match unix_stream.try_clone() {
+            Ok(unix_stream) => Socket::Unix(unix_stream),
+            Err(_) => return,
+        };
+        self.spawn(
+            socket,
+            PeerAddress::Unix(peer_address),
+            move |connection_info, handlers| serve(unix_stream, connection_info, handlers),
+        );
+    }
+
+    fn accept_tcp(&self, tcp_stream: TcpStream) {
+        let (socket, peer_address) = match self.prepare_tcp(&tcp_stream) {
+            Some(x) => x,
+            None => return,
+        };
+        self.spawn(socket, peer_address, move |connection_info, handlers| {
+            serve(tcp_stream, connection_info, handlers)
+        });
+    }
+
+    #[cfg(feature = "tls")]
+    fn accept_tls(&self, tcp_stream: TcpStream, tls_config: Arc<rustls::ServerConfig>) {
+        let (socket, peer_address) = match self.prepare_tcp(&tcp_stream) {
+            Some(x) => x,
+            None => return,
+        };
+        self.spawn(socket, peer_address, move |connection_info, handlers| {
+            let mut tcp_stream = tcp_stream;
+            let mut tls_connection =
+                rustls::ServerConnection::new(tls_config).map_err(io::Error::other)?;
+            while tls_connection.is_handshaking() {
+                tls_connection.complete_io(&mut tcp_stream)?;
+            }
+            connection_info.tls = true;
+            connection_info.peer_certificate = tls_connection
+                .peer_certificates()
+                .and_then(|certificates| certificates.first())
+                .map(|certificate| certificate.to_vec());
+            let tls_stream = rustls::StreamOwned::new(tls_connection, tcp_stream);
+            serve(tls_stream, connection_info, handlers)
+        });
+    }
+
+    fn prepare_tcp(&self, tcp_stream: &TcpStream) -> Option<(Socket, PeerAddress)> {
+        tcp_stream.set_nonblocking(false).ok()?;
+        tcp_stream.set_read_timeout(self.idle_timeout).ok()?;
+        let peer_address = PeerAddress::Tcp(tcp_stream.peer_addr().ok()?);
+        let socket = Socket::Tcp(tcp_stream.try_clone().ok()?);
+        Some((socket, peer_address))
+    }
+
+    fn spawn<F>(&self, socket: Socket, peer_address: PeerAddress, serve_fn: F)
+    where
+        F: FnOnce(&mut ConnectionInfo, &Handlers) -> io::Result<()> + Send + 'static,
+    {
+        // The lock is held until the connection is registered, so that the connection
+        // task cannot try to unregister itself before that.
+        let mut connections = self.connections.lock().unwrap();
+        if connections.active.len() >= self.max_connections {
+            socket.shutdown(Shutdown::Both);
+            return;
+        }
+        let id = connections.next_id;
+        connections.next_id += 1;
+        let mut connection_info = ConnectionInfo {
+            id,
+            peer_address,
+            bidirectional: false,
+            tls: false,
+            peer_certificate: None,
+        };
+        let handlers = Handlers {
+            handler: self.handler.clone(),
+            error_handler: self.error_handler.clone(),
+        };
+        let connections_ref = self.connections.clone();
+        let tid = thread::Builder::new()
+            .name("dnstap-collector".to_owned())
+            .spawn(move || {
+                if let Err(e) = serve_fn(&mut connection_info, &handlers) {
+                    handlers.error(&connection_info, e);
+                }
+                connections_ref.lock().unwrap().active.remove(&id);
+            });
+        match tid {
+            Ok(tid) => {
+                connections.active.insert(id, (socket, Some(tid)));
+            }
+            Err(_) => socket.shutdown(Shutdown::Both),
+        }
+    }
+
+    fn close_all(&self) {
+        let active: Vec<_> = {
+            let mut connections = self.connections.lock().unwrap();
+            connections.active.drain().map(|(_, x)| x).collect()
+        };
+        // Shutting down the read side only makes the connection tasks read whatever was
+        // already received before they see the end of the stream.
+        for (socket, tid) in active {
+            socket.shutdown(Shutdown::Read);
+            if let Some(tid) = tid {
+                let _ = tid.join();
+            }
+        }
+    }
+}
+
+/// The handlers a connection task reports messages and errors to.
+struct Handlers {
+    handler: Arc<Handler>,
+    error_handler: Option<Arc<ErrorHandler>>,
+}
+
+impl Handlers {
+    fn error(&self, connection_info: &ConnectionInfo, e: io::Error) {
+        if let Some(ref error_handler) = self.error_handler {
+            error_handler(connection_info, e);
+        }
+    }
+}
+
+fn bind_tcp(addr: SocketAddr) -> io::Result<TcpListener> {
+    let tcp_listener = TcpListener::bind(addr)?;
+    tcp_listener.set_nonblocking(true)?;
+    Ok(tcp_listener)
+}
+
+/// Runs the receiving side of the Frame Streams protocol over a single connection.
+fn serve<S: Read + Write>(
+    stream: S,
+    connection_info: &mut ConnectionInfo,
+    handlers: &Handlers,
+) -> io::Result<()> {
+    let content_type = CONTENT_TYPE.as_bytes();
+    let mut reader = BufReader::new(stream);
+    let mut control = match Frame::read_from(&mut reader)? {
+        Some(Frame::Control(control)) => control,
+        Some(Frame::Data(_)) => return Err(invalid_data("Expected a control frame")),
+        None => return Ok(()),
+    };
+    if control.control_type == CONTROL_READY {
+        if !control.has_content_type(content_type) {
+            return Err(invalid_data("Unexpected content type"));
+        }
+        connection_info.bidirectional = true;
+        ControlFrame::new(CONTROL_ACCEPT, Some(content_type)).write_to(reader.get_mut())?;
+        control = match Frame::read_from(&mut reader)? {
+            Some(Frame::Control(control)) => control,
+            Some(Frame::Data(_)) => return Err(invalid_data("Expected a control frame")),
+            None => return Ok(()),
+        };
+    }
+    if control.control_type != CONTROL_START {
+        return Err(invalid_data("Unexpected control frame"));
+    }
+    // The content type was already negotiated in bidirectional mode
+    let content_type_required = !connection_info.bidirectional || !control.content_types.is_empty();
+    if content_type_required && !control.has_content_type(content_type) {
+        return Err(invalid_data("Unexpected content type"));
+    }
+    loop {
+        match Frame::read_from(&mut reader)? {
+            Some(Frame::Data(payload)) => match DNSMessage::from_bytes(&payload) {
+                Ok(dns_message) => (handlers.handler)(connection_info, dns_message),
+                Err(e) => handlers.error(connection_info, invalid_data(e)),
+            },
+            Some(Frame::Control(ref control)) if control.control_type == CONTROL_STOP => {
+                if connection_info.bidirectional {
+                    ControlFrame::new(CONTROL_FINISH, None).write_to(reader.get_mut())?;
+                }
+                return Ok(());
+            }
+            Some(Frame::Control(_)) => return Err(invalid_data("Unexpected control frame")),
+            None => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::io::{self, Write};
+use std::net::IpAddr;
+
+use protobuf::Enum;
+
+use crate::dns_message::{DNSMessage, Policy};
+use crate::dns_packet::{self, MAX_NAME_LEN};
+use crate::{SocketFamily, SocketProtocol};
+
+const WIRE_VARINT: u32 = 0;
+const WIRE_LEN: u32 = 2;
+const WIRE_FIXED32: u32 = 5;
+
+const DNSTAP_TYPE_MESSAGE: i32 = 1;
+
+/// Serializes a `DNSMessage` as a dnstap protobuf payload, without intermediate
+/// allocations.
+///
+/// The payload is identical to the one produced by `into_protobuf()`. Its length is known
+/// before anything is written, so that it can be written straight into a frame.
+pub(crate) struct Encoder<'a> {
+    dns_message: &'a DNSMessage,
+    socket_family: Option<SocketFamily>,
+    query_zone: [u8; MAX_NAME_LEN],
+    query_zone_len: Option<usize>,
+    policy_len: usize,
+    message_len: usize,
+    len: usize,
+}
+
+impl<'a> Encoder<'a> {
+    /// Checks that the message can be encoded, and computes the length of the payload.
+    pub fn new(dns_message: &'a DNSMessage) -> Result<Encoder<'a>, &'static str> {
+        let socket_family = dns_message.encoded_socket_family()?;
+        if dns_message.http_protocol.is_some()
+            && dns_message.socket_protocol != Some(SocketProtocol::DOH)
+        {
+            return Err("HTTP protocol set on a non-DoH message");
+        }
+        let mut query_zone = [0u8; MAX_NAME_LEN];
+        let query_zone_len = match dns_message.bailiwick {
+            Some(ref bailiwick) => Some(dns_packet::name_to_wire_buf(bailiwick, &mut query_zone)?),
+            None => None,
+        };
+        let mut encoder = Encoder {
+            dns_message,
+            socket_family,
+            query_zone,
+            query_zone_len,
+            policy_len: 0,
+            message_len: 0,
+            len: 0,
+        };
+        if let Some(ref policy) = dns_message.policy {
+            encoder.policy_len = counted(|counter| write_policy(counter, policy));
+        }
+        encoder.message_len = counted(|counter| encoder.write_message(counter));
+        encoder.len = counted(|counter| encoder.write_header(counter)) + encoder.message_len;
+        Ok(encoder)
+    }
+
+    /// Length of the payload.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Writes the payload.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_header(writer)?;
+        self.write_message(writer)
+    }
+
+    /// Writes the `Dnstap` fields, up to the length of the nested `Message`.
+    fn write_header<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let dns_message = self.dns_message;
+        if let Some(ref identity) = dns_message.identity {
+            write_bytes(writer, 1, identity)?;
+        }
+        if let Some(ref version) = dns_message.version {
+            write_bytes(writer, 2, version)?;
+        }
+        if let Some(ref extra) = dns_message.extra {
+            write_bytes(writer, 3, extra)?;
+        }
+        write_enum(writer, 15, DNSTAP_TYPE_MESSAGE)?;
+        write_tag(writer, 14, WIRE_LEN)?;
+        write_varint(writer, self.message_len as u64)
+    }
+
+    fn write_message<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let dns_message = self.dns_message;
+        write_enum(writer, 1, dns_message.message_type.value())?;
+        if let Some(socket_family) = self.socket_family {
+            write_enum(writer, 2, socket_family.value())?;
+        }
+        if let Some(socket_protocol) = dns_message.socket_protocol {
+            write_enum(writer, 3, socket_protocol.value())?;
+        }
+        if let Some(query_address) = dns_message.query_address {
+            write_ip(writer, 4, query_address)?;
+        }
+        if let Some(response_address) = dns_message.response_address {
+            write_ip(writer, 5, response_address)?;
+        }
+        if let Some(query_port) = dns_message.query_port {
+            write_uint(writer, 6, u64::from(query_port))?;
+        }
+        if let Some(response_port) = dns_message.response_port {
+            write_uint(writer, 7, u64::from(response_port))?;
+        }
+        if let Some(query_time) = dns_message.query_time {
+            write_uint(writer, 8, query_time.as_secs())?;
+            write_fixed32(writer, 9, query_time.subsec_nanos())?;
+        }
+        if let Some(ref query_packet) = dns_message.query_packet {
+            write_bytes(writer, 10, query_packet)?;
+        }
+        if let Some(query_zone_len) = self.query_zone_len {
+            write_bytes(writer, 11, &self.query_zone[..query_zone_len])?;
+        }
+        if let Some(response_time) = dns_message.response_time {
+            write_uint(writer, 12, response_time.as_secs())?;
+            write_fixed32(writer, 13, response_time.subsec_nanos())?;
+        }
+        if let Some(ref response_packet) = dns_message.response_packet {
+            write_bytes(writer, 14, response_packet)?;
+        }
+        if let Some(ref policy) = dns_message.policy {
+            write_tag(writer, 15, WIRE_LEN)?;
+            write_varint(writer, self.policy_len as u64)?;
+            write_policy(writer, policy)?;
+        }
+        if let Some(http_protocol) = dns_message.http_protocol {
+            write_enum(writer, 16, http_protocol.value())?;
+        }
+        Ok(())
+    }
+}
+
+fn write_policy<W: Write>(writer: &mut W, policy: &Policy) -> io::Result<()> {
+    if let Some(ref policy_type) = policy.policy_type {
+        write_bytes(writer, 1, policy_type.as_bytes())?;
+    }
+    if let Some(ref rule) = policy.rule {
+        write_bytes(writer, 2, rule)?;
+    }
+    if let Some(action) = policy.action {
+        write_enum(writer, 3, action.value())?;
+    }
+    if let Some(policy_match) = policy.policy_match {
+        write_enum(writer, 4, policy_match.value())?;
+    }
+    if let Some(ref value) = policy.value {
+        write_bytes(writer, 5, value)?;
+    }
+    Ok(())
+}
+
+/// A writer that only counts the bytes written to it.
+struct Counter(usize);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn counted<F: FnOnce(&mut Counter) -> io::Result<()>>(write: F) -> usize {
+    let mut counter = Counter(0);
+    write(&mut counter).unwrap();
+    counter.0
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    let mut buf = [0u8; 10];
+    let mut len = 0;
+    while value >= 0x80 {
+        buf[len] = value as u8 | 0x80;
+        value >>= 7;
+        len += 1;
+    }
+    buf[len] = value as u8;
+    writer.write_all(&buf[..=len])
+}
+
+fn write_tag<W: Write>(writer: &mut W, field: u32, wire_type: u32) -> io::Result<()> {
+    write_varint(writer, u64::from(field << 3 | wire_type))
+}
+
+fn write_uint<W: Write>(writer: &mut W, field: u32, value: u64) -> io::Result<()> {
+    write_tag(writer, field, WIRE_VARINT)?;
+    write_varint(writer, value)
+}
+
+fn write_enum<W: Write>(writer: &mut W, field: u32, value: i32) -> io::Result<()> {
+    write_uint(writer, field, i64::from(value) as u64)
+}
+
+fn write_fixed32<W: Write>(writer: &mut W, field: u32, value: u32) -> io::Result<()> {
+    write_tag(writer, field, WIRE_FIXED32)?;
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_bytes<W: Write>(writer: &mut W, field: u32, bytes: &[u8]) -> io::Result<()> {
+    write_tag(writer, field, WIRE_LEN)?;
+    write_varint(writer, bytes.len() as u64)?;
+    writer.write_all(bytes)
+}
+
+fn write_ip<W: Write>(writer: &mut W, field: u32, ip: IpAddr) -> io::Result<()> {
+    match ip {
+        IpAddr::V4(ip4) => write_bytes(writer, field, &ip4.octets()),
+        IpAddr::V6(ip6) => write_bytes(writer, field, &ip6.octets()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use protobuf::Message;
+
+    use super::*;
+    use crate::test_support::*;
+    use crate::{HttpProtocol, MessageType, PolicyAction};
+
+    fn encode(dns_message: &DNSMessage) -> Result<Vec<u8>, &'static str> {
+        let encoder = Encoder::new(dns_message)?;
+        let mut encoded = vec![];
+        encoder.write_to(&mut encoded).unwrap();
+        assert_eq!(encoded.len(), encoder.len());
+        Ok(encoded)
+    }
+
+    /// The allocation-free encoder must produce the same payloads as the protobuf encoder.
+    #[test]
+    fn matches_protobuf_encoder() {
+        let mut dns_messages = reference_messages();
+        let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_RESPONSE);
+        dns_message.socket_family = Some(SocketFamily::INET);
+        dns_message.socket_protocol = Some(SocketProtocol::DOH);
+        dns_message.http_protocol = Some(HttpProtocol::HTTP3);
+        dns_message.query_address = Some(ip("2001:db8::1"));
+        dns_message.response_address = Some(ip("2001:db8::53"));
+        dns_message.response_time = Some(Duration::new(u64::MAX, 999_999_999));
+        dns_message.response_packet = Some(vec![0x5a; 300]);
+        dns_message.bailiwick = Some("ex\\.ample\\032.net.".to_owned());
+        dns_message.policy = Some(Policy {
+            policy_type: Some(String::new()),
+            rule: Some(vec![]),
+            action: Some(PolicyAction::DROP),
+            policy_match: None,
+            value: Some(vec![0xff; 200]),
+        });
+        dns_messages.push(dns_message);
+        for dns_message in dns_messages {
+            let protobuf = dns_message.clone().into_protobuf().unwrap();
+            assert_eq!(encode(&dns_message), Ok(protobuf.write_to_bytes().unwrap()));
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut dns_message = DNSMessage::new(
+            Some(vec![0, 0xff, b'x']),
+            Some(b"1.0".to_vec()),
+            MessageType::RESOLVER_RESPONSE,
+        );
+        dns_message.socket_family = Some(SocketFamily::INET6);
+        dns_message.socket_protocol = Some(SocketProtocol::DNSCryptTCP);
+        dns_message.query_address = Some(ip("::1"));
+        dns_message.query_port = Some(0);
+        dns_message.query_time = Some(Duration::new(0, 0));
+        dns_message.query_packet = Some(vec![]);
+        dns_message.response_address = Some(ip("ffff::ffff"));
+        dns_message.response_port = Some(u16::MAX);
+        dns_message.response_time = Some(Duration::new(u64::from(u32::MAX) + 1, 999_999_999));
+        dns_message.response_packet = Some(dns_response(7, "example.net", 1, &[1, 2, 3, 4]));
+        dns_message.extra = Some(vec![0, 1, 2, 0xff]);
+        dns_message.bailiwick = Some("ex\\.ample\\032.net.".to_owned());
+        let encoded = encode(&dns_message).unwrap();
+        assert_eq!(DNSMessage::from_bytes(&encoded).unwrap(), dns_message);
+    }
+
+    #[test]
+    fn http_protocol_requires_doh() {
+        let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_QUERY);
+        dns_message.http_protocol = Some(HttpProtocol::HTTP3);
+        assert_eq!(
+            encode(&dns_message),
+            Err("HTTP protocol set on a non-DoH message")
+        );
+        dns_message.socket_protocol = Some(SocketProtocol::DOQ);
+        assert!(encode(&dns_message).is_err());
+        assert!(dns_message.clone().into_protobuf().is_err());
+        dns_message.socket_protocol = Some(SocketProtocol::DOH);
+        assert!(encode(&dns_message).is_ok());
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_QUERY);
+        dns_message.query_address = Some(ip("192.0.2.1"));
+        dns_message.response_address = Some(ip("2001:db8::53"));
+        assert_eq!(
+            encode(&dns_message),
+            Err("Response address does not match the socket family")
+        );
+        let error = dns_message.encode(&mut vec![]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut dns_message = DNSMessage::new(None, None, MessageType::RESOLVER_QUERY);
+        dns_message.bailiwick = Some("example..com".to_owned());
+        assert!(encode(&dns_message).is_err());
+    }
+}
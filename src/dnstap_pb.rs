@@ -1,4 +1,4 @@
-// This file is generated by rust-protobuf 3.4.0. Do not edit
+// This file is generated by rust-protobuf 3.7.2. Do not edit
 // .proto file is parsed by pure
 // @generated
 
@@ -9,7 +9,6 @@
 #![allow(unused_attributes)]
 #![cfg_attr(rustfmt, rustfmt::skip)]
 
-#![allow(box_pointers)]
 #![allow(dead_code)]
 #![allow(missing_docs)]
 #![allow(non_camel_case_types)]
@@ -23,7 +22,7 @@
 
 /// Generated files are compatible only with the same version
 /// of protobuf runtime.
-const _PROTOBUF_VERSION_CHECK: () = ::protobuf::VERSION_3_4_0;
+const _PROTOBUF_VERSION_CHECK: () = ::protobuf::VERSION_3_7_2;
 
 // @@protoc_insertion_point(message:dnstap.Dnstap)
 #[derive(PartialEq,Clone,Default,Debug)]
@@ -421,6 +420,529 @@ pub mod dnstap {
     }
 }
 
+// @@protoc_insertion_point(message:dnstap.Policy)
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct Policy {
+    // message fields
+    // @@protoc_insertion_point(field:dnstap.Policy.type)
+    pub type_: ::std::option::Option<::std::string::String>,
+    // @@protoc_insertion_point(field:dnstap.Policy.rule)
+    pub rule: ::std::option::Option<::std::vec::Vec<u8>>,
+    // @@protoc_insertion_point(field:dnstap.Policy.action)
+    pub action: ::std::option::Option<::protobuf::EnumOrUnknown<policy::Action>>,
+    // @@protoc_insertion_point(field:dnstap.Policy.match)
+    pub match_: ::std::option::Option<::protobuf::EnumOrUnknown<policy::Match>>,
+    // @@protoc_insertion_point(field:dnstap.Policy.value)
+    pub value: ::std::option::Option<::std::vec::Vec<u8>>,
+    // special fields
+    // @@protoc_insertion_point(special_field:dnstap.Policy.special_fields)
+    pub special_fields: ::protobuf::SpecialFields,
+}
+
+impl<'a> ::std::default::Default for &'a Policy {
+    fn default() -> &'a Policy {
+        <Policy as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl Policy {
+    pub fn new() -> Policy {
+        ::std::default::Default::default()
+    }
+
+    // optional string type = 1;
+
+    pub fn type_(&self) -> &str {
+        match self.type_.as_ref() {
+            Some(v) => v,
+            None => "",
+        }
+    }
+
+    pub fn clear_type_(&mut self) {
+        self.type_ = ::std::option::Option::None;
+    }
+
+    pub fn has_type(&self) -> bool {
+        self.type_.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_type(&mut self, v: ::std::string::String) {
+        self.type_ = ::std::option::Option::Some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_type(&mut self) -> &mut ::std::string::String {
+        if self.type_.is_none() {
+            self.type_ = ::std::option::Option::Some(::std::string::String::new());
+        }
+        self.type_.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_type_(&mut self) -> ::std::string::String {
+        self.type_.take().unwrap_or_else(|| ::std::string::String::new())
+    }
+
+    // optional bytes rule = 2;
+
+    pub fn rule(&self) -> &[u8] {
+        match self.rule.as_ref() {
+            Some(v) => v,
+            None => &[],
+        }
+    }
+
+    pub fn clear_rule(&mut self) {
+        self.rule = ::std::option::Option::None;
+    }
+
+    pub fn has_rule(&self) -> bool {
+        self.rule.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_rule(&mut self, v: ::std::vec::Vec<u8>) {
+        self.rule = ::std::option::Option::Some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_rule(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.rule.is_none() {
+            self.rule = ::std::option::Option::Some(::std::vec::Vec::new());
+        }
+        self.rule.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_rule(&mut self) -> ::std::vec::Vec<u8> {
+        self.rule.take().unwrap_or_else(|| ::std::vec::Vec::new())
+    }
+
+    // optional .dnstap.Policy.Action action = 3;
+
+    pub fn action(&self) -> policy::Action {
+        match self.action {
+            Some(e) => e.enum_value_or(policy::Action::NXDOMAIN),
+            None => policy::Action::NXDOMAIN,
+        }
+    }
+
+    pub fn clear_action(&mut self) {
+        self.action = ::std::option::Option::None;
+    }
+
+    pub fn has_action(&self) -> bool {
+        self.action.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_action(&mut self, v: policy::Action) {
+        self.action = ::std::option::Option::Some(::protobuf::EnumOrUnknown::new(v));
+    }
+
+    // optional .dnstap.Policy.Match match = 4;
+
+    pub fn match_(&self) -> policy::Match {
+        match self.match_ {
+            Some(e) => e.enum_value_or(policy::Match::QNAME),
+            None => policy::Match::QNAME,
+        }
+    }
+
+    pub fn clear_match_(&mut self) {
+        self.match_ = ::std::option::Option::None;
+    }
+
+    pub fn has_match(&self) -> bool {
+        self.match_.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_match(&mut self, v: policy::Match) {
+        self.match_ = ::std::option::Option::Some(::protobuf::EnumOrUnknown::new(v));
+    }
+
+    // optional bytes value = 5;
+
+    pub fn value(&self) -> &[u8] {
+        match self.value.as_ref() {
+            Some(v) => v,
+            None => &[],
+        }
+    }
+
+    pub fn clear_value(&mut self) {
+        self.value = ::std::option::Option::None;
+    }
+
+    pub fn has_value(&self) -> bool {
+        self.value.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_value(&mut self, v: ::std::vec::Vec<u8>) {
+        self.value = ::std::option::Option::Some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_value(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.value.is_none() {
+            self.value = ::std::option::Option::Some(::std::vec::Vec::new());
+        }
+        self.value.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_value(&mut self) -> ::std::vec::Vec<u8> {
+        self.value.take().unwrap_or_else(|| ::std::vec::Vec::new())
+    }
+
+    fn generated_message_descriptor_data() -> ::protobuf::reflect::GeneratedMessageDescriptorData {
+        let mut fields = ::std::vec::Vec::with_capacity(5);
+        let mut oneofs = ::std::vec::Vec::with_capacity(0);
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "type",
+            |m: &Policy| { &m.type_ },
+            |m: &mut Policy| { &mut m.type_ },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "rule",
+            |m: &Policy| { &m.rule },
+            |m: &mut Policy| { &mut m.rule },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "action",
+            |m: &Policy| { &m.action },
+            |m: &mut Policy| { &mut m.action },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "match",
+            |m: &Policy| { &m.match_ },
+            |m: &mut Policy| { &mut m.match_ },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "value",
+            |m: &Policy| { &m.value },
+            |m: &mut Policy| { &mut m.value },
+        ));
+        ::protobuf::reflect::GeneratedMessageDescriptorData::new_2::<Policy>(
+            "Policy",
+            fields,
+            oneofs,
+        )
+    }
+}
+
+impl ::protobuf::Message for Policy {
+    const NAME: &'static str = "Policy";
+
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::Result<()> {
+        while let Some(tag) = is.read_raw_tag_or_eof()? {
+            match tag {
+                10 => {
+                    self.type_ = ::std::option::Option::Some(is.read_string()?);
+                },
+                18 => {
+                    self.rule = ::std::option::Option::Some(is.read_bytes()?);
+                },
+                24 => {
+                    self.action = ::std::option::Option::Some(is.read_enum_or_unknown()?);
+                },
+                32 => {
+                    self.match_ = ::std::option::Option::Some(is.read_enum_or_unknown()?);
+                },
+                42 => {
+                    self.value = ::std::option::Option::Some(is.read_bytes()?);
+                },
+                tag => {
+                    ::protobuf::rt::read_unknown_or_skip_group(tag, is, self.special_fields.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u64 {
+        let mut my_size = 0;
+        if let Some(v) = self.type_.as_ref() {
+            my_size += ::protobuf::rt::string_size(1, &v);
+        }
+        if let Some(v) = self.rule.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(2, &v);
+        }
+        if let Some(v) = self.action {
+            my_size += ::protobuf::rt::int32_size(3, v.value());
+        }
+        if let Some(v) = self.match_ {
+            my_size += ::protobuf::rt::int32_size(4, v.value());
+        }
+        if let Some(v) = self.value.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(5, &v);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.special_fields.unknown_fields());
+        self.special_fields.cached_size().set(my_size as u32);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::Result<()> {
+        if let Some(v) = self.type_.as_ref() {
+            os.write_string(1, v)?;
+        }
+        if let Some(v) = self.rule.as_ref() {
+            os.write_bytes(2, v)?;
+        }
+        if let Some(v) = self.action {
+            os.write_enum(3, ::protobuf::EnumOrUnknown::value(&v))?;
+        }
+        if let Some(v) = self.match_ {
+            os.write_enum(4, ::protobuf::EnumOrUnknown::value(&v))?;
+        }
+        if let Some(v) = self.value.as_ref() {
+            os.write_bytes(5, v)?;
+        }
+        os.write_unknown_fields(self.special_fields.unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn special_fields(&self) -> &::protobuf::SpecialFields {
+        &self.special_fields
+    }
+
+    fn mut_special_fields(&mut self) -> &mut ::protobuf::SpecialFields {
+        &mut self.special_fields
+    }
+
+    fn new() -> Policy {
+        Policy::new()
+    }
+
+    fn clear(&mut self) {
+        self.type_ = ::std::option::Option::None;
+        self.rule = ::std::option::Option::None;
+        self.action = ::std::option::Option::None;
+        self.match_ = ::std::option::Option::None;
+        self.value = ::std::option::Option::None;
+        self.special_fields.clear();
+    }
+
+    fn default_instance() -> &'static Policy {
+        static instance: Policy = Policy {
+            type_: ::std::option::Option::None,
+            rule: ::std::option::Option::None,
+            action: ::std::option::Option::None,
+            match_: ::std::option::Option::None,
+            value: ::std::option::Option::None,
+            special_fields: ::protobuf::SpecialFields::new(),
+        };
+        &instance
+    }
+}
+
+impl ::protobuf::MessageFull for Policy {
+    fn descriptor() -> ::protobuf::reflect::MessageDescriptor {
+        static descriptor: ::protobuf::rt::Lazy<::protobuf::reflect::MessageDescriptor> = ::protobuf::rt::Lazy::new();
+        descriptor.get(|| file_descriptor().message_by_package_relative_name("Policy").unwrap()).clone()
+    }
+}
+
+impl ::std::fmt::Display for Policy {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        ::protobuf::text_format::fmt(self, f)
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Policy {
+    type RuntimeType = ::protobuf::reflect::rt::RuntimeTypeMessage<Self>;
+}
+
+/// Nested message and enums of message `Policy`
+pub mod policy {
+    #[derive(Clone,Copy,PartialEq,Eq,Debug,Hash)]
+    // @@protoc_insertion_point(enum:dnstap.Policy.Match)
+    pub enum Match {
+        // @@protoc_insertion_point(enum_value:dnstap.Policy.Match.QNAME)
+        QNAME = 1,
+        // @@protoc_insertion_point(enum_value:dnstap.Policy.Match.CLIENT_IP)
+        CLIENT_IP = 2,
+        // @@protoc_insertion_point(enum_value:dnstap.Policy.Match.RESPONSE_IP)
+        RESPONSE_IP = 3,
+        // @@protoc_insertion_point(enum_value:dnstap.Policy.Match.NS_NAME)
+        NS_NAME = 4,
+        // @@protoc_insertion_point(enum_value:dnstap.Policy.Match.NS_IP)
+        NS_IP = 5,
+    }
+
+    impl ::protobuf::Enum for Match {
+        const NAME: &'static str = "Match";
+
+        fn value(&self) -> i32 {
+            *self as i32
+        }
+
+        fn from_i32(value: i32) -> ::std::option::Option<Match> {
+            match value {
+                1 => ::std::option::Option::Some(Match::QNAME),
+                2 => ::std::option::Option::Some(Match::CLIENT_IP),
+                3 => ::std::option::Option::Some(Match::RESPONSE_IP),
+                4 => ::std::option::Option::Some(Match::NS_NAME),
+                5 => ::std::option::Option::Some(Match::NS_IP),
+                _ => ::std::option::Option::None
+            }
+        }
+
+        fn from_str(str: &str) -> ::std::option::Option<Match> {
+            match str {
+                "QNAME" => ::std::option::Option::Some(Match::QNAME),
+                "CLIENT_IP" => ::std::option::Option::Some(Match::CLIENT_IP),
+                "RESPONSE_IP" => ::std::option::Option::Some(Match::RESPONSE_IP),
+                "NS_NAME" => ::std::option::Option::Some(Match::NS_NAME),
+                "NS_IP" => ::std::option::Option::Some(Match::NS_IP),
+                _ => ::std::option::Option::None
+            }
+        }
+
+        const VALUES: &'static [Match] = &[
+            Match::QNAME,
+            Match::CLIENT_IP,
+            Match::RESPONSE_IP,
+            Match::NS_NAME,
+            Match::NS_IP,
+        ];
+    }
+
+    impl ::protobuf::EnumFull for Match {
+        fn enum_descriptor() -> ::protobuf::reflect::EnumDescriptor {
+            static descriptor: ::protobuf::rt::Lazy<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::Lazy::new();
+            descriptor.get(|| super::file_descriptor().enum_by_package_relative_name("Policy.Match").unwrap()).clone()
+        }
+
+        fn descriptor(&self) -> ::protobuf::reflect::EnumValueDescriptor {
+            let index = match self {
+                Match::QNAME => 0,
+                Match::CLIENT_IP => 1,
+                Match::RESPONSE_IP => 2,
+                Match::NS_NAME => 3,
+                Match::NS_IP => 4,
+            };
+            Self::enum_descriptor().value_by_index(index)
+        }
+    }
+
+    // Note, `Default` is implemented although default value is not 0
+    impl ::std::default::Default for Match {
+        fn default() -> Self {
+            Match::QNAME
+        }
+    }
+
+    impl Match {
+        pub(in super) fn generated_enum_descriptor_data() -> ::protobuf::reflect::GeneratedEnumDescriptorData {
+            ::protobuf::reflect::GeneratedEnumDescriptorData::new::<Match>("Policy.Match")
+        }
+    }
+
+    #[derive(Clone,Copy,PartialEq,Eq,Debug,Hash)]
+    // @@protoc_insertion_point(enum:dnstap.Policy.Action)
+    pub enum Action {
+        // @@protoc_insertion_point(enum_value:dnstap.Policy.Action.NXDOMAIN)
+        NXDOMAIN = 1,
+        // @@protoc_insertion_point(enum_value:dnstap.Policy.Action.NODATA)
+        NODATA = 2,
+        // @@protoc_insertion_point(enum_value:dnstap.Policy.Action.PASS)
+        PASS = 3,
+        // @@protoc_insertion_point(enum_value:dnstap.Policy.Action.DROP)
+        DROP = 4,
+        // @@protoc_insertion_point(enum_value:dnstap.Policy.Action.TRUNCATE)
+        TRUNCATE = 5,
+        // @@protoc_insertion_point(enum_value:dnstap.Policy.Action.LOCAL_DATA)
+        LOCAL_DATA = 6,
+    }
+
+    impl ::protobuf::Enum for Action {
+        const NAME: &'static str = "Action";
+
+        fn value(&self) -> i32 {
+            *self as i32
+        }
+
+        fn from_i32(value: i32) -> ::std::option::Option<Action> {
+            match value {
+                1 => ::std::option::Option::Some(Action::NXDOMAIN),
+                2 => ::std::option::Option::Some(Action::NODATA),
+                3 => ::std::option::Option::Some(Action::PASS),
+                4 => ::std::option::Option::Some(Action::DROP),
+                5 => ::std::option::Option::Some(Action::TRUNCATE),
+                6 => ::std::option::Option::Some(Action::LOCAL_DATA),
+                _ => ::std::option::Option::None
+            }
+        }
+
+        fn from_str(str: &str) -> ::std::option::Option<Action> {
+            match str {
+                "NXDOMAIN" => ::std::option::Option::Some(Action::NXDOMAIN),
+                "NODATA" => ::std::option::Option::Some(Action::NODATA),
+                "PASS" => ::std::option::Option::Some(Action::PASS),
+                "DROP" => ::std::option::Option::Some(Action::DROP),
+                "TRUNCATE" => ::std::option::Option::Some(Action::TRUNCATE),
+                "LOCAL_DATA" => ::std::option::Option::Some(Action::LOCAL_DATA),
+                _ => ::std::option::Option::None
+            }
+        }
+
+        const VALUES: &'static [Action] = &[
+            Action::NXDOMAIN,
+            Action::NODATA,
+            Action::PASS,
+            Action::DROP,
+            Action::TRUNCATE,
+            Action::LOCAL_DATA,
+        ];
+    }
+
+    impl ::protobuf::EnumFull for Action {
+        fn enum_descriptor() -> ::protobuf::reflect::EnumDescriptor {
+            static descriptor: ::protobuf::rt::Lazy<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::Lazy::new();
+            descriptor.get(|| super::file_descriptor().enum_by_package_relative_name("Policy.Action").unwrap()).clone()
+        }
+
+        fn descriptor(&self) -> ::protobuf::reflect::EnumValueDescriptor {
+            let index = match self {
+                Action::NXDOMAIN => 0,
+                Action::NODATA => 1,
+                Action::PASS => 2,
+                Action::DROP => 3,
+                Action::TRUNCATE => 4,
+                Action::LOCAL_DATA => 5,
+            };
+            Self::enum_descriptor().value_by_index(index)
+        }
+    }
+
+    // Note, `Default` is implemented although default value is not 0
+    impl ::std::default::Default for Action {
+        fn default() -> Self {
+            Action::NXDOMAIN
+        }
+    }
+
+    impl Action {
+        pub(in super) fn generated_enum_descriptor_data() -> ::protobuf::reflect::GeneratedEnumDescriptorData {
+            ::protobuf::reflect::GeneratedEnumDescriptorData::new::<Action>("Policy.Action")
+        }
+    }
+}
+
 // @@protoc_insertion_point(message:dnstap.Message)
 #[derive(PartialEq,Clone,Default,Debug)]
 pub struct Message {
@@ -453,6 +975,10 @@ pub struct Message {
     pub response_time_nsec: ::std::option::Option<u32>,
     // @@protoc_insertion_point(field:dnstap.Message.response_message)
     pub response_message: ::std::option::Option<::std::vec::Vec<u8>>,
+    // @@protoc_insertion_point(field:dnstap.Message.policy)
+    pub policy: ::protobuf::MessageField<Policy>,
+    // @@protoc_insertion_point(field:dnstap.Message.http_protocol)
+    pub http_protocol: ::std::option::Option<::protobuf::EnumOrUnknown<HttpProtocol>>,
     // special fields
     // @@protoc_insertion_point(special_field:dnstap.Message.special_fields)
     pub special_fields: ::protobuf::SpecialFields,
@@ -829,8 +1355,30 @@ impl Message {
         self.response_message.take().unwrap_or_else(|| ::std::vec::Vec::new())
     }
 
+    // optional .dnstap.HttpProtocol http_protocol = 16;
+
+    pub fn http_protocol(&self) -> HttpProtocol {
+        match self.http_protocol {
+            Some(e) => e.enum_value_or(HttpProtocol::HTTP1),
+            None => HttpProtocol::HTTP1,
+        }
+    }
+
+    pub fn clear_http_protocol(&mut self) {
+        self.http_protocol = ::std::option::Option::None;
+    }
+
+    pub fn has_http_protocol(&self) -> bool {
+        self.http_protocol.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_http_protocol(&mut self, v: HttpProtocol) {
+        self.http_protocol = ::std::option::Option::Some(::protobuf::EnumOrUnknown::new(v));
+    }
+
     fn generated_message_descriptor_data() -> ::protobuf::reflect::GeneratedMessageDescriptorData {
-        let mut fields = ::std::vec::Vec::with_capacity(14);
+        let mut fields = ::std::vec::Vec::with_capacity(16);
         let mut oneofs = ::std::vec::Vec::with_capacity(0);
         fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
             "type",
@@ -902,6 +1450,16 @@ impl Message {
             |m: &Message| { &m.response_message },
             |m: &mut Message| { &mut m.response_message },
         ));
+        fields.push(::protobuf::reflect::rt::v2::make_message_field_accessor::<_, Policy>(
+            "policy",
+            |m: &Message| { &m.policy },
+            |m: &mut Message| { &mut m.policy },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "http_protocol",
+            |m: &Message| { &m.http_protocol },
+            |m: &mut Message| { &mut m.http_protocol },
+        ));
         ::protobuf::reflect::GeneratedMessageDescriptorData::new_2::<Message>(
             "Message",
             fields,
@@ -917,6 +1475,11 @@ impl ::protobuf::Message for Message {
         if self.type_.is_none() {
             return false;
         }
+        for v in &self.policy {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
         true
     }
 
@@ -965,6 +1528,12 @@ impl ::protobuf::Message for Message {
                 114 => {
                     self.response_message = ::std::option::Option::Some(is.read_bytes()?);
                 },
+                122 => {
+                    ::protobuf::rt::read_singular_message_into_field(is, &mut self.policy)?;
+                },
+                128 => {
+                    self.http_protocol = ::std::option::Option::Some(is.read_enum_or_unknown()?);
+                },
                 tag => {
                     ::protobuf::rt::read_unknown_or_skip_group(tag, is, self.special_fields.mut_unknown_fields())?;
                 },
@@ -1019,6 +1588,13 @@ impl ::protobuf::Message for Message {
         if let Some(v) = self.response_message.as_ref() {
             my_size += ::protobuf::rt::bytes_size(14, &v);
         }
+        if let Some(v) = self.policy.as_ref() {
+            let len = v.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint64_size(len) + len;
+        }
+        if let Some(v) = self.http_protocol {
+            my_size += ::protobuf::rt::int32_size(16, v.value());
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.special_fields.unknown_fields());
         self.special_fields.cached_size().set(my_size as u32);
         my_size
@@ -1067,6 +1643,12 @@ impl ::protobuf::Message for Message {
         if let Some(v) = self.response_message.as_ref() {
             os.write_bytes(14, v)?;
         }
+        if let Some(v) = self.policy.as_ref() {
+            ::protobuf::rt::write_message_field_with_cached_size(15, v, os)?;
+        }
+        if let Some(v) = self.http_protocol {
+            os.write_enum(16, ::protobuf::EnumOrUnknown::value(&v))?;
+        }
         os.write_unknown_fields(self.special_fields.unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -1098,6 +1680,8 @@ impl ::protobuf::Message for Message {
         self.response_time_sec = ::std::option::Option::None;
         self.response_time_nsec = ::std::option::Option::None;
         self.response_message = ::std::option::Option::None;
+        self.policy.clear();
+        self.http_protocol = ::std::option::Option::None;
         self.special_fields.clear();
     }
 
@@ -1117,6 +1701,8 @@ impl ::protobuf::Message for Message {
             response_time_sec: ::std::option::Option::None,
             response_time_nsec: ::std::option::Option::None,
             response_message: ::std::option::Option::None,
+            policy: ::protobuf::MessageField::none(),
+            http_protocol: ::std::option::Option::None,
             special_fields: ::protobuf::SpecialFields::new(),
         };
         &instance
@@ -1169,6 +1755,10 @@ pub mod message {
         TOOL_QUERY = 11,
         // @@protoc_insertion_point(enum_value:dnstap.Message.Type.TOOL_RESPONSE)
         TOOL_RESPONSE = 12,
+        // @@protoc_insertion_point(enum_value:dnstap.Message.Type.UPDATE_QUERY)
+        UPDATE_QUERY = 13,
+        // @@protoc_insertion_point(enum_value:dnstap.Message.Type.UPDATE_RESPONSE)
+        UPDATE_RESPONSE = 14,
     }
 
     impl ::protobuf::Enum for Type {
@@ -1192,6 +1782,8 @@ pub mod message {
                 10 => ::std::option::Option::Some(Type::STUB_RESPONSE),
                 11 => ::std::option::Option::Some(Type::TOOL_QUERY),
                 12 => ::std::option::Option::Some(Type::TOOL_RESPONSE),
+                13 => ::std::option::Option::Some(Type::UPDATE_QUERY),
+                14 => ::std::option::Option::Some(Type::UPDATE_RESPONSE),
                 _ => ::std::option::Option::None
             }
         }
@@ -1210,6 +1802,8 @@ pub mod message {
                 "STUB_RESPONSE" => ::std::option::Option::Some(Type::STUB_RESPONSE),
                 "TOOL_QUERY" => ::std::option::Option::Some(Type::TOOL_QUERY),
                 "TOOL_RESPONSE" => ::std::option::Option::Some(Type::TOOL_RESPONSE),
+                "UPDATE_QUERY" => ::std::option::Option::Some(Type::UPDATE_QUERY),
+                "UPDATE_RESPONSE" => ::std::option::Option::Some(Type::UPDATE_RESPONSE),
                 _ => ::std::option::Option::None
             }
         }
@@ -1227,6 +1821,8 @@ pub mod message {
             Type::STUB_RESPONSE,
             Type::TOOL_QUERY,
             Type::TOOL_RESPONSE,
+            Type::UPDATE_QUERY,
+            Type::UPDATE_RESPONSE,
         ];
     }
 
@@ -1250,6 +1846,8 @@ pub mod message {
                 Type::STUB_RESPONSE => 9,
                 Type::TOOL_QUERY => 10,
                 Type::TOOL_RESPONSE => 11,
+                Type::UPDATE_QUERY => 12,
+                Type::UPDATE_RESPONSE => 13,
             };
             Self::enum_descriptor().value_by_index(index)
         }
@@ -1350,6 +1948,8 @@ pub enum SocketProtocol {
     DNSCryptUDP = 5,
     // @@protoc_insertion_point(enum_value:dnstap.SocketProtocol.DNSCryptTCP)
     DNSCryptTCP = 6,
+    // @@protoc_insertion_point(enum_value:dnstap.SocketProtocol.DOQ)
+    DOQ = 7,
 }
 
 impl ::protobuf::Enum for SocketProtocol {
@@ -1367,6 +1967,7 @@ impl ::protobuf::Enum for SocketProtocol {
             4 => ::std::option::Option::Some(SocketProtocol::DOH),
             5 => ::std::option::Option::Some(SocketProtocol::DNSCryptUDP),
             6 => ::std::option::Option::Some(SocketProtocol::DNSCryptTCP),
+            7 => ::std::option::Option::Some(SocketProtocol::DOQ),
             _ => ::std::option::Option::None
         }
     }
@@ -1379,6 +1980,7 @@ impl ::protobuf::Enum for SocketProtocol {
             "DOH" => ::std::option::Option::Some(SocketProtocol::DOH),
             "DNSCryptUDP" => ::std::option::Option::Some(SocketProtocol::DNSCryptUDP),
             "DNSCryptTCP" => ::std::option::Option::Some(SocketProtocol::DNSCryptTCP),
+            "DOQ" => ::std::option::Option::Some(SocketProtocol::DOQ),
             _ => ::std::option::Option::None
         }
     }
@@ -1390,6 +1992,7 @@ impl ::protobuf::Enum for SocketProtocol {
         SocketProtocol::DOH,
         SocketProtocol::DNSCryptUDP,
         SocketProtocol::DNSCryptTCP,
+        SocketProtocol::DOQ,
     ];
 }
 
@@ -1407,6 +2010,7 @@ impl ::protobuf::EnumFull for SocketProtocol {
             SocketProtocol::DOH => 3,
             SocketProtocol::DNSCryptUDP => 4,
             SocketProtocol::DNSCryptTCP => 5,
+            SocketProtocol::DOQ => 6,
         };
         Self::enum_descriptor().value_by_index(index)
     }
@@ -1425,36 +2029,122 @@ impl SocketProtocol {
     }
 }
 
+#[derive(Clone,Copy,PartialEq,Eq,Debug,Hash)]
+// @@protoc_insertion_point(enum:dnstap.HttpProtocol)
+pub enum HttpProtocol {
+    // @@protoc_insertion_point(enum_value:dnstap.HttpProtocol.HTTP1)
+    HTTP1 = 1,
+    // @@protoc_insertion_point(enum_value:dnstap.HttpProtocol.HTTP2)
+    HTTP2 = 2,
+    // @@protoc_insertion_point(enum_value:dnstap.HttpProtocol.HTTP3)
+    HTTP3 = 3,
+}
+
+impl ::protobuf::Enum for HttpProtocol {
+    const NAME: &'static str = "HttpProtocol";
+
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<HttpProtocol> {
+        match value {
+            1 => ::std::option::Option::Some(HttpProtocol::HTTP1),
+            2 => ::std::option::Option::Some(HttpProtocol::HTTP2),
+            3 => ::std::option::Option::Some(HttpProtocol::HTTP3),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn from_str(str: &str) -> ::std::option::Option<HttpProtocol> {
+        match str {
+            "HTTP1" => ::std::option::Option::Some(HttpProtocol::HTTP1),
+            "HTTP2" => ::std::option::Option::Some(HttpProtocol::HTTP2),
+            "HTTP3" => ::std::option::Option::Some(HttpProtocol::HTTP3),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    const VALUES: &'static [HttpProtocol] = &[
+        HttpProtocol::HTTP1,
+        HttpProtocol::HTTP2,
+        HttpProtocol::HTTP3,
+    ];
+}
+
+impl ::protobuf::EnumFull for HttpProtocol {
+    fn enum_descriptor() -> ::protobuf::reflect::EnumDescriptor {
+        static descriptor: ::protobuf::rt::Lazy<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::Lazy::new();
+        descriptor.get(|| file_descriptor().enum_by_package_relative_name("HttpProtocol").unwrap()).clone()
+    }
+
+    fn descriptor(&self) -> ::protobuf::reflect::EnumValueDescriptor {
+        let index = match self {
+            HttpProtocol::HTTP1 => 0,
+            HttpProtocol::HTTP2 => 1,
+            HttpProtocol::HTTP3 => 2,
+        };
+        Self::enum_descriptor().value_by_index(index)
+    }
+}
+
+// Note, `Default` is implemented although default value is not 0
+impl ::std::default::Default for HttpProtocol {
+    fn default() -> Self {
+        HttpProtocol::HTTP1
+    }
+}
+
+impl HttpProtocol {
+    fn generated_enum_descriptor_data() -> ::protobuf::reflect::GeneratedEnumDescriptorData {
+        ::protobuf::reflect::GeneratedEnumDescriptorData::new::<HttpProtocol>("HttpProtocol")
+    }
+}
+
 static file_descriptor_proto_data: &'static [u8] = b"\
     \n\x0fdnstap_pb.proto\x12\x06dnstap\"\xbd\x01\n\x06Dnstap\x12\x1a\n\x08i\
     dentity\x18\x01\x20\x01(\x0cR\x08identity\x12\x18\n\x07version\x18\x02\
     \x20\x01(\x0cR\x07version\x12\x14\n\x05extra\x18\x03\x20\x01(\x0cR\x05ex\
     tra\x12'\n\x04type\x18\x0f\x20\x02(\x0e2\x13.dnstap.Dnstap.TypeR\x04type\
     \x12)\n\x07message\x18\x0e\x20\x01(\x0b2\x0f.dnstap.MessageR\x07message\
-    \"\x13\n\x04Type\x12\x0b\n\x07MESSAGE\x10\x01\"\xcb\x06\n\x07Message\x12\
-    (\n\x04type\x18\x01\x20\x02(\x0e2\x14.dnstap.Message.TypeR\x04type\x129\
-    \n\rsocket_family\x18\x02\x20\x01(\x0e2\x14.dnstap.SocketFamilyR\x0csock\
-    etFamily\x12?\n\x0fsocket_protocol\x18\x03\x20\x01(\x0e2\x16.dnstap.Sock\
-    etProtocolR\x0esocketProtocol\x12#\n\rquery_address\x18\x04\x20\x01(\x0c\
-    R\x0cqueryAddress\x12)\n\x10response_address\x18\x05\x20\x01(\x0cR\x0fre\
-    sponseAddress\x12\x1d\n\nquery_port\x18\x06\x20\x01(\rR\tqueryPort\x12#\
-    \n\rresponse_port\x18\x07\x20\x01(\rR\x0cresponsePort\x12$\n\x0equery_ti\
-    me_sec\x18\x08\x20\x01(\x04R\x0cqueryTimeSec\x12&\n\x0fquery_time_nsec\
-    \x18\t\x20\x01(\x07R\rqueryTimeNsec\x12#\n\rquery_message\x18\n\x20\x01(\
-    \x0cR\x0cqueryMessage\x12\x1d\n\nquery_zone\x18\x0b\x20\x01(\x0cR\tquery\
-    Zone\x12*\n\x11response_time_sec\x18\x0c\x20\x01(\x04R\x0fresponseTimeSe\
-    c\x12,\n\x12response_time_nsec\x18\r\x20\x01(\x07R\x10responseTimeNsec\
-    \x12)\n\x10response_message\x18\x0e\x20\x01(\x0cR\x0fresponseMessage\"\
-    \xee\x01\n\x04Type\x12\x0e\n\nAUTH_QUERY\x10\x01\x12\x11\n\rAUTH_RESPONS\
-    E\x10\x02\x12\x12\n\x0eRESOLVER_QUERY\x10\x03\x12\x15\n\x11RESOLVER_RESP\
-    ONSE\x10\x04\x12\x10\n\x0cCLIENT_QUERY\x10\x05\x12\x13\n\x0fCLIENT_RESPO\
-    NSE\x10\x06\x12\x13\n\x0fFORWARDER_QUERY\x10\x07\x12\x16\n\x12FORWARDER_\
-    RESPONSE\x10\x08\x12\x0e\n\nSTUB_QUERY\x10\t\x12\x11\n\rSTUB_RESPONSE\
-    \x10\n\x12\x0e\n\nTOOL_QUERY\x10\x0b\x12\x11\n\rTOOL_RESPONSE\x10\x0c*#\
-    \n\x0cSocketFamily\x12\x08\n\x04INET\x10\x01\x12\t\n\x05INET6\x10\x02*V\
-    \n\x0eSocketProtocol\x12\x07\n\x03UDP\x10\x01\x12\x07\n\x03TCP\x10\x02\
-    \x12\x07\n\x03DOT\x10\x03\x12\x07\n\x03DOH\x10\x04\x12\x0f\n\x0bDNSCrypt\
-    UDP\x10\x05\x12\x0f\n\x0bDNSCryptTCP\x10\x06b\x06proto2\
+    \"\x13\n\x04Type\x12\x0b\n\x07MESSAGE\x10\x01\"\xc3\x02\n\x06Policy\x12\
+    \x12\n\x04type\x18\x01\x20\x01(\tR\x04type\x12\x12\n\x04rule\x18\x02\x20\
+    \x01(\x0cR\x04rule\x12-\n\x06action\x18\x03\x20\x01(\x0e2\x15.dnstap.Pol\
+    icy.ActionR\x06action\x12*\n\x05match\x18\x04\x20\x01(\x0e2\x14.dnstap.P\
+    olicy.MatchR\x05match\x12\x14\n\x05value\x18\x05\x20\x01(\x0cR\x05value\
+    \"J\n\x05Match\x12\t\n\x05QNAME\x10\x01\x12\r\n\tCLIENT_IP\x10\x02\x12\
+    \x0f\n\x0bRESPONSE_IP\x10\x03\x12\x0b\n\x07NS_NAME\x10\x04\x12\t\n\x05NS\
+    _IP\x10\x05\"T\n\x06Action\x12\x0c\n\x08NXDOMAIN\x10\x01\x12\n\n\x06NODA\
+    TA\x10\x02\x12\x08\n\x04PASS\x10\x03\x12\x08\n\x04DROP\x10\x04\x12\x0c\n\
+    \x08TRUNCATE\x10\x05\x12\x0e\n\nLOCAL_DATA\x10\x06\"\xd5\x07\n\x07Messag\
+    e\x12(\n\x04type\x18\x01\x20\x02(\x0e2\x14.dnstap.Message.TypeR\x04type\
+    \x129\n\rsocket_family\x18\x02\x20\x01(\x0e2\x14.dnstap.SocketFamilyR\
+    \x0csocketFamily\x12?\n\x0fsocket_protocol\x18\x03\x20\x01(\x0e2\x16.dns\
+    tap.SocketProtocolR\x0esocketProtocol\x12#\n\rquery_address\x18\x04\x20\
+    \x01(\x0cR\x0cqueryAddress\x12)\n\x10response_address\x18\x05\x20\x01(\
+    \x0cR\x0fresponseAddress\x12\x1d\n\nquery_port\x18\x06\x20\x01(\rR\tquer\
+    yPort\x12#\n\rresponse_port\x18\x07\x20\x01(\rR\x0cresponsePort\x12$\n\
+    \x0equery_time_sec\x18\x08\x20\x01(\x04R\x0cqueryTimeSec\x12&\n\x0fquery\
+    _time_nsec\x18\t\x20\x01(\x07R\rqueryTimeNsec\x12#\n\rquery_message\x18\
+    \n\x20\x01(\x0cR\x0cqueryMessage\x12\x1d\n\nquery_zone\x18\x0b\x20\x01(\
+    \x0cR\tqueryZone\x12*\n\x11response_time_sec\x18\x0c\x20\x01(\x04R\x0fre\
+    sponseTimeSec\x12,\n\x12response_time_nsec\x18\r\x20\x01(\x07R\x10respon\
+    seTimeNsec\x12)\n\x10response_message\x18\x0e\x20\x01(\x0cR\x0fresponseM\
+    essage\x12&\n\x06policy\x18\x0f\x20\x01(\x0b2\x0e.dnstap.PolicyR\x06poli\
+    cy\x129\n\rhttp_protocol\x18\x10\x20\x01(\x0e2\x14.dnstap.HttpProtocolR\
+    \x0chttpProtocol\"\x95\x02\n\x04Type\x12\x0e\n\nAUTH_QUERY\x10\x01\x12\
+    \x11\n\rAUTH_RESPONSE\x10\x02\x12\x12\n\x0eRESOLVER_QUERY\x10\x03\x12\
+    \x15\n\x11RESOLVER_RESPONSE\x10\x04\x12\x10\n\x0cCLIENT_QUERY\x10\x05\
+    \x12\x13\n\x0fCLIENT_RESPONSE\x10\x06\x12\x13\n\x0fFORWARDER_QUERY\x10\
+    \x07\x12\x16\n\x12FORWARDER_RESPONSE\x10\x08\x12\x0e\n\nSTUB_QUERY\x10\t\
+    \x12\x11\n\rSTUB_RESPONSE\x10\n\x12\x0e\n\nTOOL_QUERY\x10\x0b\x12\x11\n\
+    \rTOOL_RESPONSE\x10\x0c\x12\x10\n\x0cUPDATE_QUERY\x10\r\x12\x13\n\x0fUPD\
+    ATE_RESPONSE\x10\x0e*#\n\x0cSocketFamily\x12\x08\n\x04INET\x10\x01\x12\t\
+    \n\x05INET6\x10\x02*_\n\x0eSocketProtocol\x12\x07\n\x03UDP\x10\x01\x12\
+    \x07\n\x03TCP\x10\x02\x12\x07\n\x03DOT\x10\x03\x12\x07\n\x03DOH\x10\x04\
+    \x12\x0f\n\x0bDNSCryptUDP\x10\x05\x12\x0f\n\x0bDNSCryptTCP\x10\x06\x12\
+    \x07\n\x03DOQ\x10\x07*/\n\x0cHttpProtocol\x12\t\n\x05HTTP1\x10\x01\x12\t\
+    \n\x05HTTP2\x10\x02\x12\t\n\x05HTTP3\x10\x03b\x06proto2\
 ";
 
 /// `FileDescriptorProto` object which was a source for this generated file
@@ -1472,13 +2162,17 @@ pub fn file_descriptor() -> &'static ::protobuf::reflect::FileDescriptor {
     file_descriptor.get(|| {
         let generated_file_descriptor = generated_file_descriptor_lazy.get(|| {
             let mut deps = ::std::vec::Vec::with_capacity(0);
-            let mut messages = ::std::vec::Vec::with_capacity(2);
+            let mut messages = ::std::vec::Vec::with_capacity(3);
             messages.push(Dnstap::generated_message_descriptor_data());
+            messages.push(Policy::generated_message_descriptor_data());
             messages.push(Message::generated_message_descriptor_data());
-            let mut enums = ::std::vec::Vec::with_capacity(4);
+            let mut enums = ::std::vec::Vec::with_capacity(7);
             enums.push(SocketFamily::generated_enum_descriptor_data());
             enums.push(SocketProtocol::generated_enum_descriptor_data());
+            enums.push(HttpProtocol::generated_enum_descriptor_data());
             enums.push(dnstap::Type::generated_enum_descriptor_data());
+            enums.push(policy::Match::generated_enum_descriptor_data());
+            enums.push(policy::Action::generated_enum_descriptor_data());
             enums.push(message::Type::generated_enum_descriptor_data());
             ::protobuf::reflect::GeneratedFileDescriptor::new_generated(
                 file_descriptor_proto(),
@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::anonymizer::Anonymization;
+use crate::context::CONTENT_TYPE;
+use crate::dns_message::*;
+use crate::frame_stream::*;
+use crate::transform::{EdnsRedaction, PacketMinimization, Transforms};
+
+/// `DNSTapReader` reads dnstap data from a unidirectional Frame Streams source,
+/// such as a file written by unbound, BIND, Knot, or `DNSTapWriter`.
+///
+/// Control frames are processed transparently, and a single source can contain
+/// multiple concatenated START/STOP segments. Each segment must announce the
+/// dnstap content type.
+///
+/// # Example
+/// ```no_run
+/// use dnstap::DNSTapReader;
+///
+/// let reader = DNSTapReader::open("/tmp/dnstap.log").unwrap();
+/// for dns_message in reader {
+///     let dns_message = dns_message.unwrap();
+///     println!("{:?}", dns_message.message_type);
+/// }
+/// ```
+pub struct DNSTapReader<R: Read> {
+    reader: R,
+    segment: Segment,
+    transforms: Transforms,
+}
+
+/// Tracks START/STOP segments in a unidirectional Frame Streams source.
+#[derive(Default)]
+pub(crate) struct Segment {
+    started: bool,
+}
+
+impl Segment {
+    /// Processes a frame, and returns the payload if it was a data frame.
+    pub fn process(&mut self, frame: Frame) -> io::Result<Option<Vec<u8>>> {
+        match frame {
+            Frame::Data(payload) => {
+                if !self.started {
+                    return Err(invalid_data("Data frame received outside of a segment"));
+                }
+                Ok(Some(payload))
+            }
+            Frame::Control(control) => match control.control_type {
+                CONTROL_START if !self.started => {
+                    if !control.has_content_type(CONTENT_TYPE.as_bytes()) {
+                        return Err(invalid_data("Unexpected content type"));
+                    }
+                    self.started = true;
+                    Ok(None)
+                }
+                CONTROL_STOP if self.started => {
+                    self.started = false;
+                    Ok(None)
+                }
+                _ => Err(invalid_data("Unexpected control frame")),
+            },
+        }
+    }
+}
+
+impl DNSTapReader<BufReader<File>> {
+    /// Opens a dnstap file.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<DNSTapReader<BufReader<File>>> {
+        Ok(DNSTapReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read> DNSTapReader<R> {
+    /// Creates a `DNSTapReader` object reading from `reader`. Using a buffered reader is
+    /// recommended.
+    pub fn new(reader: R) -> DNSTapReader<R> {
+        DNSTapReader {
+            reader,
+            segment: Segment::default(),
+            transforms: Transforms::default(),
+        }
+    }
+
+    /// Anonymizes the query and response addresses of the messages read. Raw payloads
+    /// returned by `read_frame()` are left untouched.
+    pub fn anonymization(mut self, anonymization: Anonymization) -> Self {
+        self.transforms.set_anonymization(&anonymization);
+        self
+    }
+
+    /// Redacts EDNS options in the packets of the messages read.
+    pub fn edns_redaction(mut self, edns_redaction: EdnsRedaction) -> Self {
+        self.transforms.set_edns_redaction(edns_redaction);
+        self
+    }
+
+    /// Minimizes the packets of the messages read.
+    pub fn packet_minimization(mut self, packet_minimization: PacketMinimization) -> Self {
+        self.transforms.set_packet_minimization(packet_minimization);
+        self
+    }
+
+    /// Returns the next raw dnstap payload, or `None` at the end of the stream.
+    pub fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        while let Some(frame) = Frame::read_from(&mut self.reader)? {
+            if let Some(payload) = self.segment.process(frame)? {
+                return Ok(Some(payload));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the next DNS message, or `None` at the end of the stream.
+    pub fn read_message(&mut self) -> io::Result<Option<DNSMessage>> {
+        let payload = match self.read_frame()? {
+            None => return Ok(None),
+            Some(payload) => payload,
+        };
+        let mut dns_message = DNSMessage::from_bytes(&payload).map_err(invalid_data)?;
+        self.transforms.apply(&mut dns_message);
+        Ok(Some(dns_message))
+    }
+
+    /// Returns an iterator over the raw dnstap payloads.
+    pub fn frames(self) -> Frames<R> {
+        Frames(self)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for DNSTapReader<R> {
+    type Item = io::Result<DNSMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_message().transpose()
+    }
+}
+
+/// An iterator over the raw payloads of a `DNSTapReader`.
+pub struct Frames<R: Read>(DNSTapReader<R>);
+
+impl<R: Read> Iterator for Frames<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.read_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::test_support::*;
+    use crate::{MessageType, SocketFamily};
+
+    fn message(identity: &[u8]) -> DNSMessage {
+        let mut dns_message =
+            DNSMessage::new(Some(identity.to_vec()), None, MessageType::CLIENT_QUERY);
+        dns_message.query_packet = Some(vec![0u8; 12]);
+        dns_message
+    }
+
+    fn stream(content_type: &'static str, messages: &[DNSMessage]) -> Vec<u8> {
+        let mut writer = FrameWriter::new(vec![], 4096, content_type);
+        for dns_message in messages {
+            let len = dns_message.encoded_len().unwrap();
+            writer.write_frame(len, |w| dns_message.encode(w)).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let messages = vec![message(b"a"), message(b"b")];
+        let mut data = stream(CONTENT_TYPE, &messages);
+        data.extend(stream(CONTENT_TYPE, &messages[1..]));
+        let read: Vec<_> = DNSTapReader::new(&data[..])
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(read, vec![message(b"a"), message(b"b"), message(b"b")]);
+    }
+
+    #[test]
+    fn wrong_content_type_is_rejected() {
+        let data = stream("protobuf:other", &[message(b"a")]);
+        let err = DNSTapReader::new(&data[..]).read_message().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn data_outside_of_a_segment_is_rejected() {
+        let mut data = vec![];
+        data.extend_from_slice(&3u32.to_be_bytes());
+        data.extend_from_slice(b"abc");
+        let err = DNSTapReader::new(&data[..]).read_frame().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_stream_is_rejected() {
+        let data = stream(CONTENT_TYPE, &[message(b"a"), message(b"b")]);
+        let stop_len = 12;
+        let mut reader = DNSTapReader::new(&data[..data.len() - stop_len - 1]);
+        assert_eq!(reader.read_message().unwrap(), Some(message(b"a")));
+        let err = reader.read_message().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn transforms() {
+        let mut query_packet = dns_query(0x1234, "example.com", 1);
+        let rdlen = query_packet.len() - 2;
+        query_packet[rdlen + 1] = 6;
+        query_packet.extend_from_slice(&[0xfd, 0xe9, 0, 2, 0xab, 0xcd]);
+        let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_QUERY);
+        dns_message.socket_family = Some(SocketFamily::INET);
+        dns_message.query_address = Some(ip("192.0.2.123"));
+        dns_message.query_packet = Some(query_packet);
+        let data = stream(CONTENT_TYPE, &[dns_message]);
+
+        let dns_message = DNSTapReader::new(&data[..])
+            .anonymization(Anonymization::Prefix {
+                ipv4_prefix: 24,
+                ipv6_prefix: 32,
+            })
+            .edns_redaction(EdnsRedaction::default().remove_option(65001))
+            .read_message()
+            .unwrap()
+            .unwrap();
+        assert_eq!(dns_message.query_address, Some(ip("192.0.2.0")));
+        assert_eq!(
+            dns_message.query_packet,
+            Some(dns_query(0x1234, "example.com", 1))
+        );
+
+        let dns_message = DNSTapReader::new(&data[..])
+            .packet_minimization(PacketMinimization::HeaderAndQuestion)
+            .read_message()
+            .unwrap()
+            .unwrap();
+        let mut query_packet = dns_query(0x1234, "example.com", 1);
+        query_packet.truncate(query_packet.len() - 11);
+        query_packet[11] = 0;
+        assert_eq!(dns_message.query_packet, Some(query_packet));
+        assert_eq!(dns_message.query_address, Some(ip("192.0.2.123")));
+    }
+
+    #[test]
+    fn invalid_payloads_are_rejected() {
+        let mut writer = FrameWriter::new(vec![], 64, CONTENT_TYPE);
+        writer
+            .write_frame(2, |w| w.write_all(&[0xff, 0xff]))
+            .unwrap();
+        let data = writer.finish().unwrap();
+        let mut reader = DNSTapReader::new(&data[..]);
+        let err = reader.read_message().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,555 @@
+use std::fmt;
+
+use crate::dig_format;
+use crate::dns_message::*;
+use crate::dns_packet::{self, DNSPacket};
+use crate::timestamp;
+use crate::{MessageType, PolicyMatch};
+
+/// One-line rendering of a DNS message, compatible with the quiet output of the reference
+/// `dnstap` tool: time, message type, address, protocol, packet size and question.
+pub struct QuietText<'a>(&'a DNSMessage);
+
+/// Multi-line rendering of a DNS message: the quiet line, followed by the query and
+/// response packets in `dig` presentation format.
+pub struct FullText<'a>(&'a DNSMessage);
+
+/// Multi-line YAML rendering of a DNS message, compatible with the output of the reference
+/// `dnstap -y` tool, including the way DNS packets are rendered by its `miekg/dns`
+/// dependency. Every document ends with a `---` separator.
+pub struct YamlText<'a>(&'a DNSMessage);
+
+/// Single-line JSON rendering of a DNS message, suitable for JSON Lines output.
+///
+/// Fields that are not set are omitted. Parsed header and question fields of the DNS
+/// packets can be added with `with_dns_fields()`.
+#[cfg(feature = "serde")]
+pub struct JsonText<'a> {
+    dns_message: &'a DNSMessage,
+    dns_fields: bool,
+}
+
+#[cfg(feature = "serde")]
+impl<'a> JsonText<'a> {
+    /// Adds `query_dns` and `response_dns` objects with the identifier, flags, response
+    /// code, section counts and question parsed from the DNS packets.
+    pub fn with_dns_fields(mut self) -> Self {
+        self.dns_fields = true;
+        self
+    }
+}
+
+impl DNSMessage {
+    /// Returns an object whose `Display` implementation renders the message in the
+    /// one-line quiet format.
+    pub fn quiet_text(&self) -> QuietText<'_> {
+        QuietText(self)
+    }
+
+    /// Returns an object whose `Display` implementation renders the message and its DNS
+    /// packets in full.
+    pub fn full_text(&self) -> FullText<'_> {
+        FullText(self)
+    }
+
+    /// Returns an object whose `Display` implementation renders the message as a YAML
+    /// document.
+    pub fn yaml_text(&self) -> YamlText<'_> {
+        YamlText(self)
+    }
+
+    /// Returns an object whose `Display` implementation renders the message as a single
+    /// line of JSON.
+    #[cfg(feature = "serde")]
+    pub fn json_text(&self) -> JsonText<'_> {
+        JsonText {
+            dns_message: self,
+            dns_fields: false,
+        }
+    }
+}
+
+pub(crate) fn is_query(message_type: MessageType) -> bool {
+    match message_type {
+        MessageType::AUTH_QUERY
+        | MessageType::RESOLVER_QUERY
+        | MessageType::CLIENT_QUERY
+        | MessageType::FORWARDER_QUERY
+        | MessageType::STUB_QUERY
+        | MessageType::TOOL_QUERY
+        | MessageType::UPDATE_QUERY => true,
+        MessageType::AUTH_RESPONSE
+        | MessageType::RESOLVER_RESPONSE
+        | MessageType::CLIENT_RESPONSE
+        | MessageType::FORWARDER_RESPONSE
+        | MessageType::STUB_RESPONSE
+        | MessageType::TOOL_RESPONSE
+        | MessageType::UPDATE_RESPONSE => false,
+    }
+}
+
+fn message_type_code(message_type: MessageType) -> &'static str {
+    match message_type {
+        MessageType::AUTH_QUERY => "AQ",
+        MessageType::AUTH_RESPONSE => "AR",
+        MessageType::RESOLVER_QUERY => "RQ",
+        MessageType::RESOLVER_RESPONSE => "RR",
+        MessageType::CLIENT_QUERY => "CQ",
+        MessageType::CLIENT_RESPONSE => "CR",
+        MessageType::FORWARDER_QUERY => "FQ",
+        MessageType::FORWARDER_RESPONSE => "FR",
+        MessageType::STUB_QUERY => "SQ",
+        MessageType::STUB_RESPONSE => "SR",
+        MessageType::TOOL_QUERY => "TQ",
+        MessageType::TOOL_RESPONSE => "TR",
+        MessageType::UPDATE_QUERY => "UQ",
+        MessageType::UPDATE_RESPONSE => "UR",
+    }
+}
+
+impl<'a> fmt::Display for QuietText<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dns_message = self.0;
+        let message_type = dns_message.message_type;
+        let (time, packet) = if is_query(message_type) {
+            (dns_message.query_time, &dns_message.query_packet)
+        } else {
+            (dns_message.response_time, &dns_message.response_packet)
+        };
+        if let Some(time) = time {
+            f.write_str(&timestamp::time_of_day(time))?;
+        }
+        write!(f, " {} ", message_type_code(message_type))?;
+        let address = match message_type {
+            MessageType::AUTH_QUERY
+            | MessageType::AUTH_RESPONSE
+            | MessageType::CLIENT_QUERY
+            | MessageType::CLIENT_RESPONSE
+            | MessageType::UPDATE_QUERY
+            | MessageType::UPDATE_RESPONSE => dns_message.query_address,
+            _ => dns_message.response_address,
+        };
+        if let Some(address) = address {
+            write!(f, "{}", address)?;
+        }
+        f.write_str(" ")?;
+        if let Some(socket_protocol) = dns_message.socket_protocol {
+            write!(f, "{:?}", socket_protocol)?;
+        }
+        let packet = packet.as_deref().unwrap_or_default();
+        write!(f, " {}b ", packet.len())?;
+        match DNSPacket::new(packet).and_then(|dns_packet| dns_packet.question()) {
+            Some(question) => write!(
+                f,
+                "\"{}\" {} {}",
+                question.qname,
+                dns_packet::class_name(question.qclass),
+                dns_packet::rr_type_name(question.qtype)
+            ),
+            None => f.write_str("X"),
+        }
+    }
+}
+
+impl<'a> fmt::Display for FullText<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dns_message = self.0;
+        writeln!(f, "{}", dns_message.quiet_text())?;
+        if let Some(ref policy) = dns_message.policy {
+            f.write_str(";; policy:")?;
+            if let Some(ref policy_type) = policy.policy_type {
+                write!(f, " {}", policy_type)?;
+            }
+            if let Some(action) = policy.action {
+                write!(f, " {:?}", action)?;
+            }
+            if let Some(policy_match) = policy.policy_match {
+                write!(f, " match {:?}", policy_match)?;
+            }
+            if let Some(ref value) = policy.value {
+                f.write_str(" ")?;
+                write_policy_value(f, policy.policy_match, value)?;
+            }
+            if let Some(ref rule) = policy.rule {
+                f.write_str(" rule ")?;
+                write_policy_name(f, rule)?;
+            }
+            writeln!(f)?;
+        }
+        let packets = [
+            ("query_message", dns_message.query_dns_packet()),
+            ("response_message", dns_message.response_dns_packet()),
+        ];
+        for (name, dns_packet) in packets.iter() {
+            if let Some(dns_packet) = dns_packet {
+                write!(f, "\n;; {}:\n{}", name, dns_packet.dig_text())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &[u8]) -> fmt::Result {
+    f.write_str("\"")?;
+    for &c in s {
+        match c {
+            b'"' => f.write_str("\\\"")?,
+            b'\\' => f.write_str("\\\\")?,
+            0x20..=0x7e => write!(f, "{}", c as char)?,
+            _ => write!(f, "\\x{:02x}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Writes a policy rule, which is usually a wire format name.
+fn write_policy_name(f: &mut fmt::Formatter<'_>, rule: &[u8]) -> fmt::Result {
+    match dns_packet::name_from_wire(rule) {
+        Ok(name) => write_quoted(f, name.as_bytes()),
+        Err(_) => write_quoted(f, rule),
+    }
+}
+
+/// Writes a matched policy value according to the feature it was matched on.
+fn write_policy_value(
+    f: &mut fmt::Formatter<'_>,
+    policy_match: Option<PolicyMatch>,
+    value: &[u8],
+) -> fmt::Result {
+    match policy_match {
+        Some(PolicyMatch::CLIENT_IP | PolicyMatch::RESPONSE_IP | PolicyMatch::NS_IP) => {
+            match ip_from_bytes(value) {
+                Some(ip) => write!(f, "{}", ip),
+                None => write_quoted(f, value),
+            }
+        }
+        Some(PolicyMatch::QNAME | PolicyMatch::NS_NAME) => write_policy_name(f, value),
+        None => write_quoted(f, value),
+    }
+}
+
+fn write_packet(f: &mut fmt::Formatter<'_>, field_name: &str, packet: &[u8]) -> fmt::Result {
+    match dig_format::render_go(packet) {
+        Some(text) => {
+            writeln!(f, "  {}: |", field_name)?;
+            for line in text.trim().lines() {
+                writeln!(f, "    {}", line)?;
+            }
+            Ok(())
+        }
+        None => writeln!(f, "  # {}: parse failed", field_name),
+    }
+}
+
+impl<'a> fmt::Display for YamlText<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dns_message = self.0;
+        f.write_str("type: MESSAGE\n")?;
+        if let Some(ref identity) = dns_message.identity {
+            f.write_str("identity: ")?;
+            write_quoted(f, identity)?;
+            writeln!(f)?;
+        }
+        if let Some(ref version) = dns_message.version {
+            f.write_str("version: ")?;
+            write_quoted(f, version)?;
+            writeln!(f)?;
+        }
+        if let Some(ref extra) = dns_message.extra {
+            f.write_str("extra: ")?;
+            write_quoted(f, extra)?;
+            writeln!(f)?;
+        }
+        f.write_str("message:\n")?;
+        writeln!(f, "  type: {:?}", dns_message.message_type)?;
+        if let Some(query_time) = dns_message.query_time {
+            writeln!(
+                f,
+                "  query_time: !!timestamp {}",
+                timestamp::date_time(query_time)
+            )?;
+        }
+        if let Some(response_time) = dns_message.response_time {
+            writeln!(
+                f,
+                "  response_time: !!timestamp {}",
+                timestamp::date_time(response_time)
+            )?;
+        }
+        if let Some(socket_family) = dns_message.socket_family {
+            writeln!(f, "  socket_family: {:?}", socket_family)?;
+        }
+        if let Some(socket_protocol) = dns_message.socket_protocol {
+            writeln!(f, "  socket_protocol: {:?}", socket_protocol)?;
+        }
+        if let Some(http_protocol) = dns_message.http_protocol {
+            writeln!(f, "  http_protocol: {:?}", http_protocol)?;
+        }
+        if let Some(query_address) = dns_message.query_address {
+            writeln!(f, "  query_address: {}", query_address)?;
+        }
+        if let Some(response_address) = dns_message.response_address {
+            writeln!(f, "  response_address: {}", response_address)?;
+        }
+        if let Some(query_port) = dns_message.query_port {
+            writeln!(f, "  query_port: {}", query_port)?;
+        }
+        if let Some(response_port) = dns_message.response_port {
+            writeln!(f, "  response_port: {}", response_port)?;
+        }
+        if let Some(ref bailiwick) = dns_message.bailiwick {
+            let mut query_zone = bailiwick.clone();
+            if !query_zone.ends_with('.') {
+                query_zone.push('.');
+            }
+            f.write_str("  query_zone: ")?;
+            write_quoted(f, query_zone.as_bytes())?;
+            writeln!(f)?;
+        }
+        if let Some(ref policy) = dns_message.policy {
+            f.write_str("  policy:\n")?;
+            if let Some(ref policy_type) = policy.policy_type {
+                f.write_str("    type: ")?;
+                write_quoted(f, policy_type.as_bytes())?;
+                writeln!(f)?;
+            }
+            if let Some(ref rule) = policy.rule {
+                f.write_str("    rule: ")?;
+                write_policy_name(f, rule)?;
+                writeln!(f)?;
+            }
+            if let Some(action) = policy.action {
+                writeln!(f, "    action: {:?}", action)?;
+            }
+            if let Some(policy_match) = policy.policy_match {
+                writeln!(f, "    match: {:?}", policy_match)?;
+            }
+            if let Some(ref value) = policy.value {
+                f.write_str("    value: ")?;
+                write_policy_value(f, policy.policy_match, value)?;
+                writeln!(f)?;
+            }
+        }
+        if let Some(ref query_packet) = dns_message.query_packet {
+            write_packet(f, "query_message", query_packet)?;
+        }
+        if let Some(ref response_packet) = dns_message.response_packet {
+            write_packet(f, "response_message", response_packet)?;
+        }
+        f.write_str("---")
+    }
+}
+
+#[cfg(feature = "serde")]
+fn dns_fields(packet: &[u8]) -> Option<serde_json::Value> {
+    let dns_packet = DNSPacket::new(packet)?;
+    let mut fields = serde_json::json!({
+        "id": dns_packet.id(),
+        "flags": dns_packet.flags(),
+        "rcode": dns_packet.rcode(),
+        "qdcount": dns_packet.question_count(),
+        "ancount": dns_packet.answer_count(),
+        "nscount": dns_packet.authority_count(),
+        "arcount": dns_packet.additional_count(),
+    });
+    if let Some(question) = dns_packet.question() {
+        fields["qname"] = question.qname.to_string().into();
+        fields["qtype"] = dns_packet::rr_type_name(question.qtype).into();
+        fields["qclass"] = dns_packet::class_name(question.qclass).into();
+    }
+    Some(fields)
+}
+
+#[cfg(feature = "serde")]
+impl<'a> fmt::Display for JsonText<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dns_message = self.dns_message;
+        let mut value = serde_json::to_value(dns_message).map_err(|_| fmt::Error)?;
+        if self.dns_fields {
+            let parsed = [
+                ("query_dns", &dns_message.query_packet),
+                ("response_dns", &dns_message.response_packet),
+            ];
+            for (name, packet) in parsed.iter() {
+                if let Some(fields) = packet.as_deref().and_then(dns_fields) {
+                    value[*name] = fields;
+                }
+            }
+        }
+        write!(f, "{}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_support::*;
+    use crate::{SocketFamily, SocketProtocol};
+
+    fn client_query() -> DNSMessage {
+        let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_QUERY);
+        dns_message.socket_protocol = Some(SocketProtocol::UDP);
+        dns_message.query_address = Some(ip("192.0.2.1"));
+        dns_message.response_address = Some(ip("192.0.2.53"));
+        dns_message.query_time = Some(Duration::new(1_389_130_919, 318_932_123));
+        dns_message.query_packet = Some(dns_query(0x1234, "www.example.com", 1));
+        dns_message
+    }
+
+    #[test]
+    fn quiet_text() {
+        let dns_message = client_query();
+        assert_eq!(
+            dns_message.quiet_text().to_string(),
+            "21:41:59.318932 CQ 192.0.2.1 UDP 44b \"www.example.com.\" IN A"
+        );
+
+        let mut dns_message = client_query();
+        dns_message.message_type = MessageType::RESOLVER_RESPONSE;
+        dns_message.socket_protocol = Some(SocketProtocol::DOT);
+        dns_message.response_time = Some(Duration::new(1_389_130_920, 5_000));
+        dns_message.response_packet = Some(dns_response(0x1234, "example.com", 15, &[]));
+        assert_eq!(
+            dns_message.quiet_text().to_string(),
+            "21:42:00.000005 RR 192.0.2.53 DOT 41b \"example.com.\" IN MX"
+        );
+    }
+
+    /// The expected output is modelled on `yaml.go` in `golang-dnstap`, with packets rendered
+    /// by `miekg/dns`. It was written by hand, not produced by that tool.
+    #[test]
+    fn yaml_text() {
+        let mut response_packet = dns_response(0xbeef, "www.example.com", 1, &[192, 0, 2, 80]);
+        response_packet[11] = 1;
+        response_packet.extend_from_slice(&[0, 0, 41, 0x04, 0xd0, 0, 0, 0x80, 0, 0, 35]);
+        // CLIENT-SUBNET 198.51.100.0/24/0
+        response_packet.extend_from_slice(&[0, 8, 0, 7, 0, 1, 24, 0, 198, 51, 100]);
+        // COOKIE
+        response_packet.extend_from_slice(&[0, 10, 0, 8, 1, 2, 3, 4, 5, 6, 7, 0xab]);
+        // EDE 15 (Blocked)
+        response_packet
+            .extend_from_slice(&[0, 15, 0, 8, 0, 15, b'b', b'l', b'o', b'c', b'k', b's']);
+        let mut dns_message = DNSMessage::new(
+            Some(b"resolver.example".to_vec()),
+            Some(b"unbound 1.19".to_vec()),
+            MessageType::RESOLVER_RESPONSE,
+        );
+        dns_message.socket_family = Some(SocketFamily::INET6);
+        dns_message.socket_protocol = Some(SocketProtocol::UDP);
+        dns_message.response_address = Some(ip("2001:db8::53"));
+        dns_message.response_port = Some(53);
+        dns_message.query_time = Some(Duration::new(1_700_000_003, 0));
+        dns_message.response_time = Some(Duration::new(1_700_000_003, 250_000_000));
+        dns_message.bailiwick = Some("example.com".to_owned());
+        dns_message.response_packet = Some(response_packet);
+        let expected = "\
+type: MESSAGE
+identity: \"resolver.example\"
+version: \"unbound 1.19\"
+message:
+  type: RESOLVER_RESPONSE
+  query_time: !!timestamp 2023-11-14 22:13:23
+  response_time: !!timestamp 2023-11-14 22:13:23.25
+  socket_family: INET6
+  socket_protocol: UDP
+  response_address: 2001:db8::53
+  response_port: 53
+  query_zone: \"example.com.\"
+  response_message: |
+    ;; opcode: QUERY, status: NOERROR, id: 48879
+    ;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 1
+    
+    ;; OPT PSEUDOSECTION:
+    ; EDNS: version 0; flags: do; udp: 1232
+    ; SUBNET: 198.51.100.0/24/0
+    ; COOKIE: 01020304050607ab
+    ; EDE: 15 (Blocked): (blocks)
+    
+    ;; QUESTION SECTION:
+    ;www.example.com.\tIN\t A
+    
+    ;; ANSWER SECTION:
+    www.example.com.\t300\tIN\tA\t192.0.2.80
+---";
+        assert_eq!(dns_message.yaml_text().to_string(), expected);
+    }
+
+    #[test]
+    fn yaml_text_with_unparsable_packets() {
+        let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_QUERY);
+        dns_message.query_packet = Some(vec![0x12, 0x34, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0, 3]);
+        assert_eq!(
+            dns_message.yaml_text().to_string(),
+            "type: MESSAGE\nmessage:\n  type: CLIENT_QUERY\n  # query_message: parse failed\n---"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_text_round_trip() {
+        let mut dns_message = client_query();
+        dns_message.identity = Some(b"resolver.example".to_vec());
+        dns_message.extra = Some(vec![0, 1, 2, 0xff]);
+        dns_message.bailiwick = Some("example.com.".to_owned());
+        dns_message.policy = Some(Policy {
+            policy_type: Some("RPZ".to_owned()),
+            rule: Some(name("rule.rpz.example")),
+            action: Some(crate::PolicyAction::NXDOMAIN),
+            policy_match: Some(PolicyMatch::QNAME),
+            value: None,
+        });
+        let json = dns_message.json_text().to_string();
+        assert!(!json.contains('\n'));
+        assert_eq!(
+            serde_json::from_str::<DNSMessage>(&json).unwrap(),
+            dns_message
+        );
+
+        let json = dns_message.json_text().with_dns_fields().to_string();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["query_time"], "2014-01-07T21:41:59.318932123Z");
+        assert_eq!(value["query_address"], "192.0.2.1");
+        assert_eq!(value["socket_protocol"], "UDP");
+        assert_eq!(value["extra"], "AAEC/w==");
+        assert_eq!(value["query_dns"]["id"], 0x1234);
+        assert_eq!(value["query_dns"]["qname"], "www.example.com.");
+        assert_eq!(value["query_dns"]["qtype"], "A");
+        assert!(value.get("response_dns").is_none());
+        assert!(value.get("response_time").is_none());
+        assert_eq!(
+            serde_json::from_value::<DNSMessage>(value).unwrap(),
+            dns_message
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_text_rejects_invalid_fields() {
+        let json = r#"{"message_type":"CLIENT_QUERY","query_time":"yesterday"}"#;
+        assert!(serde_json::from_str::<DNSMessage>(json).is_err());
+        let json = r#"{"message_type":"CLIENT_QUERY","query_packet":"not base64!"}"#;
+        assert!(serde_json::from_str::<DNSMessage>(json).is_err());
+        let json = r#"{"message_type":"CLIENT_QUESTION"}"#;
+        assert!(serde_json::from_str::<DNSMessage>(json).is_err());
+        let json = r#"{"message_type":"CLIENT_QUERY"}"#;
+        assert_eq!(
+            serde_json::from_str::<DNSMessage>(json).unwrap(),
+            DNSMessage::new(None, None, MessageType::CLIENT_QUERY)
+        );
+    }
+
+    #[test]
+    fn quiet_text_with_missing_fields() {
+        let dns_message = DNSMessage::new(None, None, MessageType::TOOL_QUERY);
+        assert_eq!(dns_message.quiet_text().to_string(), " TQ   0b X");
+
+        let mut dns_message = client_query();
+        dns_message.query_packet = Some(vec![0x12, 0x34, 0x01]);
+        assert_eq!(
+            dns_message.quiet_text().to_string(),
+            "21:41:59.318932 CQ 192.0.2.1 UDP 3b X"
+        );
+    }
+}
@@ -1,13 +1,18 @@
 use crate::context::*;
 use crate::dns_message::*;
 use crate::dnstap_builder::*;
+use crate::dnstap_text::is_query;
+use crate::timestamp::Clock;
+use crate::transform::Transforms;
 use mio::*;
 use std::any::Any;
 use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
 
 pub struct DNSTapPendingWriter {
-    dnstap_tx: channel::SyncSender<DNSMessage>,
+    sender: Sender,
     context: Context,
 }
 
@@ -15,6 +20,13 @@ impl DNSTapPendingWriter {
     /// Creates a `DNSTapPendingWriter` object. The communication channel is established at this
     /// point, and the `sender()` function can be used in order to get `Sender` objects.
     pub fn listen(builder: DNSTapBuilder) -> Result<DNSTapPendingWriter, &'static str> {
+        match (&builder.unix_socket_path, &builder.tcp_socket_addr) {
+            (None, None) => return Err("A UNIX socket path or a TCP address is required"),
+            (Some(_), Some(_)) => {
+                return Err("Only one of UNIX socket path and TCP address can be set")
+            }
+            _ => {}
+        }
         let (dnstap_tx, dnstap_rx) = channel::sync_channel(builder.backlog);
         let mio_poll = Poll::new().unwrap();
         mio_poll
@@ -29,17 +41,29 @@ impl DNSTapPendingWriter {
         mio_poll
             .register(&mio_timers, TIMER_TOK, Ready::readable(), PollOpt::edge())
             .unwrap();
-        assert!(builder.unix_socket_path.is_some());
         let context = Context {
             mio_poll,
             mio_timers,
             retry_timeout: None,
             dnstap_rx,
             unix_socket_path: builder.unix_socket_path,
-            unix_stream: None,
+            tcp_socket_addr: builder.tcp_socket_addr,
+            extra: builder.extra,
+            transforms: Transforms::new(
+                builder.anonymization.as_ref(),
+                builder.edns_redaction.clone(),
+                builder.packet_minimization,
+            ),
+            dropped_messages: Arc::new(AtomicUsize::new(0)),
+            stream: None,
             frame_stream: None,
         };
-        Ok(DNSTapPendingWriter { dnstap_tx, context })
+        let sender = Sender {
+            dnstap_tx,
+            strict: builder.strict,
+            clock: builder.clock,
+        };
+        Ok(DNSTapPendingWriter { sender, context })
     }
 
     /// Spawns a new task handling writes to the socket.
@@ -50,12 +74,12 @@ impl DNSTapPendingWriter {
     /// Returns a cloneable `Sender` object that can used to send DNS messages.
     #[inline]
     pub fn sender(&self) -> Sender {
-        Sender(self.dnstap_tx.clone())
+        self.sender.clone()
     }
 }
 
 /// `DNSTapWriter` is responsible for receiving DNS messages, connecting (and automatically
-/// reconnecting) to a UNIX socket, and asynchronously pushing the serialized data using
+/// reconnecting) to a UNIX socket or a TCP collector, and asynchronously pushing the serialized data using
 /// frame stream protocol.
 ///
 /// # Example
@@ -72,7 +96,8 @@ impl DNSTapPendingWriter {
 /// dnstap_writer.join().unwrap();
 /// ```
 pub struct DNSTapWriter {
-    dnstap_tx: channel::SyncSender<DNSMessage>,
+    sender: Sender,
+    dropped_messages: Arc<AtomicUsize>,
     tid: thread::JoinHandle<()>,
 }
 
@@ -81,7 +106,8 @@ impl DNSTapWriter {
     pub fn start(mut dnstap_pending_writer: DNSTapPendingWriter) -> io::Result<DNSTapWriter> {
         dnstap_pending_writer.context.connect();
         let mut events = Events::with_capacity(512);
-        let dnstap_tx = dnstap_pending_writer.dnstap_tx.clone();
+        let sender = dnstap_pending_writer.sender.clone();
+        let dropped_messages = dnstap_pending_writer.context.dropped_messages.clone();
         let tid = (thread::Builder::new()
             .name("dnstap".to_owned())
             .spawn(move || {
@@ -93,7 +119,7 @@ impl DNSTapWriter {
                 {
                     for event in events.iter() {
                         match event.token() {
-                            UNIX_SOCKET_TOK => dnstap_pending_writer.context.write_cb(event),
+                            STREAM_TOK => dnstap_pending_writer.context.write_cb(event),
                             NOTIFY_TOK => dnstap_pending_writer.context.message_cb(),
                             TIMER_TOK => dnstap_pending_writer.context.connect(),
                             _ => unreachable!(),
@@ -104,7 +130,11 @@ impl DNSTapWriter {
                     frame_stream.finish().unwrap();
                 }
             }))?;
-        Ok(DNSTapWriter { dnstap_tx, tid })
+        Ok(DNSTapWriter {
+            sender,
+            dropped_messages,
+            tid,
+        })
     }
 
     pub fn join(self) -> Result<(), Box<dyn Any + Send + 'static>> {
@@ -114,18 +144,61 @@ impl DNSTapWriter {
     /// Returns a cloneable `Sender` object that can used to send DNS messages.
     #[inline]
     pub fn sender(&self) -> Sender {
-        Sender(self.dnstap_tx.clone())
+        self.sender.clone()
+    }
+
+    /// Returns the number of messages accepted by `Sender::send()` that could not be
+    /// encoded once the anonymization and packet transformations were applied, and were
+    /// dropped.
+    pub fn dropped_messages(&self) -> usize {
+        self.dropped_messages.load(Ordering::Relaxed)
     }
 }
 
 /// `Sender` is a cloneable structure to send DNS messages.
 #[derive(Clone)]
-pub struct Sender(channel::SyncSender<DNSMessage>);
+pub struct Sender {
+    dnstap_tx: channel::SyncSender<DNSMessage>,
+    strict: bool,
+    clock: Arc<dyn Clock>,
+}
 
 impl Sender {
     /// Sends a DNS message.
-    #[inline]
-    pub fn send(&self, dns_message: DNSMessage) -> Result<(), channel::TrySendError<DNSMessage>> {
-        self.0.try_send(dns_message)
+    ///
+    /// Queries without a query time, and responses without a response time, are timestamped
+    /// with the clock of the writer.
+    ///
+    /// Messages that cannot be encoded, because of addresses of different families, an HTTP
+    /// protocol on a non-DoH message or an invalid `bailiwick`, and messages that don't pass
+    /// `DNSMessage::validate()` in strict mode, are rejected with a `TrySendError::Io` error
+    /// of kind `InvalidInput`. Messages are checked after they were timestamped. Messages
+    /// that cannot be encoded once the writer transformed them are dropped, and counted by
+    /// `DNSTapWriter::dropped_messages()`.
+    // The error hands the message back to the caller, like `SyncSender::try_send()`
+    #[allow(clippy::result_large_err)]
+    pub fn send(
+        &self,
+        mut dns_message: DNSMessage,
+    ) -> Result<(), channel::TrySendError<DNSMessage>> {
+        if is_query(dns_message.message_type) {
+            if dns_message.query_time.is_none() {
+                dns_message.set_query_time(self.clock.now());
+            }
+        } else if dns_message.response_time.is_none() {
+            dns_message.set_response_time(self.clock.now());
+        }
+        let checked = if self.strict {
+            dns_message.validate()
+        } else {
+            dns_message.check_encoding()
+        };
+        if let Err(e) = checked {
+            return Err(channel::TrySendError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                e,
+            )));
+        }
+        self.dnstap_tx.try_send(dns_message)
     }
 }
@@ -0,0 +1,341 @@
+use std::io::{self, BufWriter, Read, Write};
+
+pub const CONTROL_ACCEPT: u32 = 0x01;
+pub const CONTROL_START: u32 = 0x02;
+pub const CONTROL_STOP: u32 = 0x03;
+pub const CONTROL_READY: u32 = 0x04;
+pub const CONTROL_FINISH: u32 = 0x05;
+pub const CONTROL_FIELD_CONTENT_TYPE: u32 = 0x01;
+
+/// Maximum size of a data frame we are willing to allocate.
+pub const MAX_FRAME_SIZE: usize = 1_048_576;
+
+/// Maximum size of a control frame, as recommended by the Frame Streams specification.
+pub const MAX_CONTROL_FRAME_SIZE: usize = 512;
+
+/// A Frame Streams control frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ControlFrame {
+    pub control_type: u32,
+    pub content_types: Vec<Vec<u8>>,
+}
+
+impl ControlFrame {
+    pub fn new(control_type: u32, content_type: Option<&[u8]>) -> ControlFrame {
+        ControlFrame {
+            control_type,
+            content_types: content_type.into_iter().map(|x| x.to_vec()).collect(),
+        }
+    }
+
+    /// Returns `true` if the frame advertises `content_type`.
+    pub fn has_content_type(&self, content_type: &[u8]) -> bool {
+        self.content_types.iter().any(|x| x == content_type)
+    }
+
+    pub fn parse(payload: &[u8]) -> io::Result<ControlFrame> {
+        if payload.len() < 4 {
+            return Err(invalid_data("Short control frame"));
+        }
+        let control_type = be32(&payload[0..4]);
+        let mut content_types = vec![];
+        let mut fields = &payload[4..];
+        while !fields.is_empty() {
+            if fields.len() < 8 {
+                return Err(invalid_data("Truncated control frame field"));
+            }
+            let field_type = be32(&fields[0..4]);
+            let field_len = be32(&fields[4..8]) as usize;
+            fields = &fields[8..];
+            if field_len > fields.len() {
+                return Err(invalid_data("Truncated control frame field"));
+            }
+            if field_type == CONTROL_FIELD_CONTENT_TYPE {
+                content_types.push(fields[..field_len].to_vec());
+            }
+            fields = &fields[field_len..];
+        }
+        Ok(ControlFrame {
+            control_type,
+            content_types,
+        })
+    }
+
+    /// Writes the control frame, including the escape sequence, and flushes the writer.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.encode_to(writer)?;
+        writer.flush()
+    }
+
+    /// Writes the control frame, including the escape sequence, without flushing the writer.
+    pub fn encode_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let fields_len: usize = self.content_types.iter().map(|x| 8 + x.len()).sum();
+        let mut buf = Vec::with_capacity(12 + fields_len);
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        buf.extend_from_slice(&(4 + fields_len as u32).to_be_bytes());
+        buf.extend_from_slice(&self.control_type.to_be_bytes());
+        for content_type in &self.content_types {
+            buf.extend_from_slice(&CONTROL_FIELD_CONTENT_TYPE.to_be_bytes());
+            buf.extend_from_slice(&(content_type.len() as u32).to_be_bytes());
+            buf.extend_from_slice(content_type);
+        }
+        writer.write_all(&buf)
+    }
+}
+
+/// A Frame Streams frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    Data(Vec<u8>),
+    Control(ControlFrame),
+}
+
+impl Frame {
+    /// Decodes a single frame from the beginning of `buf`. Returns the frame and the number
+    /// of bytes it used, or `None` if more data is required.
+    #[cfg(feature = "async")]
+    pub fn decode(buf: &[u8]) -> io::Result<Option<(Frame, usize)>> {
+        let (header_len, len) = match frame_size(buf)? {
+            Some(size) => size,
+            None => return Ok(None),
+        };
+        if buf.len() < header_len + len {
+            return Ok(None);
+        }
+        let payload = buf[header_len..header_len + len].to_vec();
+        let frame = Frame::from_payload(header_len, payload)?;
+        Ok(Some((frame, header_len + len)))
+    }
+
+    /// Reads a single frame. Returns `None` if the end of the stream was reached
+    /// on a frame boundary.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<Frame>> {
+        let mut header = [0u8; 8];
+        if !read_exact_or_eof(reader, &mut header[..4])? {
+            return Ok(None);
+        }
+        let (header_len, len) = match frame_size(&header[..4])? {
+            Some(size) => size,
+            None => {
+                reader.read_exact(&mut header[4..])?;
+                frame_size(&header)?.expect("complete control frame header")
+            }
+        };
+        let mut payload = vec![0u8; len];
+        reader.read_exact(&mut payload)?;
+        Frame::from_payload(header_len, payload).map(Some)
+    }
+
+    fn from_payload(header_len: usize, payload: Vec<u8>) -> io::Result<Frame> {
+        if header_len == DATA_HEADER_LEN {
+            Ok(Frame::Data(payload))
+        } else {
+            Ok(Frame::Control(ControlFrame::parse(&payload)?))
+        }
+    }
+}
+
+const DATA_HEADER_LEN: usize = 4;
+const CONTROL_HEADER_LEN: usize = 8;
+
+/// Returns the length of the header and of the payload of the frame starting at the
+/// beginning of `header`, or `None` if more bytes are required to tell.
+fn frame_size(header: &[u8]) -> io::Result<Option<(usize, usize)>> {
+    if header.len() < DATA_HEADER_LEN {
+        return Ok(None);
+    }
+    let len = be32(&header[0..4]) as usize;
+    if len != 0 {
+        if len > MAX_FRAME_SIZE {
+            return Err(invalid_data("Frame too large"));
+        }
+        return Ok(Some((DATA_HEADER_LEN, len)));
+    }
+    if header.len() < CONTROL_HEADER_LEN {
+        return Ok(None);
+    }
+    let len = be32(&header[4..8]) as usize;
+    if len > MAX_CONTROL_FRAME_SIZE {
+        return Err(invalid_data("Control frame too large"));
+    }
+    Ok(Some((CONTROL_HEADER_LEN, len)))
+}
+
+/// A unidirectional Frame Streams writer, serializing data frames straight into its
+/// output buffer.
+pub struct FrameWriter<W: Write> {
+    writer: BufWriter<W>,
+    content_type: &'static str,
+    started: bool,
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(writer: W, capacity: usize, content_type: &'static str) -> FrameWriter<W> {
+        FrameWriter {
+            writer: BufWriter::with_capacity(capacity, writer),
+            content_type,
+            started: false,
+        }
+    }
+
+    /// Writes a data frame with a `len`-byte payload written by `write_payload`.
+    ///
+    /// The buffer is flushed first if the frame doesn't fit, so that a frame is either
+    /// entirely buffered, or not written at all if flushing fails.
+    pub fn write_frame<F>(&mut self, len: usize, write_payload: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut BufWriter<W>) -> io::Result<()>,
+    {
+        if !self.started {
+            ControlFrame::new(CONTROL_START, Some(self.content_type.as_bytes()))
+                .encode_to(&mut self.writer)?;
+            self.started = true;
+        }
+        if self.writer.capacity() - self.writer.buffer().len() < 4 + len {
+            self.writer.flush()?;
+        }
+        self.writer.write_all(&(len as u32).to_be_bytes())?;
+        write_payload(&mut self.writer)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Writes the STOP control frame if the stream was started, and flushes the buffer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.started {
+            ControlFrame::new(CONTROL_STOP, None).encode_to(&mut self.writer)?;
+        }
+        self.writer.into_inner().map_err(|e| e.into_error())
+    }
+}
+
+/// Fills `buf`, or returns `false` if the reader is at the end of the stream.
+pub(crate) fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut pos = 0;
+    while pos < buf.len() {
+        match reader.read(&mut buf[pos..]) {
+            Ok(0) if pos == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => pos += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+#[inline]
+fn be32(x: &[u8]) -> u32 {
+    u32::from_be_bytes([x[0], x[1], x[2], x[3]])
+}
+
+pub fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(mut stream: &[u8]) -> io::Result<Vec<Frame>> {
+        let mut frames = vec![];
+        while let Some(frame) = Frame::read_from(&mut stream)? {
+            frames.push(frame);
+        }
+        Ok(frames)
+    }
+
+    #[test]
+    fn frame_writer_round_trip() {
+        let mut writer = FrameWriter::new(vec![], 64, "protobuf:dnstap.Dnstap");
+        writer.write_frame(3, |w| w.write_all(b"abc")).unwrap();
+        writer
+            .write_frame(100, |w| w.write_all(&[7u8; 100]))
+            .unwrap();
+        let stream = writer.finish().unwrap();
+        assert_eq!(
+            read_all(&stream).unwrap(),
+            vec![
+                Frame::Control(ControlFrame::new(
+                    CONTROL_START,
+                    Some(b"protobuf:dnstap.Dnstap")
+                )),
+                Frame::Data(b"abc".to_vec()),
+                Frame::Data(vec![7u8; 100]),
+                Frame::Control(ControlFrame::new(CONTROL_STOP, None)),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_writer_writes_nothing() {
+        let writer = FrameWriter::new(vec![], 64, "protobuf:dnstap.Dnstap");
+        assert!(writer.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        let stream = ((MAX_FRAME_SIZE + 1) as u32).to_be_bytes();
+        let err = read_all(&stream).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut stream = 0u32.to_be_bytes().to_vec();
+        stream.extend_from_slice(&((MAX_CONTROL_FRAME_SIZE + 1) as u32).to_be_bytes());
+        let err = read_all(&stream).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_streams_are_rejected() {
+        let mut stream = 10u32.to_be_bytes().to_vec();
+        stream.extend_from_slice(b"abc");
+        let err = read_all(&stream).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let err = read_all(&[0, 0]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut stream = vec![];
+        ControlFrame::new(CONTROL_START, Some(b"x"))
+            .encode_to(&mut stream)
+            .unwrap();
+        stream.truncate(stream.len() - 1);
+        let err = read_all(&stream).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn decode_matches_read_from() {
+        let mut writer = FrameWriter::new(vec![], 64, "protobuf:dnstap.Dnstap");
+        writer.write_frame(3, |w| w.write_all(b"abc")).unwrap();
+        let stream = writer.finish().unwrap();
+        let frames = read_all(&stream).unwrap();
+        let mut decoded = vec![];
+        let mut pos = 0;
+        while pos < stream.len() {
+            let (frame, len) = Frame::decode(&stream[pos..]).unwrap().unwrap();
+            for end in pos..pos + len {
+                assert_eq!(Frame::decode(&stream[pos..end]).unwrap(), None);
+            }
+            decoded.push(frame);
+            pos += len;
+        }
+        assert_eq!(decoded, frames);
+
+        let stream = ((MAX_FRAME_SIZE + 1) as u32).to_be_bytes();
+        assert!(Frame::decode(&stream).is_err());
+    }
+
+    #[test]
+    fn malformed_control_frames_are_rejected() {
+        assert!(ControlFrame::parse(&[0, 0, 0]).is_err());
+        let mut payload = CONTROL_START.to_be_bytes().to_vec();
+        payload.extend_from_slice(&CONTROL_FIELD_CONTENT_TYPE.to_be_bytes());
+        payload.extend_from_slice(&10u32.to_be_bytes());
+        payload.extend_from_slice(b"short");
+        assert!(ControlFrame::parse(&payload).is_err());
+    }
+}
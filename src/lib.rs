@@ -1,20 +1,67 @@
 //! An implementation of the dnstap protocol
 //!
-//! This crate implements the sender part of the [dnstap](http://dnstap.info/) protocol,
-//! a flexible, structured binary log format for DNS software.
+//! This crate implements the [dnstap](http://dnstap.info/) protocol, a flexible,
+//! structured binary log format for DNS software.
+//!
+//! `DNSTapWriter` sends DNS messages to a UNIX socket, `DNSTapCollector` receives them from
+//! any number of producers, and `DNSTapReader` reads them back from a Frame Streams file.
+//! With the `async` feature, `AsyncDNSTapReader` does the same from any tokio `AsyncRead`.
+//! `PcapWriter` and `PcapReader` convert DNS messages to and from pcap captures.
+//! Writers, collectors and readers can all anonymize client and server addresses, redact
+//! EDNS options and minimize DNS packets, see `Anonymization`, `EdnsRedaction` and
+//! `PacketMinimization`.
 
 #![allow(deprecated)]
 
+mod anonymizer;
 mod context;
+mod dig_format;
 mod dns_message;
+mod dns_packet;
+#[cfg(feature = "async")]
+mod dnstap_async_reader;
 mod dnstap_builder;
+mod dnstap_collector;
+mod dnstap_encoder;
 mod dnstap_pb;
+mod dnstap_reader;
+mod dnstap_text;
 mod dnstap_writer;
+mod frame_stream;
+mod pcap_file;
+mod pcap_reader;
+mod pcap_writer;
+#[cfg(feature = "serde")]
+mod serde_support;
+#[cfg(test)]
+mod test_support;
+mod timestamp;
+mod transform;
 
 pub use crate::dnstap_pb::message::Type as MessageType;
+pub use crate::dnstap_pb::policy::Action as PolicyAction;
+pub use crate::dnstap_pb::policy::Match as PolicyMatch;
+pub use crate::dnstap_pb::HttpProtocol;
 pub use crate::dnstap_pb::SocketFamily;
 pub use crate::dnstap_pb::SocketProtocol;
 
+pub use crate::anonymizer::{Anonymization, Anonymizer};
+pub use crate::context::CONTENT_TYPE;
+pub use crate::dig_format::DigText;
 pub use crate::dns_message::*;
+pub use crate::dns_packet::{DNSName, DNSPacket, DNSQuestion, Labels};
+#[cfg(feature = "async")]
+pub use crate::dnstap_async_reader::AsyncDNSTapReader;
 pub use crate::dnstap_builder::*;
+pub use crate::dnstap_collector::{
+    ConnectionInfo, DNSTapCollector, DNSTapPendingCollector, PeerAddress,
+};
+pub use crate::dnstap_reader::{DNSTapReader, Frames};
+#[cfg(feature = "serde")]
+pub use crate::dnstap_text::JsonText;
+pub use crate::dnstap_text::{FullText, QuietText, YamlText};
 pub use crate::dnstap_writer::{DNSTapPendingWriter, DNSTapWriter, Sender};
+pub use crate::pcap_reader::PcapReader;
+pub use crate::pcap_writer::PcapWriter;
+pub use crate::timestamp::{Clock, SystemClock};
+pub use crate::transform::{ClientSubnetRedaction, EdnsRedaction, PacketMinimization};
@@ -0,0 +1,551 @@
+use std::io::{self, Read};
+use std::time;
+
+use crate::frame_stream::{invalid_data, read_exact_or_eof};
+
+const PCAP_MAGIC_USEC: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NSEC: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER_BLOCK: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const PCAPNG_PACKET_BLOCK: u32 = 0x0000_0002;
+const PCAPNG_SIMPLE_PACKET_BLOCK: u32 = 0x0000_0003;
+const PCAPNG_ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+const PCAPNG_OPTION_IF_TSRESOL: u16 = 9;
+
+/// Maximum size of a record or block we are willing to allocate.
+const MAX_RECORD_SIZE: usize = 16_777_216;
+
+/// A packet read from a capture file.
+pub struct CapturedPacket {
+    pub ts: time::Duration,
+    pub linktype: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
+struct Interface {
+    linktype: u32,
+    /// Number of timestamp units per second
+    ts_units: u64,
+}
+
+enum Format {
+    Pcap {
+        big_endian: bool,
+        nanos: bool,
+        linktype: u32,
+    },
+    PcapNg {
+        big_endian: bool,
+        interfaces: Vec<Interface>,
+    },
+}
+
+/// Reads packets from a classic pcap or a pcapng file.
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    format: Format,
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut reader: R) -> io::Result<CaptureReader<R>> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if u32::from_le_bytes(magic) == PCAPNG_SECTION_HEADER_BLOCK {
+            let mut capture_reader = CaptureReader {
+                reader,
+                format: Format::PcapNg {
+                    big_endian: false,
+                    interfaces: vec![],
+                },
+            };
+            capture_reader.read_section_header()?;
+            return Ok(capture_reader);
+        }
+        let (big_endian, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAP_MAGIC_USEC, _) => (false, false),
+            (PCAP_MAGIC_NSEC, _) => (false, true),
+            (_, PCAP_MAGIC_USEC) => (true, false),
+            (_, PCAP_MAGIC_NSEC) => (true, true),
+            _ => return Err(invalid_data("Not a pcap or pcapng file")),
+        };
+        let mut header = [0u8; 20];
+        reader.read_exact(&mut header)?;
+        let linktype = u32_at(&header, 16, big_endian) & 0x0fff_ffff;
+        Ok(CaptureReader {
+            reader,
+            format: Format::Pcap {
+                big_endian,
+                nanos,
+                linktype,
+            },
+        })
+    }
+
+    /// Returns the next packet, or `None` at the end of the file.
+    pub fn next_packet(&mut self) -> io::Result<Option<CapturedPacket>> {
+        match self.format {
+            Format::Pcap {
+                big_endian,
+                nanos,
+                linktype,
+            } => {
+                let mut header = [0u8; 16];
+                if !read_exact_or_eof(&mut self.reader, &mut header)? {
+                    return Ok(None);
+                }
+                let secs = u64::from(u32_at(&header, 0, big_endian));
+                let frac = u32_at(&header, 4, big_endian);
+                let caplen = u32_at(&header, 8, big_endian) as usize;
+                if caplen > MAX_RECORD_SIZE {
+                    return Err(invalid_data("Record too large"));
+                }
+                let mut data = vec![0u8; caplen];
+                self.reader.read_exact(&mut data)?;
+                let nanos = if nanos {
+                    frac
+                } else {
+                    frac.saturating_mul(1000)
+                };
+                let ts = time::Duration::new(secs, nanos.min(999_999_999));
+                Ok(Some(CapturedPacket { ts, linktype, data }))
+            }
+            Format::PcapNg { .. } => self.next_pcapng_packet(),
+        }
+    }
+
+    fn read_block(&mut self) -> io::Result<Option<(u32, Vec<u8>)>> {
+        let mut header = [0u8; 8];
+        if !read_exact_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        if u32::from_le_bytes([header[0], header[1], header[2], header[3]])
+            == PCAPNG_SECTION_HEADER_BLOCK
+        {
+            // A new section starts, possibly with a different byte order
+            let body = self.read_section_header_body(&header[4..8])?;
+            return Ok(Some((PCAPNG_SECTION_HEADER_BLOCK, body)));
+        }
+        let big_endian = self.big_endian();
+        let block_type = u32_at(&header, 0, big_endian);
+        let total_len = u32_at(&header, 4, big_endian) as usize;
+        if !(12..=MAX_RECORD_SIZE).contains(&total_len) {
+            return Err(invalid_data("Invalid block length"));
+        }
+        let mut body = vec![0u8; total_len - 8];
+        self.reader.read_exact(&mut body)?;
+        body.truncate(total_len - 12);
+        Ok(Some((block_type, body)))
+    }
+
+    fn big_endian(&self) -> bool {
+        match self.format {
+            Format::Pcap { big_endian, .. } | Format::PcapNg { big_endian, .. } => big_endian,
+        }
+    }
+
+    fn read_section_header(&mut self) -> io::Result<()> {
+        let mut total_len = [0u8; 4];
+        self.reader.read_exact(&mut total_len)?;
+        self.read_section_header_body(&total_len).map(|_| ())
+    }
+
+    fn read_section_header_body(&mut self, total_len: &[u8]) -> io::Result<Vec<u8>> {
+        let mut byte_order_magic = [0u8; 4];
+        self.reader.read_exact(&mut byte_order_magic)?;
+        let big_endian = match u32::from_le_bytes(byte_order_magic) {
+            PCAPNG_BYTE_ORDER_MAGIC => false,
+            x if x.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+            _ => return Err(invalid_data("Invalid pcapng byte order magic")),
+        };
+        let total_len = u32_at(total_len, 0, big_endian) as usize;
+        if !(28..=MAX_RECORD_SIZE).contains(&total_len) {
+            return Err(invalid_data("Invalid block length"));
+        }
+        let mut body = vec![0u8; total_len - 12];
+        self.reader.read_exact(&mut body)?;
+        self.format = Format::PcapNg {
+            big_endian,
+            interfaces: vec![],
+        };
+        Ok(body)
+    }
+
+    fn next_pcapng_packet(&mut self) -> io::Result<Option<CapturedPacket>> {
+        loop {
+            let (block_type, body) = match self.read_block()? {
+                None => return Ok(None),
+                Some(block) => block,
+            };
+            let big_endian = self.big_endian();
+            let interfaces = match self.format {
+                Format::PcapNg {
+                    ref mut interfaces, ..
+                } => interfaces,
+                _ => unreachable!(),
+            };
+            let (interface_id, ts, data) = match block_type {
+                PCAPNG_INTERFACE_DESCRIPTION_BLOCK => {
+                    if body.len() < 8 {
+                        return Err(invalid_data("Short interface description block"));
+                    }
+                    let linktype = u32::from(u16_at(&body, 0, big_endian));
+                    let ts_units = if_tsresol(&body[8..], big_endian);
+                    interfaces.push(Interface { linktype, ts_units });
+                    continue;
+                }
+                PCAPNG_ENHANCED_PACKET_BLOCK => {
+                    if body.len() < 20 {
+                        return Err(invalid_data("Short enhanced packet block"));
+                    }
+                    let ts = (u64::from(u32_at(&body, 4, big_endian)) << 32)
+                        | u64::from(u32_at(&body, 8, big_endian));
+                    let caplen = u32_at(&body, 12, big_endian) as usize;
+                    let data = body
+                        .get(20..20 + caplen)
+                        .ok_or_else(|| invalid_data("Truncated enhanced packet block"))?;
+                    (u32_at(&body, 0, big_endian) as usize, Some(ts), data)
+                }
+                PCAPNG_PACKET_BLOCK => {
+                    if body.len() < 20 {
+                        return Err(invalid_data("Short packet block"));
+                    }
+                    let ts = (u64::from(u32_at(&body, 4, big_endian)) << 32)
+                        | u64::from(u32_at(&body, 8, big_endian));
+                    let caplen = u32_at(&body, 12, big_endian) as usize;
+                    let data = body
+                        .get(20..20 + caplen)
+                        .ok_or_else(|| invalid_data("Truncated packet block"))?;
+                    (usize::from(u16_at(&body, 0, big_endian)), Some(ts), data)
+                }
+                PCAPNG_SIMPLE_PACKET_BLOCK => {
+                    if body.len() < 4 {
+                        return Err(invalid_data("Short simple packet block"));
+                    }
+                    let len = (u32_at(&body, 0, big_endian) as usize).min(body.len() - 4);
+                    (0, None, &body[4..4 + len])
+                }
+                _ => continue,
+            };
+            let interface = *interfaces
+                .get(interface_id)
+                .ok_or_else(|| invalid_data("Unknown interface"))?;
+            let ts = ts
+                .map(|ts| {
+                    let secs = ts / interface.ts_units;
+                    let frac = ts % interface.ts_units;
+                    let nanos =
+                        (u128::from(frac) * 1_000_000_000 / u128::from(interface.ts_units)) as u32;
+                    time::Duration::new(secs, nanos)
+                })
+                .unwrap_or_default();
+            return Ok(Some(CapturedPacket {
+                ts,
+                linktype: interface.linktype,
+                data: data.to_vec(),
+            }));
+        }
+    }
+}
+
+/// Returns the number of timestamp units per second of an interface.
+fn if_tsresol(mut options: &[u8], big_endian: bool) -> u64 {
+    while options.len() >= 4 {
+        let code = u16_at(options, 0, big_endian);
+        let len = usize::from(u16_at(options, 2, big_endian));
+        let value = match options.get(4..4 + len) {
+            Some(value) => value,
+            None => break,
+        };
+        if code == PCAPNG_OPTION_IF_TSRESOL && len == 1 {
+            let resol = value[0];
+            let exp = u32::from(resol & 0x7f);
+            let units = if resol & 0x80 == 0 {
+                10u64.checked_pow(exp)
+            } else {
+                2u64.checked_pow(exp)
+            };
+            return units.unwrap_or(1_000_000).max(1);
+        }
+        if code == 0 {
+            break;
+        }
+        options = options.get(4 + ((len + 3) & !3)..).unwrap_or_default();
+    }
+    1_000_000
+}
+
+#[inline]
+fn u16_at(x: &[u8], offset: usize, big_endian: bool) -> u16 {
+    let b = [x[offset], x[offset + 1]];
+    if big_endian {
+        u16::from_be_bytes(b)
+    } else {
+        u16::from_le_bytes(b)
+    }
+}
+
+#[inline]
+fn u32_at(x: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let b = [x[offset], x[offset + 1], x[offset + 2], x[offset + 3]];
+    if big_endian {
+        u32::from_be_bytes(b)
+    } else {
+        u32::from_le_bytes(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pcap(magic: u32, big_endian: bool, linktype: u32, records: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let u16_bytes = |x: u16| {
+            if big_endian {
+                x.to_be_bytes()
+            } else {
+                x.to_le_bytes()
+            }
+        };
+        let u32_bytes = |x: u32| {
+            if big_endian {
+                x.to_be_bytes()
+            } else {
+                x.to_le_bytes()
+            }
+        };
+        let mut file = vec![];
+        file.extend_from_slice(&u32_bytes(magic));
+        file.extend_from_slice(&u16_bytes(2));
+        file.extend_from_slice(&u16_bytes(4));
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&u32_bytes(65_535));
+        file.extend_from_slice(&u32_bytes(linktype));
+        for &(secs, frac, data) in records {
+            file.extend_from_slice(&u32_bytes(secs));
+            file.extend_from_slice(&u32_bytes(frac));
+            file.extend_from_slice(&u32_bytes(data.len() as u32));
+            file.extend_from_slice(&u32_bytes(data.len() as u32 + 100));
+            file.extend_from_slice(data);
+        }
+        file
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8], big_endian: bool) -> Vec<u8> {
+        let u32_bytes = |x: u32| {
+            if big_endian {
+                x.to_be_bytes()
+            } else {
+                x.to_le_bytes()
+            }
+        };
+        let padded_len = (body.len() + 3) & !3;
+        let total_len = (12 + padded_len) as u32;
+        let mut block = vec![];
+        block.extend_from_slice(&u32_bytes(block_type));
+        block.extend_from_slice(&u32_bytes(total_len));
+        block.extend_from_slice(body);
+        block.resize(8 + padded_len, 0);
+        block.extend_from_slice(&u32_bytes(total_len));
+        block
+    }
+
+    fn section_header(big_endian: bool) -> Vec<u8> {
+        let mut body = vec![];
+        if big_endian {
+            body.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_be_bytes());
+            body.extend_from_slice(&[0, 1, 0, 0]);
+        } else {
+            body.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+            body.extend_from_slice(&[1, 0, 0, 0]);
+        }
+        body.extend_from_slice(&[0xff; 8]);
+        pcapng_block(PCAPNG_SECTION_HEADER_BLOCK, &body, big_endian)
+    }
+
+    fn interface(linktype: u16, tsresol: Option<u8>) -> Vec<u8> {
+        let mut body = vec![];
+        body.extend_from_slice(&linktype.to_le_bytes());
+        body.extend_from_slice(&[0, 0]);
+        body.extend_from_slice(&65_535u32.to_le_bytes());
+        if let Some(tsresol) = tsresol {
+            // An unknown option first, then if_tsresol and opt_endofopt
+            body.extend_from_slice(&[2, 0, 3, 0, b'e', b't', b'h', 0]);
+            body.extend_from_slice(&[9, 0, 1, 0, tsresol, 0, 0, 0]);
+            body.extend_from_slice(&[0, 0, 0, 0]);
+        }
+        pcapng_block(PCAPNG_INTERFACE_DESCRIPTION_BLOCK, &body, false)
+    }
+
+    fn enhanced_packet(interface_id: u32, ts: u64, data: &[u8]) -> Vec<u8> {
+        let mut body = vec![];
+        body.extend_from_slice(&interface_id.to_le_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ts as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        pcapng_block(PCAPNG_ENHANCED_PACKET_BLOCK, &body, false)
+    }
+
+    fn read_all(file: &[u8]) -> io::Result<Vec<(time::Duration, u32, Vec<u8>)>> {
+        let mut capture_reader = CaptureReader::new(file)?;
+        let mut packets = vec![];
+        while let Some(packet) = capture_reader.next_packet()? {
+            packets.push((packet.ts, packet.linktype, packet.data));
+        }
+        Ok(packets)
+    }
+
+    #[test]
+    fn classic_pcap() {
+        let records: &[(u32, u32, &[u8])] = &[(1_700_000_000, 123_456, b"first"), (1, 0, b"")];
+        let expected = vec![
+            (
+                time::Duration::new(1_700_000_000, 123_456_000),
+                1,
+                b"first".to_vec(),
+            ),
+            (time::Duration::new(1, 0), 1, vec![]),
+        ];
+        assert_eq!(
+            read_all(&pcap(PCAP_MAGIC_USEC, false, 1, records)).unwrap(),
+            expected
+        );
+        assert_eq!(
+            read_all(&pcap(PCAP_MAGIC_USEC, true, 1, records)).unwrap(),
+            expected
+        );
+
+        // Nanosecond timestamps, and FCS bits in the link type field
+        let records: &[(u32, u32, &[u8])] = &[(1_700_000_000, 123_456_789, b"first")];
+        let packets = read_all(&pcap(PCAP_MAGIC_NSEC, true, 0x1000_0065, records)).unwrap();
+        assert_eq!(
+            packets,
+            vec![(
+                time::Duration::new(1_700_000_000, 123_456_789),
+                101,
+                b"first".to_vec()
+            )]
+        );
+    }
+
+    #[test]
+    fn invalid_pcap() {
+        assert!(CaptureReader::new(&b"\x00\x01\x02\x03"[..]).is_err());
+        assert!(CaptureReader::new(&b"\xd4\xc3"[..]).is_err());
+
+        let mut file = pcap(PCAP_MAGIC_USEC, false, 1, &[(1, 0, b"truncated")]);
+        file.pop();
+        assert!(read_all(&file).is_err());
+
+        let mut file = pcap(PCAP_MAGIC_USEC, false, 1, &[]);
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&(MAX_RECORD_SIZE as u32 + 1).to_le_bytes());
+        file.extend_from_slice(&[0; 4]);
+        assert!(read_all(&file).is_err());
+    }
+
+    #[test]
+    fn pcapng_blocks() {
+        let mut file = section_header(false);
+        file.extend_from_slice(&interface(1, None));
+        file.extend_from_slice(&interface(101, Some(9)));
+        file.extend_from_slice(&interface(113, Some(0x80 | 10)));
+        // An unknown block is skipped
+        file.extend_from_slice(&pcapng_block(0x0bad, b"skipped", false));
+        file.extend_from_slice(&enhanced_packet(0, 1_700_000_000_123_456, b"usec"));
+        file.extend_from_slice(&enhanced_packet(1, 1_700_000_000_123_456_789, b"nsec"));
+        file.extend_from_slice(&enhanced_packet(2, 1024 * 5 + 512, b"pow2"));
+        let mut simple_packet = 3u32.to_le_bytes().to_vec();
+        simple_packet.extend_from_slice(b"spb");
+        file.extend_from_slice(&pcapng_block(
+            PCAPNG_SIMPLE_PACKET_BLOCK,
+            &simple_packet,
+            false,
+        ));
+        assert_eq!(
+            read_all(&file).unwrap(),
+            vec![
+                (
+                    time::Duration::new(1_700_000_000, 123_456_000),
+                    1,
+                    b"usec".to_vec()
+                ),
+                (
+                    time::Duration::new(1_700_000_000, 123_456_789),
+                    101,
+                    b"nsec".to_vec()
+                ),
+                (time::Duration::new(5, 500_000_000), 113, b"pow2".to_vec()),
+                (time::Duration::ZERO, 1, b"spb".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn pcapng_sections() {
+        let mut file = section_header(false);
+        file.extend_from_slice(&interface(1, None));
+        file.extend_from_slice(&enhanced_packet(0, 1_000_000, b"first"));
+        // A big-endian section resets the interfaces
+        file.extend_from_slice(&section_header(true));
+        let mut body = vec![];
+        body.extend_from_slice(&101u16.to_be_bytes());
+        body.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff]);
+        file.extend_from_slice(&pcapng_block(
+            PCAPNG_INTERFACE_DESCRIPTION_BLOCK,
+            &body,
+            true,
+        ));
+        let mut body = vec![];
+        body.extend_from_slice(&0u32.to_be_bytes());
+        body.extend_from_slice(&0u32.to_be_bytes());
+        body.extend_from_slice(&2_000_000u32.to_be_bytes());
+        body.extend_from_slice(&6u32.to_be_bytes());
+        body.extend_from_slice(&6u32.to_be_bytes());
+        body.extend_from_slice(b"second");
+        file.extend_from_slice(&pcapng_block(PCAPNG_ENHANCED_PACKET_BLOCK, &body, true));
+        assert_eq!(
+            read_all(&file).unwrap(),
+            vec![
+                (time::Duration::new(1, 0), 1, b"first".to_vec()),
+                (time::Duration::new(2, 0), 101, b"second".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_pcapng() {
+        // Packet for an interface that wasn't described
+        let mut file = section_header(false);
+        file.extend_from_slice(&enhanced_packet(0, 0, b"packet"));
+        assert!(read_all(&file).is_err());
+
+        // Captured length past the end of the block
+        let mut file = section_header(false);
+        file.extend_from_slice(&interface(1, None));
+        let mut block = enhanced_packet(0, 0, b"packet");
+        block[20..24].copy_from_slice(&100u32.to_le_bytes());
+        file.extend_from_slice(&block);
+        assert!(read_all(&file).is_err());
+
+        // Invalid block length
+        let mut file = section_header(false);
+        file.extend_from_slice(&PCAPNG_ENHANCED_PACKET_BLOCK.to_le_bytes());
+        file.extend_from_slice(&8u32.to_le_bytes());
+        assert!(read_all(&file).is_err());
+
+        // Truncated block
+        let mut file = section_header(false);
+        file.extend_from_slice(&interface(1, None));
+        file.truncate(file.len() - 2);
+        assert!(read_all(&file).is_err());
+
+        // Invalid byte order magic
+        let mut file = section_header(false);
+        file[8] = 0;
+        assert!(CaptureReader::new(&file[..]).is_err());
+    }
+}
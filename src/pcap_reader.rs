@@ -0,0 +1,837 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::time;
+
+use crate::dns_message::*;
+use crate::dnstap_builder::PcapReaderBuilder;
+use crate::pcap_file::CaptureReader;
+use crate::transform::Transforms;
+use crate::{SocketFamily, SocketProtocol};
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW_BSD: u32 = 12;
+const LINKTYPE_RAW_OPENBSD: u32 = 14;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+
+const IPPROTO_HOPOPTS: u8 = 0;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ROUTING: u8 = 43;
+const IPPROTO_FRAGMENT: u8 = 44;
+const IPPROTO_DSTOPTS: u8 = 60;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+
+const DNS_HEADER_SIZE: usize = 12;
+
+/// Maximum amount of data buffered for a single TCP flow.
+const MAX_TCP_BUFFER_SIZE: usize = 131_072;
+/// Maximum number of out-of-order segments buffered for a single TCP flow.
+const MAX_TCP_PENDING_SEGMENTS: usize = 64;
+/// Maximum number of TCP flows tracked at the same time.
+const MAX_TCP_FLOWS: usize = 65_536;
+
+type Endpoint = (IpAddr, u16);
+
+/// `PcapReader` extracts DNS messages from a pcap or pcapng capture.
+///
+/// DNS over UDP is read directly, and DNS over TCP is reassembled before being split into
+/// individual messages. Packets sent to the server are turned into queries, packets sent by
+/// the server into responses, using the message types configured in `PcapReaderBuilder`.
+/// IP fragments and packets that don't involve the server are ignored.
+///
+/// # Example
+/// ```no_run
+/// use dnstap::{MessageType, PcapReaderBuilder};
+///
+/// let reader = PcapReaderBuilder::default()
+///     .server_addr("192.0.2.53".parse().unwrap())
+///     .message_types(MessageType::AUTH_QUERY, MessageType::AUTH_RESPONSE)
+///     .open("/tmp/dns.pcap")
+///     .unwrap();
+/// for dns_message in reader {
+///     println!("{:?}", dns_message.unwrap().message_type);
+/// }
+/// ```
+pub struct PcapReader<R: Read> {
+    capture_reader: CaptureReader<R>,
+    builder: PcapReaderBuilder,
+    tcp_flows: HashMap<(Endpoint, Endpoint), TcpFlow>,
+    dns_messages: VecDeque<DNSMessage>,
+    transforms: Transforms,
+}
+
+#[derive(Default)]
+struct TcpFlow {
+    next_seq: Option<u32>,
+    buf: Vec<u8>,
+    pending: BTreeMap<u32, Vec<u8>>,
+}
+
+impl TcpFlow {
+    /// Adds a segment to the stream, and returns `false` if the flow should be discarded.
+    fn add_segment(&mut self, seq: u32, mut payload: &[u8]) -> bool {
+        let next_seq = *self.next_seq.get_or_insert(seq);
+        let delta = seq.wrapping_sub(next_seq) as i32;
+        if delta > 0 {
+            if self.pending.len() >= MAX_TCP_PENDING_SEGMENTS {
+                return false;
+            }
+            self.pending.entry(seq).or_insert_with(|| payload.to_vec());
+            return true;
+        }
+        // Skip data that was already received
+        let overlap = delta.unsigned_abs() as usize;
+        if overlap >= payload.len() {
+            return true;
+        }
+        payload = &payload[overlap..];
+        self.buf.extend_from_slice(payload);
+        let mut next_seq = next_seq.wrapping_add(payload.len() as u32);
+        loop {
+            let (seq, segment) = match self.pending.iter().next() {
+                Some((&seq, _)) if seq.wrapping_sub(next_seq) as i32 <= 0 => {
+                    let segment = self.pending.remove(&seq).unwrap();
+                    (seq, segment)
+                }
+                _ => break,
+            };
+            let overlap = next_seq.wrapping_sub(seq) as usize;
+            if overlap < segment.len() {
+                self.buf.extend_from_slice(&segment[overlap..]);
+                next_seq = next_seq.wrapping_add((segment.len() - overlap) as u32);
+            }
+        }
+        self.next_seq = Some(next_seq);
+        self.buf.len() <= MAX_TCP_BUFFER_SIZE
+    }
+
+    /// Removes and returns the next complete DNS message from the stream.
+    fn next_dns_packet(&mut self) -> Option<Vec<u8>> {
+        if self.buf.len() < 2 {
+            return None;
+        }
+        let len = usize::from(u16::from_be_bytes([self.buf[0], self.buf[1]]));
+        if self.buf.len() < 2 + len {
+            return None;
+        }
+        let dns_packet = self.buf[2..2 + len].to_vec();
+        self.buf.drain(..2 + len);
+        Some(dns_packet)
+    }
+}
+
+impl PcapReader<BufReader<File>> {
+    /// Opens a pcap or pcapng file.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        builder: PcapReaderBuilder,
+    ) -> io::Result<PcapReader<BufReader<File>>> {
+        PcapReader::new(BufReader::new(File::open(path)?), builder)
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Creates a `PcapReader` object reading a pcap or pcapng capture from `reader`. Using a
+    /// buffered reader is recommended.
+    pub fn new(reader: R, builder: PcapReaderBuilder) -> io::Result<PcapReader<R>> {
+        Ok(PcapReader {
+            capture_reader: CaptureReader::new(reader)?,
+            transforms: Transforms::new(
+                builder.anonymization.as_ref(),
+                builder.edns_redaction.clone(),
+                builder.packet_minimization,
+            ),
+            builder,
+            tcp_flows: HashMap::new(),
+            dns_messages: VecDeque::new(),
+        })
+    }
+
+    /// Returns the next DNS message, or `None` at the end of the capture.
+    pub fn read_message(&mut self) -> io::Result<Option<DNSMessage>> {
+        loop {
+            if let Some(dns_message) = self.dns_messages.pop_front() {
+                return Ok(Some(dns_message));
+            }
+            let packet = match self.capture_reader.next_packet()? {
+                None => return Ok(None),
+                Some(packet) => packet,
+            };
+            self.process_packet(packet.ts, packet.linktype, &packet.data);
+        }
+    }
+
+    fn process_packet(&mut self, ts: time::Duration, linktype: u32, data: &[u8]) {
+        let ip_packet = match link_payload(linktype, data) {
+            None => return,
+            Some(ip_packet) => ip_packet,
+        };
+        let (src_ip, dst_ip, ip_proto, transport) = match ip_payload(ip_packet) {
+            None => return,
+            Some(x) => x,
+        };
+        match ip_proto {
+            IPPROTO_UDP if transport.len() >= 8 => {
+                let src = (src_ip, u16::from_be_bytes([transport[0], transport[1]]));
+                let dst = (dst_ip, u16::from_be_bytes([transport[2], transport[3]]));
+                let udp_len = usize::from(u16::from_be_bytes([transport[4], transport[5]]));
+                let end = if udp_len >= 8 {
+                    udp_len.min(transport.len())
+                } else {
+                    transport.len()
+                };
+                let dns_packet = &transport[8..end];
+                self.push_dns_message(ts, src, dst, SocketProtocol::UDP, dns_packet);
+            }
+            IPPROTO_TCP if transport.len() >= 20 => {
+                let src = (src_ip, u16::from_be_bytes([transport[0], transport[1]]));
+                let dst = (dst_ip, u16::from_be_bytes([transport[2], transport[3]]));
+                if self.is_query(src, dst, None).is_none() {
+                    return;
+                }
+                let seq =
+                    u32::from_be_bytes([transport[4], transport[5], transport[6], transport[7]]);
+                let data_offset = usize::from(transport[12] >> 4) * 4;
+                let flags = transport[13];
+                let payload = match transport.get(data_offset..) {
+                    Some(payload) if data_offset >= 20 => payload,
+                    _ => return,
+                };
+                self.process_tcp_segment(ts, src, dst, seq, flags, payload);
+            }
+            _ => {}
+        }
+    }
+
+    fn process_tcp_segment(
+        &mut self,
+        ts: time::Duration,
+        src: Endpoint,
+        dst: Endpoint,
+        seq: u32,
+        flags: u8,
+        payload: &[u8],
+    ) {
+        let key = (src, dst);
+        if flags & TCP_SYN != 0 {
+            let flow = TcpFlow {
+                next_seq: Some(seq.wrapping_add(1)),
+                ..TcpFlow::default()
+            };
+            if self.tcp_flows.len() < MAX_TCP_FLOWS || self.tcp_flows.contains_key(&key) {
+                self.tcp_flows.insert(key, flow);
+            }
+        } else if !payload.is_empty()
+            && !self.tcp_flows.contains_key(&key)
+            && self.tcp_flows.len() < MAX_TCP_FLOWS
+        {
+            // The capture started in the middle of the connection
+            self.tcp_flows.insert(key, TcpFlow::default());
+        }
+        let mut dns_packets = vec![];
+        if let Some(flow) = self.tcp_flows.get_mut(&key) {
+            if !payload.is_empty() && !flow.add_segment(seq, payload) {
+                self.tcp_flows.remove(&key);
+                return;
+            }
+            while let Some(dns_packet) = flow.next_dns_packet() {
+                dns_packets.push(dns_packet);
+            }
+        }
+        if flags & (TCP_FIN | TCP_RST) != 0 {
+            self.tcp_flows.remove(&key);
+        }
+        for dns_packet in dns_packets {
+            self.push_dns_message(ts, src, dst, SocketProtocol::TCP, &dns_packet);
+        }
+    }
+
+    /// Returns `true` if a packet is a query, `false` if it is a response, and `None` if
+    /// it doesn't involve the server.
+    fn is_query(&self, src: Endpoint, dst: Endpoint, dns_packet: Option<&[u8]>) -> Option<bool> {
+        let is_server = |endpoint: Endpoint| {
+            endpoint.1 == self.builder.server_port
+                && (self.builder.server_addresses.is_empty()
+                    || self.builder.server_addresses.contains(&endpoint.0))
+        };
+        match (is_server(src), is_server(dst)) {
+            (false, true) => Some(true),
+            (true, false) => Some(false),
+            (true, true) => match dns_packet {
+                Some(dns_packet) => dns_packet.get(2).map(|flags| flags & 0x80 == 0),
+                None => Some(true),
+            },
+            (false, false) => None,
+        }
+    }
+
+    fn push_dns_message(
+        &mut self,
+        ts: time::Duration,
+        src: Endpoint,
+        dst: Endpoint,
+        socket_protocol: SocketProtocol,
+        dns_packet: &[u8],
+    ) {
+        if dns_packet.len() < DNS_HEADER_SIZE {
+            return;
+        }
+        let is_query = match self.is_query(src, dst, Some(dns_packet)) {
+            None => return,
+            Some(is_query) => is_query,
+        };
+        let (client, server, message_type) = if is_query {
+            (src, dst, self.builder.query_type)
+        } else {
+            (dst, src, self.builder.response_type)
+        };
+        let socket_family = match client.0 {
+            IpAddr::V4(_) => SocketFamily::INET,
+            IpAddr::V6(_) => SocketFamily::INET6,
+        };
+        let (query_time, query_packet, response_time, response_packet) = if is_query {
+            (Some(ts), Some(dns_packet.to_vec()), None, None)
+        } else {
+            (None, None, Some(ts), Some(dns_packet.to_vec()))
+        };
+        let mut dns_message = DNSMessage {
+            identity: self.builder.identity.clone(),
+            version: self.builder.version.clone(),
+            extra: self.builder.extra.clone(),
+            message_type,
+            socket_family: Some(socket_family),
+            socket_protocol: Some(socket_protocol),
+            http_protocol: None,
+            query_address: Some(client.0),
+            query_port: Some(client.1),
+            query_time,
+            query_packet,
+            response_address: Some(server.0),
+            response_port: Some(server.1),
+            response_time,
+            response_packet,
+            bailiwick: None,
+            policy: None,
+            summary: None,
+        };
+        self.transforms.apply(&mut dns_message);
+        self.dns_messages.push_back(dns_message);
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = io::Result<DNSMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_message().transpose()
+    }
+}
+
+/// Returns the IP packet carried by a link-layer frame.
+fn link_payload(linktype: u32, data: &[u8]) -> Option<&[u8]> {
+    let (ethertype, payload) = match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut ethertype = u16::from_be_bytes([*data.get(12)?, *data.get(13)?]);
+            let mut offset = 14;
+            while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+                ethertype = u16::from_be_bytes([*data.get(offset + 2)?, *data.get(offset + 3)?]);
+                offset += 4;
+            }
+            (Some(ethertype), data.get(offset..)?)
+        }
+        LINKTYPE_LINUX_SLL => (
+            Some(u16::from_be_bytes([*data.get(14)?, *data.get(15)?])),
+            data.get(16..)?,
+        ),
+        LINKTYPE_LINUX_SLL2 => (
+            Some(u16::from_be_bytes([*data.first()?, *data.get(1)?])),
+            data.get(20..)?,
+        ),
+        LINKTYPE_NULL | LINKTYPE_LOOP => (None, data.get(4..)?),
+        LINKTYPE_RAW | LINKTYPE_RAW_BSD | LINKTYPE_RAW_OPENBSD | LINKTYPE_IPV4 | LINKTYPE_IPV6 => {
+            (None, data)
+        }
+        _ => return None,
+    };
+    match ethertype {
+        None | Some(ETHERTYPE_IPV4) | Some(ETHERTYPE_IPV6) => Some(payload),
+        _ => None,
+    }
+}
+
+/// Returns the source address, destination address, protocol and payload of an IP packet.
+fn ip_payload(packet: &[u8]) -> Option<(IpAddr, IpAddr, u8, &[u8])> {
+    match packet.first()? >> 4 {
+        4 => {
+            let header_len = usize::from(packet[0] & 0x0f) * 4;
+            let total_len = match u16::from_be_bytes([*packet.get(2)?, *packet.get(3)?]) {
+                // Packets captured before TCP segmentation offload may have no total length
+                0 => packet.len(),
+                total_len => usize::from(total_len),
+            };
+            if header_len < 20 || total_len < header_len || packet.len() < header_len {
+                return None;
+            }
+            // Fragments can't be processed without reassembly
+            let fragment = u16::from_be_bytes([packet[6], packet[7]]);
+            if fragment & 0x3fff != 0 {
+                return None;
+            }
+            let src = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+            let dst = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+            let end = total_len.min(packet.len());
+            Some((src.into(), dst.into(), packet[9], &packet[header_len..end]))
+        }
+        6 => {
+            if packet.len() < 40 {
+                return None;
+            }
+            let payload_len = usize::from(u16::from_be_bytes([packet[4], packet[5]]));
+            let mut next_header = packet[6];
+            let src = ipv6_addr(&packet[8..24]);
+            let dst = ipv6_addr(&packet[24..40]);
+            let end = (40 + payload_len).min(packet.len());
+            let mut payload = &packet[40..end];
+            loop {
+                match next_header {
+                    IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS => {
+                        let len = (usize::from(*payload.get(1)?) + 1) * 8;
+                        next_header = payload[0];
+                        payload = payload.get(len..)?;
+                    }
+                    IPPROTO_FRAGMENT => return None,
+                    _ => return Some((src.into(), dst.into(), next_header, payload)),
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+fn ipv6_addr(x: &[u8]) -> Ipv6Addr {
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(x);
+    Ipv6Addr::from(octets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use crate::MessageType;
+
+    const CLIENT: &str = "192.0.2.1";
+    const SERVER: &str = "192.0.2.53";
+    const CLIENT6: &str = "2001:db8::1";
+    const SERVER6: &str = "2001:db8::53";
+
+    fn pcap(linktype: u32, frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = vec![];
+        file.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+        file.extend_from_slice(&[2, 0, 4, 0]);
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&65_535u32.to_le_bytes());
+        file.extend_from_slice(&linktype.to_le_bytes());
+        for (i, frame) in frames.iter().enumerate() {
+            file.extend_from_slice(&(1_700_000_000 + i as u32).to_le_bytes());
+            file.extend_from_slice(&0u32.to_le_bytes());
+            file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            file.extend_from_slice(frame);
+        }
+        file
+    }
+
+    fn read(builder: PcapReaderBuilder, linktype: u32, frames: &[Vec<u8>]) -> Vec<DNSMessage> {
+        builder
+            .reader(&pcap(linktype, frames)[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+
+    fn ethernet(ethertype: u16, ip_packet: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x02, 0, 0, 0, 0, 0x02, 0x02, 0, 0, 0, 0, 0x01];
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(ip_packet);
+        frame
+    }
+
+    fn ipv4(src: &str, dst: &str, ip_proto: u8, transport: &[u8]) -> Vec<u8> {
+        let (src, dst) = match (ip(src), ip(dst)) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => (src, dst),
+            _ => unreachable!(),
+        };
+        let mut packet = vec![0x45, 0];
+        packet.extend_from_slice(&(20 + transport.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0x40, 0, 64, ip_proto, 0, 0]);
+        packet.extend_from_slice(&src.octets());
+        packet.extend_from_slice(&dst.octets());
+        packet.extend_from_slice(transport);
+        packet
+    }
+
+    fn ipv6(src: &str, dst: &str, next_header: u8, payload: &[u8]) -> Vec<u8> {
+        let (src, dst) = match (ip(src), ip(dst)) {
+            (IpAddr::V6(src), IpAddr::V6(dst)) => (src, dst),
+            _ => unreachable!(),
+        };
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[next_header, 64]);
+        packet.extend_from_slice(&src.octets());
+        packet.extend_from_slice(&dst.octets());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn udp(src_port: u16, dst_port: u16, dns_packet: &[u8]) -> Vec<u8> {
+        let mut datagram = src_port.to_be_bytes().to_vec();
+        datagram.extend_from_slice(&dst_port.to_be_bytes());
+        datagram.extend_from_slice(&(8 + dns_packet.len() as u16).to_be_bytes());
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(dns_packet);
+        datagram
+    }
+
+    fn tcp(src_port: u16, dst_port: u16, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = src_port.to_be_bytes().to_vec();
+        segment.extend_from_slice(&dst_port.to_be_bytes());
+        segment.extend_from_slice(&seq.to_be_bytes());
+        segment.extend_from_slice(&[0, 0, 0, 0, 0x50, flags | 0x10, 0xff, 0xff, 0, 0, 0, 0]);
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    fn length_prefixed(dns_packet: &[u8]) -> Vec<u8> {
+        let mut data = (dns_packet.len() as u16).to_be_bytes().to_vec();
+        data.extend_from_slice(dns_packet);
+        data
+    }
+
+    fn expected(
+        message_type: MessageType,
+        socket_protocol: SocketProtocol,
+        client: (&str, u16),
+        server: (&str, u16),
+        secs: u64,
+        dns_packet: Vec<u8>,
+    ) -> DNSMessage {
+        let mut dns_message = DNSMessage::new(None, None, message_type);
+        dns_message.socket_family = Some(match ip(client.0) {
+            IpAddr::V4(_) => SocketFamily::INET,
+            IpAddr::V6(_) => SocketFamily::INET6,
+        });
+        dns_message.socket_protocol = Some(socket_protocol);
+        dns_message.query_address = Some(ip(client.0));
+        dns_message.query_port = Some(client.1);
+        dns_message.response_address = Some(ip(server.0));
+        dns_message.response_port = Some(server.1);
+        let ts = time::Duration::new(1_700_000_000 + secs, 0);
+        if message_type == MessageType::CLIENT_QUERY {
+            dns_message.query_time = Some(ts);
+            dns_message.query_packet = Some(dns_packet);
+        } else {
+            dns_message.response_time = Some(ts);
+            dns_message.response_packet = Some(dns_packet);
+        }
+        dns_message
+    }
+
+    #[test]
+    fn udp_over_ethernet() {
+        let query = dns_query(1, "example.com", 1);
+        let response = dns_response(1, "example.com", 1, &[192, 0, 2, 80]);
+        let frames = vec![
+            ethernet(
+                ETHERTYPE_IPV4,
+                &ipv4(CLIENT, SERVER, IPPROTO_UDP, &udp(40000, 53, &query)),
+            ),
+            ethernet(
+                ETHERTYPE_IPV4,
+                &ipv4(SERVER, CLIENT, IPPROTO_UDP, &udp(53, 40000, &response)),
+            ),
+            // Neither endpoint is the server
+            ethernet(
+                ETHERTYPE_IPV4,
+                &ipv4(CLIENT, SERVER, IPPROTO_UDP, &udp(40000, 5353, &query)),
+            ),
+            // Too short to be a DNS packet
+            ethernet(
+                ETHERTYPE_IPV4,
+                &ipv4(CLIENT, SERVER, IPPROTO_UDP, &udp(40000, 53, &query[..11])),
+            ),
+            // Not IP
+            ethernet(
+                0x0806,
+                &ipv4(CLIENT, SERVER, IPPROTO_UDP, &udp(40000, 53, &query)),
+            ),
+        ];
+        assert_eq!(
+            read(PcapReaderBuilder::default(), LINKTYPE_ETHERNET, &frames),
+            vec![
+                expected(
+                    MessageType::CLIENT_QUERY,
+                    SocketProtocol::UDP,
+                    (CLIENT, 40000),
+                    (SERVER, 53),
+                    0,
+                    query.clone(),
+                ),
+                expected(
+                    MessageType::CLIENT_RESPONSE,
+                    SocketProtocol::UDP,
+                    (CLIENT, 40000),
+                    (SERVER, 53),
+                    1,
+                    response,
+                ),
+            ]
+        );
+
+        // Only the configured server addresses are considered
+        let builder = PcapReaderBuilder::default().server_addr(ip("192.0.2.54"));
+        assert!(read(builder, LINKTYPE_ETHERNET, &frames).is_empty());
+    }
+
+    #[test]
+    fn vlan_and_link_types() {
+        let query = dns_query(1, "example.com", 1);
+        let ip_packet = ipv4(CLIENT, SERVER, IPPROTO_UDP, &udp(40000, 53, &query));
+        let mut vlan = ethernet(ETHERTYPE_QINQ, &[]);
+        vlan.extend_from_slice(&[0, 100]);
+        vlan.extend_from_slice(&ETHERTYPE_VLAN.to_be_bytes());
+        vlan.extend_from_slice(&[0, 200]);
+        vlan.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        vlan.extend_from_slice(&ip_packet);
+        let mut sll = vec![0, 0, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0];
+        sll.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        sll.extend_from_slice(&ip_packet);
+        let mut sll2 = ETHERTYPE_IPV4.to_be_bytes().to_vec();
+        sll2.extend_from_slice(&[0; 18]);
+        sll2.extend_from_slice(&ip_packet);
+        let mut null = 2u32.to_le_bytes().to_vec();
+        null.extend_from_slice(&ip_packet);
+
+        let message = expected(
+            MessageType::CLIENT_QUERY,
+            SocketProtocol::UDP,
+            (CLIENT, 40000),
+            (SERVER, 53),
+            0,
+            query,
+        );
+        for &(linktype, ref frame) in [
+            (LINKTYPE_ETHERNET, vlan),
+            (LINKTYPE_LINUX_SLL, sll),
+            (LINKTYPE_LINUX_SLL2, sll2),
+            (LINKTYPE_NULL, null),
+            (LINKTYPE_RAW, ip_packet.clone()),
+            (LINKTYPE_IPV4, ip_packet),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                read(
+                    PcapReaderBuilder::default(),
+                    linktype,
+                    std::slice::from_ref(frame)
+                ),
+                vec![message.clone()],
+                "link type {}",
+                linktype
+            );
+        }
+    }
+
+    #[test]
+    fn ipv4_total_length() {
+        let query = dns_query(1, "example.com", 1);
+        let mut ip_packet = ipv4(CLIENT, SERVER, IPPROTO_UDP, &udp(40000, 53, &query));
+        let message = expected(
+            MessageType::CLIENT_QUERY,
+            SocketProtocol::UDP,
+            (CLIENT, 40000),
+            (SERVER, 53),
+            0,
+            query,
+        );
+
+        // Ethernet padding is ignored
+        let mut padded = ip_packet.clone();
+        padded.extend_from_slice(&[0; 6]);
+        let frames = [padded];
+        assert_eq!(
+            read(PcapReaderBuilder::default(), LINKTYPE_RAW, &frames),
+            vec![message.clone()]
+        );
+
+        // Captured before TCP segmentation offload
+        ip_packet[2..4].copy_from_slice(&[0, 0]);
+        let frames = [ip_packet.clone()];
+        assert_eq!(
+            read(PcapReaderBuilder::default(), LINKTYPE_RAW, &frames),
+            vec![message]
+        );
+
+        // Shorter than the header
+        ip_packet[2..4].copy_from_slice(&[0, 19]);
+        let frames = [ip_packet.clone()];
+        assert!(read(PcapReaderBuilder::default(), LINKTYPE_RAW, &frames).is_empty());
+
+        // Fragments are skipped
+        ip_packet[2..4].copy_from_slice(&[0, 0]);
+        ip_packet[6] = 0x20;
+        let frames = [ip_packet];
+        assert!(read(PcapReaderBuilder::default(), LINKTYPE_RAW, &frames).is_empty());
+    }
+
+    #[test]
+    fn ipv6_extension_headers() {
+        let query = dns_query(1, "example.com", 28);
+        let mut payload = vec![IPPROTO_DSTOPTS, 0, 1, 4, 0, 0, 0, 0];
+        payload.extend_from_slice(&[IPPROTO_ROUTING, 1, 1, 12]);
+        payload.extend_from_slice(&[0; 12]);
+        payload.extend_from_slice(&[IPPROTO_UDP, 0, 0, 0, 0, 0, 0, 0]);
+        payload.extend_from_slice(&udp(40000, 53, &query));
+        let frames = [
+            ethernet(
+                ETHERTYPE_IPV6,
+                &ipv6(CLIENT6, SERVER6, IPPROTO_HOPOPTS, &payload),
+            ),
+            // Fragments are skipped
+            ethernet(
+                ETHERTYPE_IPV6,
+                &ipv6(CLIENT6, SERVER6, IPPROTO_FRAGMENT, &payload),
+            ),
+            // Truncated extension header
+            ethernet(
+                ETHERTYPE_IPV6,
+                &ipv6(CLIENT6, SERVER6, IPPROTO_HOPOPTS, &payload[..1]),
+            ),
+        ];
+        assert_eq!(
+            read(PcapReaderBuilder::default(), LINKTYPE_ETHERNET, &frames),
+            vec![expected(
+                MessageType::CLIENT_QUERY,
+                SocketProtocol::UDP,
+                (CLIENT6, 40000),
+                (SERVER6, 53),
+                0,
+                query,
+            )]
+        );
+    }
+
+    #[test]
+    fn tcp_reassembly() {
+        let queries: Vec<_> = (1..=3).map(|id| dns_query(id, "example.com", 1)).collect();
+        let response = dns_response(1, "example.com", 1, &[192, 0, 2, 80]);
+        let mut stream = vec![];
+        for query in &queries {
+            stream.extend_from_slice(&length_prefixed(query));
+        }
+        let isn = u32::MAX - 10;
+        let seq = |offset: usize| isn.wrapping_add(1 + offset as u32);
+        let segment = |seq: u32, flags: u8, payload: &[u8]| {
+            ipv4(
+                CLIENT,
+                SERVER,
+                IPPROTO_TCP,
+                &tcp(40000, 53, seq, flags, payload),
+            )
+        };
+        let frames = vec![
+            segment(isn, TCP_SYN, &[]),
+            // The first query is split in two, with the sequence number wrapping around
+            segment(seq(0), 0, &stream[..5]),
+            // The third query arrives before the end of the second one
+            segment(seq(84), 0, &stream[84..]),
+            segment(seq(5), 0, &stream[5..50]),
+            // Retransmission overlapping received data
+            segment(seq(30), 0, &stream[30..84]),
+            ipv4(
+                SERVER,
+                CLIENT,
+                IPPROTO_TCP,
+                &tcp(53, 40000, 1000, 0, &length_prefixed(&response)),
+            ),
+            segment(seq(stream.len()), TCP_FIN, &[]),
+        ];
+        let query_message = |secs, query: &Vec<u8>| {
+            expected(
+                MessageType::CLIENT_QUERY,
+                SocketProtocol::TCP,
+                (CLIENT, 40000),
+                (SERVER, 53),
+                secs,
+                query.clone(),
+            )
+        };
+        assert_eq!(
+            read(PcapReaderBuilder::default(), LINKTYPE_RAW, &frames),
+            vec![
+                query_message(3, &queries[0]),
+                query_message(4, &queries[1]),
+                query_message(4, &queries[2]),
+                expected(
+                    MessageType::CLIENT_RESPONSE,
+                    SocketProtocol::TCP,
+                    (CLIENT, 40000),
+                    (SERVER, 53),
+                    5,
+                    response,
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn tcp_flow_reset() {
+        let query = length_prefixed(&dns_query(1, "example.com", 1));
+        let segment = |seq: u32, flags: u8, payload: &[u8]| {
+            ipv6(
+                CLIENT6,
+                SERVER6,
+                IPPROTO_TCP,
+                &tcp(40000, 53, seq, flags, payload),
+            )
+        };
+        let frames = vec![
+            segment(100, TCP_SYN, &[]),
+            segment(101, 0, &query[..10]),
+            // The partial message is discarded with the flow
+            segment(111, TCP_RST, &[]),
+            segment(200, TCP_SYN, &[]),
+            segment(201, 0, &query),
+        ];
+        assert_eq!(
+            read(PcapReaderBuilder::default(), LINKTYPE_RAW, &frames),
+            vec![expected(
+                MessageType::CLIENT_QUERY,
+                SocketProtocol::TCP,
+                (CLIENT6, 40000),
+                (SERVER6, 53),
+                4,
+                dns_query(1, "example.com", 1),
+            )]
+        );
+    }
+}
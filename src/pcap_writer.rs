@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::time;
+
+use crate::dns_message::*;
+use crate::{SocketFamily, SocketProtocol};
+
+/// Magic number of classic pcap files with nanosecond timestamps.
+const PCAP_MAGIC_NSEC: u32 = 0xa1b2_3c4d;
+const LINKTYPE_ETHERNET: u32 = 1;
+const SNAPLEN: u32 = 262_144;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const DNS_PORT: u16 = 53;
+
+const ETHERNET_HEADER_SIZE: usize = 14;
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV6_HEADER_SIZE: usize = 40;
+const UDP_HEADER_SIZE: usize = 8;
+const TCP_HEADER_SIZE: usize = 20;
+
+type Endpoint = (IpAddr, u16);
+
+/// `PcapWriter` converts DNS messages into a classic pcap file that can be opened with
+/// Wireshark or tcpdump.
+///
+/// Every query and response packet is written as a regular DNS packet, with synthesized
+/// Ethernet, IP and UDP or TCP headers built from the addresses, ports, socket family and
+/// socket protocol of the message. Addresses and ports that are not set are replaced with
+/// unspecified addresses and the DNS port.
+///
+/// # Example
+/// ```no_run
+/// use dnstap::{DNSTapReader, PcapWriter};
+///
+/// let reader = DNSTapReader::open("/tmp/dnstap.log").unwrap();
+/// let mut pcap_writer = PcapWriter::create("/tmp/dnstap.pcap").unwrap();
+/// for dns_message in reader {
+///     pcap_writer.write_message(&dns_message.unwrap()).unwrap();
+/// }
+/// pcap_writer.flush().unwrap();
+/// ```
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    tcp_seqs: HashMap<(Endpoint, Endpoint), u32>,
+}
+
+impl PcapWriter<BufWriter<File>> {
+    /// Creates a pcap file.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<PcapWriter<BufWriter<File>>> {
+        PcapWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Creates a `PcapWriter` object, and writes the pcap file header.
+    pub fn new(mut writer: W) -> io::Result<PcapWriter<W>> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MAGIC_NSEC.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&0i32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        writer.write_all(&header)?;
+        Ok(PcapWriter {
+            writer,
+            tcp_seqs: HashMap::new(),
+        })
+    }
+
+    /// Writes the query and response packets of a DNS message, if present.
+    pub fn write_message(&mut self, dns_message: &DNSMessage) -> io::Result<()> {
+        let (query_address, response_address) = addresses(dns_message)?;
+        let query_endpoint = (query_address, dns_message.query_port.unwrap_or(DNS_PORT));
+        let response_endpoint = (
+            response_address,
+            dns_message.response_port.unwrap_or(DNS_PORT),
+        );
+        let tcp = match dns_message.socket_protocol {
+            Some(SocketProtocol::UDP)
+            | Some(SocketProtocol::DNSCryptUDP)
+            | Some(SocketProtocol::DOQ)
+            | None => false,
+            Some(_) => true,
+        };
+        if let Some(ref query_packet) = dns_message.query_packet {
+            let ts = dns_message.query_time.or(dns_message.response_time);
+            self.write_packet(ts, query_endpoint, response_endpoint, tcp, query_packet)?;
+        }
+        if let Some(ref response_packet) = dns_message.response_packet {
+            let ts = dns_message.response_time.or(dns_message.query_time);
+            self.write_packet(ts, response_endpoint, query_endpoint, tcp, response_packet)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_packet(
+        &mut self,
+        ts: Option<time::Duration>,
+        src: Endpoint,
+        dst: Endpoint,
+        tcp: bool,
+        dns_packet: &[u8],
+    ) -> io::Result<()> {
+        // IPv4 includes the header in the total length, IPv6 doesn't
+        let ip_header_size = if src.0.is_ipv4() { IPV4_HEADER_SIZE } else { 0 };
+        let transport_header_size = if tcp {
+            TCP_HEADER_SIZE + 2
+        } else {
+            UDP_HEADER_SIZE
+        };
+        if ip_header_size + transport_header_size + dns_packet.len() > usize::from(u16::MAX) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "DNS packet too large",
+            ));
+        }
+        let mut transport = Vec::with_capacity(transport_header_size + dns_packet.len());
+        let ip_proto = if tcp {
+            let ack = self.tcp_seqs.get(&(dst, src)).cloned().unwrap_or(1);
+            let seq = self.tcp_seqs.entry((src, dst)).or_insert(1);
+            transport.extend_from_slice(&src.1.to_be_bytes());
+            transport.extend_from_slice(&dst.1.to_be_bytes());
+            transport.extend_from_slice(&seq.to_be_bytes());
+            transport.extend_from_slice(&ack.to_be_bytes());
+            transport.push((TCP_HEADER_SIZE as u8 / 4) << 4);
+            transport.push(0x18); // PSH, ACK
+            transport.extend_from_slice(&u16::MAX.to_be_bytes());
+            transport.extend_from_slice(&[0, 0, 0, 0]);
+            transport.extend_from_slice(&(dns_packet.len() as u16).to_be_bytes());
+            transport.extend_from_slice(dns_packet);
+            *seq = seq.wrapping_add((2 + dns_packet.len()) as u32);
+            IPPROTO_TCP
+        } else {
+            let udp_len = UDP_HEADER_SIZE + dns_packet.len();
+            transport.extend_from_slice(&src.1.to_be_bytes());
+            transport.extend_from_slice(&dst.1.to_be_bytes());
+            transport.extend_from_slice(&(udp_len as u16).to_be_bytes());
+            transport.extend_from_slice(&[0, 0]);
+            transport.extend_from_slice(dns_packet);
+            IPPROTO_UDP
+        };
+        let checksum_offset = if tcp { 16 } else { 6 };
+        let mut checksum = transport_checksum(src.0, dst.0, ip_proto, &transport);
+        if !tcp && checksum == 0 {
+            checksum = 0xffff;
+        }
+        transport[checksum_offset..checksum_offset + 2].copy_from_slice(&checksum.to_be_bytes());
+
+        let mut frame =
+            Vec::with_capacity(ETHERNET_HEADER_SIZE + IPV6_HEADER_SIZE + transport.len());
+        frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 0x02]);
+        frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 0x01]);
+        match (src.0, dst.0) {
+            (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) => {
+                let total_len = IPV4_HEADER_SIZE + transport.len();
+                frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+                let mut ip = Vec::with_capacity(IPV4_HEADER_SIZE);
+                ip.push(0x45);
+                ip.push(0);
+                ip.extend_from_slice(&(total_len as u16).to_be_bytes());
+                ip.extend_from_slice(&[0, 0, 0x40, 0]); // DF
+                ip.push(64);
+                ip.push(ip_proto);
+                ip.extend_from_slice(&[0, 0]);
+                ip.extend_from_slice(&src_ip.octets());
+                ip.extend_from_slice(&dst_ip.octets());
+                let checksum = !ones_complement_sum(0, &ip);
+                ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+                frame.extend_from_slice(&ip);
+            }
+            (IpAddr::V6(src_ip), IpAddr::V6(dst_ip)) => {
+                frame.extend_from_slice(&ETHERTYPE_IPV6.to_be_bytes());
+                frame.extend_from_slice(&[0x60, 0, 0, 0]);
+                frame.extend_from_slice(&(transport.len() as u16).to_be_bytes());
+                frame.push(ip_proto);
+                frame.push(64);
+                frame.extend_from_slice(&src_ip.octets());
+                frame.extend_from_slice(&dst_ip.octets());
+            }
+            _ => unreachable!(),
+        }
+        frame.extend_from_slice(&transport);
+
+        let ts = ts.unwrap_or_default();
+        let mut record_header = Vec::with_capacity(16);
+        record_header.extend_from_slice(&(ts.as_secs() as u32).to_le_bytes());
+        record_header.extend_from_slice(&ts.subsec_nanos().to_le_bytes());
+        record_header.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        record_header.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        self.writer.write_all(&record_header)?;
+        self.writer.write_all(&frame)
+    }
+}
+
+/// Returns the query and response addresses, replacing missing ones with the unspecified
+/// address of the same family.
+fn addresses(dns_message: &DNSMessage) -> io::Result<(IpAddr, IpAddr)> {
+    let unspecified = match (
+        dns_message.socket_family,
+        dns_message.query_address.or(dns_message.response_address),
+    ) {
+        (_, Some(IpAddr::V6(_))) | (Some(SocketFamily::INET6), None) => {
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        }
+        _ => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    };
+    let query_address = dns_message.query_address.unwrap_or(unspecified);
+    let response_address = dns_message.response_address.unwrap_or(unspecified);
+    if query_address.is_ipv4() != response_address.is_ipv4() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Query and response addresses belong to different families",
+        ));
+    }
+    Ok((query_address, response_address))
+}
+
+fn ones_complement_sum(mut sum: u32, data: &[u8]) -> u16 {
+    let mut chunks = data.chunks_exact(2);
+    for chunk in &mut chunks {
+        sum += u32::from(u16::from_be_bytes([chunk[0], chunk[1]]));
+    }
+    if let [last] = chunks.remainder() {
+        sum += u32::from(*last) << 8;
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+fn transport_checksum(src: IpAddr, dst: IpAddr, ip_proto: u8, transport: &[u8]) -> u16 {
+    let mut pseudo_header = Vec::with_capacity(40);
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            pseudo_header.extend_from_slice(&src.octets());
+            pseudo_header.extend_from_slice(&dst.octets());
+            pseudo_header.extend_from_slice(&[0, ip_proto]);
+            pseudo_header.extend_from_slice(&(transport.len() as u16).to_be_bytes());
+        }
+        (src, dst) => {
+            pseudo_header.extend_from_slice(&ipv6_octets(src));
+            pseudo_header.extend_from_slice(&ipv6_octets(dst));
+            pseudo_header.extend_from_slice(&(transport.len() as u32).to_be_bytes());
+            pseudo_header.extend_from_slice(&[0, 0, 0, ip_proto]);
+        }
+    }
+    let sum = ones_complement_sum(0, &pseudo_header);
+    !ones_complement_sum(u32::from(sum), transport)
+}
+
+fn ipv6_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_support::*;
+    use crate::{MessageType, PcapReaderBuilder};
+
+    const QUERY_TIME: Duration = Duration::new(1_389_130_919, 318_932_123);
+    const RESPONSE_TIME: Duration = Duration::new(1_389_130_919, 321_000_456);
+
+    fn exchange(
+        socket_protocol: SocketProtocol,
+        client: &str,
+        server: &str,
+        id: u16,
+    ) -> DNSMessage {
+        let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_RESPONSE);
+        dns_message.socket_protocol = Some(socket_protocol);
+        dns_message.query_address = Some(ip(client));
+        dns_message.query_port = Some(40000);
+        dns_message.response_address = Some(ip(server));
+        dns_message.response_port = Some(53);
+        dns_message.query_time = Some(QUERY_TIME);
+        dns_message.query_packet = Some(dns_query(id, "example.com", 1));
+        dns_message.response_time = Some(RESPONSE_TIME);
+        dns_message.response_packet = Some(dns_response(id, "example.com", 1, &[192, 0, 2, 80]));
+        dns_message
+    }
+
+    /// Splits an exchange into the messages `PcapReader` returns for it.
+    fn split(dns_message: &DNSMessage) -> Vec<DNSMessage> {
+        let mut query = dns_message.clone();
+        query.message_type = MessageType::CLIENT_QUERY;
+        query.socket_family = Some(match query.query_address {
+            Some(IpAddr::V4(_)) => SocketFamily::INET,
+            _ => SocketFamily::INET6,
+        });
+        query.response_time = None;
+        query.response_packet = None;
+        let mut response = query.clone();
+        response.message_type = MessageType::CLIENT_RESPONSE;
+        response.query_time = None;
+        response.query_packet = None;
+        response.response_time = dns_message.response_time;
+        response.response_packet = dns_message.response_packet.clone();
+        vec![query, response]
+    }
+
+    fn write(dns_messages: &[DNSMessage]) -> Vec<u8> {
+        let mut pcap_writer = PcapWriter::new(vec![]).unwrap();
+        for dns_message in dns_messages {
+            pcap_writer.write_message(dns_message).unwrap();
+        }
+        pcap_writer.into_inner()
+    }
+
+    #[test]
+    fn pcap_reader_round_trip() {
+        let dns_messages = vec![
+            exchange(SocketProtocol::UDP, "192.0.2.1", "192.0.2.53", 1),
+            exchange(SocketProtocol::TCP, "2001:db8::1", "2001:db8::53", 2),
+            // A second exchange over the same TCP connection
+            exchange(SocketProtocol::TCP, "2001:db8::1", "2001:db8::53", 3),
+            exchange(SocketProtocol::UDP, "2001:db8::2", "2001:db8::53", 4),
+        ];
+        let pcap = write(&dns_messages);
+        let read: Vec<_> = PcapReaderBuilder::default()
+            .reader(&pcap[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        let expected: Vec<_> = dns_messages.iter().flat_map(split).collect();
+        assert_eq!(read, expected);
+    }
+
+    #[test]
+    fn message_without_addresses() {
+        let mut dns_message = DNSMessage::new(None, None, MessageType::AUTH_QUERY);
+        dns_message.query_packet = Some(dns_query(1, "example.com", 1));
+        let pcap = write(&[dns_message.clone()]);
+        let read: Vec<_> = PcapReaderBuilder::default()
+            .message_types(MessageType::AUTH_QUERY, MessageType::AUTH_RESPONSE)
+            .reader(&pcap[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        dns_message.socket_family = Some(SocketFamily::INET);
+        dns_message.socket_protocol = Some(SocketProtocol::UDP);
+        dns_message.query_address = Some(ip("0.0.0.0"));
+        dns_message.query_port = Some(DNS_PORT);
+        dns_message.response_address = Some(ip("0.0.0.0"));
+        dns_message.response_port = Some(DNS_PORT);
+        dns_message.query_time = Some(Duration::ZERO);
+        assert_eq!(read, vec![dns_message]);
+
+        let mut dns_message = DNSMessage::new(None, None, MessageType::AUTH_QUERY);
+        dns_message.query_address = Some(ip("192.0.2.1"));
+        dns_message.response_address = Some(ip("2001:db8::53"));
+        let mut pcap_writer = PcapWriter::new(vec![]).unwrap();
+        assert!(pcap_writer.write_message(&dns_message).is_err());
+    }
+
+    #[test]
+    fn oversized_packets_are_rejected() {
+        let mut dns_message = exchange(SocketProtocol::UDP, "192.0.2.1", "192.0.2.53", 1);
+        dns_message.query_packet = Some(vec![0; 65_535 - 28 + 1]);
+        let mut pcap_writer = PcapWriter::new(vec![]).unwrap();
+        let err = pcap_writer.write_message(&dns_message).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        dns_message.query_packet = Some(vec![0; 65_535 - 28]);
+        pcap_writer.write_message(&dns_message).unwrap();
+    }
+
+    #[test]
+    fn ones_complement() {
+        // Example from RFC 1071, section 3
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(ones_complement_sum(0, &data), 0xddf2);
+        assert_eq!(ones_complement_sum(0, &data[..7]), 0xddf2 - 0xf7);
+        assert_eq!(ones_complement_sum(0xffff, &[0, 1]), 1);
+    }
+
+    /// The expected checksums were computed independently from the packet layouts.
+    #[test]
+    fn checksums() {
+        const RECORD_OFFSET: usize = 24 + 16;
+        const IP_OFFSET: usize = RECORD_OFFSET + ETHERNET_HEADER_SIZE;
+
+        let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_QUERY);
+        dns_message.socket_protocol = Some(SocketProtocol::UDP);
+        dns_message.query_address = Some(ip("192.0.2.1"));
+        dns_message.query_port = Some(40000);
+        dns_message.response_address = Some(ip("192.0.2.53"));
+        dns_message.query_time = Some(QUERY_TIME);
+        dns_message.query_packet = Some(dns_query(0x1234, "example.com", 1));
+        let pcap = write(&[dns_message.clone()]);
+        assert_eq!(pcap[IP_OFFSET + 10..IP_OFFSET + 12], [0xb6, 0x72]);
+        let udp_offset = IP_OFFSET + IPV4_HEADER_SIZE;
+        assert_eq!(pcap[udp_offset + 6..udp_offset + 8], [0xf8, 0x45]);
+
+        dns_message.socket_protocol = Some(SocketProtocol::TCP);
+        dns_message.query_address = Some(ip("2001:db8::1"));
+        dns_message.response_address = Some(ip("2001:db8::53"));
+        let pcap = write(&[dns_message]);
+        let tcp_offset = IP_OFFSET + IPV6_HEADER_SIZE;
+        assert_eq!(pcap[tcp_offset + 16..tcp_offset + 18], [0xd0, 0xa1]);
+    }
+}
@@ -0,0 +1,124 @@
+//! `serde` support for DNS messages. Addresses are serialized as strings, times in RFC 3339
+//! format, DNS packets in base64, and enumerations by name.
+
+use std::fmt;
+
+use protobuf::Enum;
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::{HttpProtocol, MessageType, PolicyAction, PolicyMatch, SocketFamily, SocketProtocol};
+
+struct EnumVisitor<E>(std::marker::PhantomData<E>);
+
+impl<'de, E: Enum> Visitor<'de> for EnumVisitor<E> {
+    type Value = E;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a {} name", E::NAME)
+    }
+
+    fn visit_str<Err: de::Error>(self, v: &str) -> Result<E, Err> {
+        E::from_str(v).ok_or_else(|| Err::unknown_variant(v, &[]))
+    }
+}
+
+macro_rules! serde_enum {
+    ($t:ty) => {
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(&format_args!("{:?}", self))
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$t, D::Error> {
+                deserializer.deserialize_str(EnumVisitor(std::marker::PhantomData))
+            }
+        }
+    };
+}
+
+serde_enum!(MessageType);
+serde_enum!(SocketFamily);
+serde_enum!(SocketProtocol);
+serde_enum!(HttpProtocol);
+serde_enum!(PolicyAction);
+serde_enum!(PolicyMatch);
+
+pub mod time_rfc3339 {
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::Serializer;
+    use std::time;
+
+    use crate::timestamp;
+
+    pub fn serialize<S: Serializer>(
+        t: &Option<time::Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match t {
+            Some(t) => serializer.serialize_str(&timestamp::rfc3339(*t)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<time::Duration>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(s) => timestamp::parse_rfc3339(&s)
+                .map(Some)
+                .ok_or_else(|| de::Error::custom("invalid RFC 3339 timestamp")),
+            None => Ok(None),
+        }
+    }
+}
+
+pub mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::Serializer;
+
+    pub fn serialize<S: Serializer>(
+        bytes: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_str(&STANDARD.encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(s) => STANDARD.decode(s).map(Some).map_err(de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Identity and version strings are usually text, and are serialized as such.
+pub mod lossy_string {
+    use serde::de::{Deserialize, Deserializer};
+    use serde::ser::Serializer;
+
+    pub fn serialize<S: Serializer>(
+        bytes: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_str(&String::from_utf8_lossy(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?.map(String::into_bytes))
+    }
+}
@@ -0,0 +1,53 @@
+//! DNS packets and messages shared by the unit tests.
+
+use std::io;
+use std::net::IpAddr;
+
+use crate::{DNSMessage, DNSTapReader};
+
+/// Encodes a name in uncompressed wire format.
+pub fn name(name: &str) -> Vec<u8> {
+    let mut wire = vec![];
+    for label in name.trim_end_matches('.').split('.') {
+        wire.push(label.len() as u8);
+        wire.extend_from_slice(label.as_bytes());
+    }
+    wire.push(0);
+    wire
+}
+
+/// A recursive query, with an empty OPT record.
+pub fn dns_query(id: u16, qname: &str, qtype: u16) -> Vec<u8> {
+    let mut packet = id.to_be_bytes().to_vec();
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 1]);
+    packet.extend_from_slice(&name(qname));
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&[0, 1]);
+    packet.extend_from_slice(&[0, 0, 41, 0x04, 0xd0, 0, 0, 0, 0, 0, 0]);
+    packet
+}
+
+/// A response with a single answer of type `qtype`, TTL 300, pointing to the question.
+pub fn dns_response(id: u16, qname: &str, qtype: u16, rdata: &[u8]) -> Vec<u8> {
+    let mut packet = id.to_be_bytes().to_vec();
+    packet.extend_from_slice(&[0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0]);
+    packet.extend_from_slice(&name(qname));
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&[0, 1, 0xc0, 0x0c]);
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&[0, 1, 0, 0, 0x01, 0x2c]);
+    packet.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    packet.extend_from_slice(rdata);
+    packet
+}
+
+pub fn ip(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+/// The messages of `tests/data/reference.dnstap`, one of each type, in type order.
+pub fn reference_messages() -> Vec<DNSMessage> {
+    DNSTapReader::new(&include_bytes!("../tests/data/reference.dnstap")[..])
+        .collect::<io::Result<_>>()
+        .unwrap()
+}
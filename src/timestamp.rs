@@ -0,0 +1,279 @@
+use std::time;
+
+/// A source of the current time, used to timestamp DNS messages.
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> time::SystemTime;
+}
+
+/// The system clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> time::SystemTime {
+        time::SystemTime::now()
+    }
+}
+
+/// Converts a time into a duration since the UNIX epoch. Times before the epoch are
+/// converted to the epoch.
+pub fn since_epoch(t: time::SystemTime) -> time::Duration {
+    t.duration_since(time::UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Splits a duration since the UNIX epoch into a UTC calendar date and a time of day.
+pub fn civil(since_epoch: time::Duration) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = since_epoch.as_secs();
+    let days = (secs / 86_400) as i64;
+    let secs_of_day = (secs % 86_400) as u32;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+    )
+}
+
+/// Formats a timestamp as a UTC time of day with microseconds, such as `14:44:32.150207`.
+pub fn time_of_day(since_epoch: time::Duration) -> String {
+    let (_, _, _, hour, min, sec) = civil(since_epoch);
+    format!(
+        "{:02}:{:02}:{:02}.{:06}",
+        hour,
+        min,
+        sec,
+        since_epoch.subsec_micros()
+    )
+}
+
+/// Formats a timestamp as a UTC date and time with up to nanosecond precision and no
+/// trailing zeros, such as `2014-01-07 21:41:59.3189`, as in `golang-dnstap` YAML output.
+pub fn date_time(since_epoch: time::Duration) -> String {
+    let (year, month, day, hour, min, sec) = civil(since_epoch);
+    let mut out = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, hour, min, sec
+    );
+    let nanos = since_epoch.subsec_nanos();
+    if nanos != 0 {
+        out.push_str(format!(".{:09}", nanos).trim_end_matches('0'));
+    }
+    out
+}
+
+#[cfg(feature = "serde")]
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Formats a timestamp in RFC 3339 format with nanoseconds, such as
+/// `2014-01-07T21:41:59.318932000Z`.
+#[cfg(feature = "serde")]
+pub fn rfc3339(since_epoch: time::Duration) -> String {
+    let (year, month, day, hour, min, sec) = civil(since_epoch);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        hour,
+        min,
+        sec,
+        since_epoch.subsec_nanos()
+    )
+}
+
+/// Parses an RFC 3339 timestamp into a duration since the UNIX epoch.
+#[cfg(feature = "serde")]
+pub fn parse_rfc3339(s: &str) -> Option<time::Duration> {
+    let b = s.as_bytes();
+    if b.len() < 20 || b[4] != b'-' || b[7] != b'-' || b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+    if !matches!(b[10], b'T' | b't' | b' ') {
+        return None;
+    }
+    let num = |range: std::ops::Range<usize>| -> Option<u32> {
+        let digits = s.get(range)?;
+        if !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, min, sec) = (num(11..13)?, num(14..16)?, num(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+    let mut pos = 19;
+    let mut nanos = 0u32;
+    if b[pos] == b'.' {
+        pos += 1;
+        let start = pos;
+        while pos < b.len() && b[pos].is_ascii_digit() {
+            if pos - start < 9 {
+                nanos = nanos * 10 + u32::from(b[pos] - b'0');
+            }
+            pos += 1;
+        }
+        if pos == start {
+            return None;
+        }
+        for _ in (pos - start)..9 {
+            nanos *= 10;
+        }
+    }
+    let offset = match s.get(pos..)? {
+        "Z" | "z" => 0,
+        tz if tz.len() == 6 && (tz.starts_with('+') || tz.starts_with('-')) => {
+            if tz.as_bytes()[3] != b':' {
+                return None;
+            }
+            let offset = i64::from(num(pos + 1..pos + 3)? * 3600 + num(pos + 4..pos + 6)? * 60);
+            if tz.starts_with('-') {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return None,
+    };
+    let secs = days_from_civil(i64::from(year), month, day) * 86_400
+        + i64::from(hour * 3600 + min * 60 + sec)
+        - offset;
+    if secs < 0 {
+        return None;
+    }
+    Some(time::Duration::new(secs as u64, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn civil_at(secs: u64) -> (i64, u32, u32, u32, u32, u32) {
+        civil(time::Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_at(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(civil_at(86_399), (1970, 1, 1, 23, 59, 59));
+        assert_eq!(civil_at(1_709_210_096), (2024, 2, 29, 12, 34, 56));
+        assert_eq!(civil_at(253_402_300_799), (9999, 12, 31, 23, 59, 59));
+    }
+
+    #[test]
+    fn civil_leap_days() {
+        // 2000 is a leap year, being divisible by 400
+        assert_eq!(civil_at(951_782_400), (2000, 2, 29, 0, 0, 0));
+        assert_eq!(civil_at(951_868_799), (2000, 2, 29, 23, 59, 59));
+        assert_eq!(civil_at(951_868_800), (2000, 3, 1, 0, 0, 0));
+        // 2100 isn't
+        assert_eq!(civil_at(4_107_456_000), (2100, 2, 28, 0, 0, 0));
+        assert_eq!(civil_at(4_107_542_400), (2100, 3, 1, 0, 0, 0));
+    }
+
+    #[test]
+    fn civil_after_2038() {
+        assert_eq!(civil_at(2_147_483_647), (2038, 1, 19, 3, 14, 7));
+        assert_eq!(civil_at(2_147_483_648), (2038, 1, 19, 3, 14, 8));
+        assert_eq!(civil_at(4_294_967_296), (2106, 2, 7, 6, 28, 16));
+    }
+
+    #[test]
+    fn formatting() {
+        let t = time::Duration::new(1_389_130_919, 318_932_123);
+        assert_eq!(time_of_day(t), "21:41:59.318932");
+        assert_eq!(date_time(t), "2014-01-07 21:41:59.318932123");
+        let t = time::Duration::new(1_389_130_919, 300_000_000);
+        assert_eq!(date_time(t), "2014-01-07 21:41:59.3");
+        assert_eq!(date_time(time::Duration::ZERO), "1970-01-01 00:00:00");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rfc3339_round_trip() {
+        let times = [
+            time::Duration::ZERO,
+            time::Duration::new(1_389_130_919, 318_932_123),
+            time::Duration::new(951_782_400, 1),
+            time::Duration::new(2_147_483_648, 999_999_999),
+            time::Duration::new(253_402_300_799, 0),
+        ];
+        for &t in times.iter() {
+            assert_eq!(parse_rfc3339(&rfc3339(t)), Some(t));
+        }
+        assert_eq!(
+            rfc3339(time::Duration::new(1_389_130_919, 318_932_000)),
+            "2014-01-07T21:41:59.318932000Z"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn parse_rfc3339_variants() {
+        let t = Some(time::Duration::new(1_389_130_919, 0));
+        assert_eq!(parse_rfc3339("2014-01-07T21:41:59Z"), t);
+        assert_eq!(parse_rfc3339("2014-01-07t21:41:59z"), t);
+        assert_eq!(parse_rfc3339("2014-01-07 21:41:59Z"), t);
+        assert_eq!(parse_rfc3339("2014-01-07T23:41:59+02:00"), t);
+        assert_eq!(parse_rfc3339("2014-01-07T20:11:59-01:30"), t);
+        assert_eq!(
+            parse_rfc3339("2014-01-07T21:41:59.5Z"),
+            Some(time::Duration::new(1_389_130_919, 500_000_000))
+        );
+        // Digits beyond nanoseconds are truncated
+        assert_eq!(
+            parse_rfc3339("2014-01-07T21:41:59.1234567891Z"),
+            Some(time::Duration::new(1_389_130_919, 123_456_789))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn parse_rfc3339_rejects_invalid_timestamps() {
+        for s in [
+            "",
+            "2014-01-07",
+            "2014-01-07T21:41:59",
+            "2014-01-07X21:41:59Z",
+            "2014-13-07T21:41:59Z",
+            "2014-01-00T21:41:59Z",
+            "2014-01-07T24:41:59Z",
+            "2014-01-07T21:41:59.Z",
+            "2014-01-07T21:41:59+0200",
+            "2014-01-07T21:41:59 UTC",
+            "+014-01-07T21:41:59Z",
+            "1969-12-31T23:59:59Z",
+        ]
+        .iter()
+        {
+            assert_eq!(parse_rfc3339(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn times_before_the_epoch() {
+        let t = time::UNIX_EPOCH - time::Duration::from_secs(1);
+        assert_eq!(since_epoch(t), time::Duration::ZERO);
+    }
+}
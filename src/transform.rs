@@ -0,0 +1,385 @@
+use crate::anonymizer::{Anonymization, Anonymizer};
+use crate::dns_message::*;
+use crate::dns_packet::{DNSName, DNSPacket};
+use std::ops::Range;
+
+const DNS_HEADER_SIZE: usize = 12;
+const TYPE_OPT: u16 = 41;
+const OPTION_CLIENT_SUBNET: u16 = 8;
+const OPTION_COOKIE: u16 = 10;
+const OPTION_PADDING: u16 = 12;
+
+/// How much of the query and response packets of DNS messages is kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PacketMinimization {
+    /// Keeps the DNS header and the question section. Counts of the other sections are set
+    /// to zero, so that the packets remain valid.
+    HeaderAndQuestion,
+    /// Removes the packets. The queried name, type and class and the response code are kept
+    /// in the `summary` of the message. The summary is not part of the dnstap encoding, so
+    /// encoded messages have no packet fields.
+    Drop,
+}
+
+impl PacketMinimization {
+    /// Minimizes the query and response packets of a DNS message.
+    pub fn minimize(self, dns_message: &mut DNSMessage) {
+        match self {
+            PacketMinimization::HeaderAndQuestion => {
+                if let Some(ref mut query_packet) = dns_message.query_packet {
+                    header_and_question(query_packet);
+                }
+                if let Some(ref mut response_packet) = dns_message.response_packet {
+                    header_and_question(response_packet);
+                }
+            }
+            PacketMinimization::Drop => {
+                if dns_message.query_packet.is_none() && dns_message.response_packet.is_none() {
+                    return;
+                }
+                dns_message.summary = Some(PacketSummary {
+                    qname: dns_message.qname(),
+                    qtype: dns_message.qtype(),
+                    qclass: dns_message.qclass(),
+                    rcode: dns_message.rcode(),
+                });
+                dns_message.query_packet = None;
+                dns_message.response_packet = None;
+            }
+        }
+    }
+}
+
+/// Truncates a packet after the question section. Questions that cannot be parsed are
+/// removed, and packets shorter than a header are left untouched.
+fn header_and_question(packet: &mut Vec<u8>) {
+    let question_count = match DNSPacket::new(packet) {
+        Some(dns_packet) => dns_packet.question_count(),
+        None => return,
+    };
+    let mut offset = DNS_HEADER_SIZE;
+    let mut kept = 0u16;
+    while kept < question_count {
+        match DNSName::parse(packet, offset) {
+            Some((_, next_offset)) if next_offset + 4 <= packet.len() => {
+                offset = next_offset + 4;
+                kept += 1;
+            }
+            _ => break,
+        }
+    }
+    packet.truncate(offset);
+    packet[4..6].copy_from_slice(&kept.to_be_bytes());
+    packet[6..12].iter_mut().for_each(|x| *x = 0);
+}
+
+/// How the EDNS Client Subnet option is redacted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ClientSubnetRedaction {
+    /// Removes the option.
+    Remove,
+    /// Reduces the source prefix length to at most `ipv4_prefix` or `ipv6_prefix` bits, and
+    /// truncates the address accordingly.
+    Truncate { ipv4_prefix: u8, ipv6_prefix: u8 },
+}
+
+/// EDNS options removed from, or truncated in, the OPT record of query and response
+/// packets.
+///
+/// Packets remain valid: the length of the OPT record is updated, and everything else is
+/// left untouched.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct EdnsRedaction {
+    pub client_subnet: Option<ClientSubnetRedaction>,
+    pub cookie: bool,
+    pub padding: bool,
+    pub option_codes: Vec<u16>,
+}
+
+impl EdnsRedaction {
+    /// Removes or truncates the EDNS Client Subnet option.
+    pub fn client_subnet(mut self, client_subnet: ClientSubnetRedaction) -> Self {
+        self.client_subnet = Some(client_subnet);
+        self
+    }
+
+    /// Removes the DNS Cookie option.
+    pub fn remove_cookie(mut self) -> Self {
+        self.cookie = true;
+        self
+    }
+
+    /// Removes the Padding option.
+    pub fn remove_padding(mut self) -> Self {
+        self.padding = true;
+        self
+    }
+
+    /// Removes options with the given code.
+    pub fn remove_option(mut self, option_code: u16) -> Self {
+        self.option_codes.push(option_code);
+        self
+    }
+
+    /// Redacts the options of the query and response packets of a DNS message.
+    pub fn redact(&self, dns_message: &mut DNSMessage) {
+        if let Some(ref mut query_packet) = dns_message.query_packet {
+            self.redact_packet(query_packet);
+        }
+        if let Some(ref mut response_packet) = dns_message.response_packet {
+            self.redact_packet(response_packet);
+        }
+    }
+
+    /// Redacts the options of a DNS packet. Packets without an OPT record are left
+    /// untouched, and all the options are removed if they cannot be parsed.
+    pub fn redact_packet(&self, packet: &mut Vec<u8>) {
+        let rdata = match opt_rdata(packet) {
+            Some(rdata) => rdata,
+            None => return,
+        };
+        let mut options = Vec::with_capacity(rdata.len());
+        let mut offset = rdata.start;
+        while offset < rdata.end {
+            let (code, len) = match (be16(packet, offset), be16(packet, offset + 2)) {
+                (Some(code), Some(len)) => (code, usize::from(len)),
+                _ => break,
+            };
+            let data = match packet.get(offset + 4..offset + 4 + len) {
+                Some(data) if offset + 4 + len <= rdata.end => data,
+                _ => break,
+            };
+            offset += 4 + len;
+            let data = match code {
+                OPTION_CLIENT_SUBNET => match self.client_subnet {
+                    None => data.to_vec(),
+                    Some(ClientSubnetRedaction::Remove) => continue,
+                    Some(ClientSubnetRedaction::Truncate {
+                        ipv4_prefix,
+                        ipv6_prefix,
+                    }) => match truncate_client_subnet(data, ipv4_prefix, ipv6_prefix) {
+                        Some(data) => data,
+                        None => continue,
+                    },
+                },
+                OPTION_COOKIE if self.cookie => continue,
+                OPTION_PADDING if self.padding => continue,
+                _ if self.option_codes.contains(&code) => continue,
+                _ => data.to_vec(),
+            };
+            options.extend_from_slice(&code.to_be_bytes());
+            options.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            options.extend_from_slice(&data);
+        }
+        if offset != rdata.end {
+            options.clear();
+        }
+        let rdlen = (options.len() as u16).to_be_bytes();
+        packet[rdata.start - 2..rdata.start].copy_from_slice(&rdlen);
+        packet.splice(rdata, options);
+    }
+}
+
+/// Returns the location of the data of the OPT record in the additional section.
+fn opt_rdata(packet: &[u8]) -> Option<Range<usize>> {
+    let dns_packet = DNSPacket::new(packet)?;
+    let mut offset = DNS_HEADER_SIZE;
+    for _ in 0..dns_packet.question_count() {
+        let (_, next_offset) = DNSName::parse(packet, offset)?;
+        offset = next_offset + 4;
+    }
+    let records =
+        usize::from(dns_packet.answer_count()) + usize::from(dns_packet.authority_count());
+    for i in 0..records + usize::from(dns_packet.additional_count()) {
+        let (_, next_offset) = DNSName::parse(packet, offset)?;
+        let rr_type = be16(packet, next_offset)?;
+        let rdlen = usize::from(be16(packet, next_offset + 8)?);
+        let rdata = next_offset + 10..next_offset + 10 + rdlen;
+        if rdata.end > packet.len() {
+            return None;
+        }
+        if i >= records && rr_type == TYPE_OPT {
+            return Some(rdata);
+        }
+        offset = rdata.end;
+    }
+    None
+}
+
+/// Returns a copy of a Client Subnet option with a shorter source prefix, or `None` if the
+/// option is malformed.
+fn truncate_client_subnet(data: &[u8], ipv4_prefix: u8, ipv6_prefix: u8) -> Option<Vec<u8>> {
+    let max_prefix = match be16(data, 0)? {
+        1 => ipv4_prefix.min(32),
+        2 => ipv6_prefix.min(128),
+        _ => return None,
+    };
+    let source_prefix = (*data.get(2)?).min(max_prefix);
+    let len = 4 + usize::from(source_prefix).div_ceil(8);
+    let mut truncated = data.get(..len)?.to_vec();
+    truncated[2] = source_prefix;
+    if source_prefix % 8 != 0 {
+        truncated[len - 1] &= 0xff << (8 - source_prefix % 8);
+    }
+    Some(truncated)
+}
+
+fn be16(packet: &[u8], offset: usize) -> Option<u16> {
+    let x = packet.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([x[0], x[1]]))
+}
+
+/// Transforms applied to DNS messages by writers, collectors and readers.
+#[derive(Default)]
+pub(crate) struct Transforms {
+    anonymizer: Option<Anonymizer>,
+    edns_redaction: Option<EdnsRedaction>,
+    packet_minimization: Option<PacketMinimization>,
+}
+
+impl Transforms {
+    pub fn new(
+        anonymization: Option<&Anonymization>,
+        edns_redaction: Option<EdnsRedaction>,
+        packet_minimization: Option<PacketMinimization>,
+    ) -> Transforms {
+        Transforms {
+            anonymizer: anonymization.map(Anonymizer::new),
+            edns_redaction,
+            packet_minimization,
+        }
+    }
+
+    pub fn set_anonymization(&mut self, anonymization: &Anonymization) {
+        self.anonymizer = Some(Anonymizer::new(anonymization));
+    }
+
+    pub fn set_edns_redaction(&mut self, edns_redaction: EdnsRedaction) {
+        self.edns_redaction = Some(edns_redaction);
+    }
+
+    pub fn set_packet_minimization(&mut self, packet_minimization: PacketMinimization) {
+        self.packet_minimization = Some(packet_minimization);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anonymizer.is_none()
+            && self.edns_redaction.is_none()
+            && self.packet_minimization.is_none()
+    }
+
+    pub fn apply(&self, dns_message: &mut DNSMessage) {
+        if let Some(ref anonymizer) = self.anonymizer {
+            anonymizer.anonymize(dns_message);
+        }
+        if let Some(ref edns_redaction) = self.edns_redaction {
+            edns_redaction.redact(dns_message);
+        }
+        if let Some(packet_minimization) = self.packet_minimization {
+            packet_minimization.minimize(dns_message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use crate::MessageType;
+
+    fn response() -> DNSMessage {
+        let mut dns_message = DNSMessage::new(None, None, MessageType::RESOLVER_RESPONSE);
+        dns_message.query_packet = Some(dns_query(0xbeef, "example.com", 1));
+        dns_message.response_packet =
+            Some(dns_response(0xbeef, "example.com", 1, &[203, 0, 113, 1]));
+        dns_message
+    }
+
+    #[test]
+    fn header_and_question_minimization() {
+        let mut dns_message = response();
+        PacketMinimization::HeaderAndQuestion.minimize(&mut dns_message);
+        let mut query_packet = dns_query(0xbeef, "example.com", 1);
+        query_packet.truncate(query_packet.len() - 11);
+        query_packet[11] = 0;
+        assert_eq!(dns_message.query_packet, Some(query_packet));
+        let mut response_packet = dns_response(0xbeef, "example.com", 1, &[203, 0, 113, 1]);
+        response_packet.truncate(12 + name("example.com").len() + 4);
+        response_packet[7] = 0;
+        assert_eq!(dns_message.response_packet, Some(response_packet));
+
+        let mut packet = dns_query(0xbeef, "example.com", 1);
+        packet.truncate(20);
+        header_and_question(&mut packet);
+        assert_eq!(packet, [0xbe, 0xef, 0x01, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let mut packet = vec![0xbe, 0xef, 0x01];
+        header_and_question(&mut packet);
+        assert_eq!(packet, [0xbe, 0xef, 0x01]);
+    }
+
+    #[test]
+    fn drop_minimization() {
+        let mut dns_message = response();
+        PacketMinimization::Drop.minimize(&mut dns_message);
+        assert_eq!(dns_message.query_packet, None);
+        assert_eq!(dns_message.response_packet, None);
+        assert_eq!(dns_message.qname(), Some("example.com.".to_owned()));
+        assert_eq!(dns_message.qtype(), Some(1));
+        assert_eq!(dns_message.qclass(), Some(1));
+        assert_eq!(dns_message.rcode(), Some(0));
+
+        let protobuf = dns_message.clone().into_protobuf().unwrap();
+        let decoded = DNSMessage::from_protobuf(protobuf).unwrap();
+        assert_eq!(decoded.query_packet, None);
+        assert_eq!(decoded.response_packet, None);
+        assert_eq!(decoded.summary, None);
+        assert_eq!(decoded.message_type, dns_message.message_type);
+    }
+
+    #[test]
+    fn edns_redaction() {
+        let query_packet = dns_query(0x1234, "www.example.com", 1);
+        let options: &[u8] = &[
+            0, 8, 0, 7, 0, 1, 24, 0, 192, 0, 2, // Client Subnet 192.0.2.0/24
+            0, 10, 0, 8, 1, 2, 3, 4, 5, 6, 7, 8, // Cookie
+            0, 12, 0, 4, 0, 0, 0, 0, // Padding
+            0xfd, 0xe9, 0, 2, 0xab, 0xcd, // Local option 65001
+        ];
+        let mut packet = query_packet.clone();
+        let rdlen = packet.len() - 2;
+        packet[rdlen + 1] = options.len() as u8;
+        packet.extend_from_slice(options);
+
+        let mut redacted = packet.clone();
+        EdnsRedaction::default()
+            .client_subnet(ClientSubnetRedaction::Truncate {
+                ipv4_prefix: 20,
+                ipv6_prefix: 48,
+            })
+            .remove_cookie()
+            .remove_padding()
+            .redact_packet(&mut redacted);
+        let options: &[u8] = &[
+            0, 8, 0, 7, 0, 1, 20, 0, 192, 0, 0, 0xfd, 0xe9, 0, 2, 0xab, 0xcd,
+        ];
+        let mut expected = query_packet.clone();
+        expected[rdlen + 1] = options.len() as u8;
+        expected.extend_from_slice(options);
+        assert_eq!(redacted, expected);
+
+        let mut redacted = packet.clone();
+        EdnsRedaction::default()
+            .client_subnet(ClientSubnetRedaction::Remove)
+            .remove_cookie()
+            .remove_padding()
+            .remove_option(65001)
+            .redact_packet(&mut redacted);
+        assert_eq!(redacted, query_packet);
+
+        let mut malformed = packet;
+        malformed[rdlen + 1] += 1;
+        malformed.push(0);
+        EdnsRedaction::default().redact_packet(&mut malformed);
+        assert_eq!(malformed, query_packet);
+    }
+}
@@ -0,0 +1,270 @@
+//! Loopback tests of `DNSTapCollector`.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use dnstap::*;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn control_frame(control_type: u32, content_type: Option<&[u8]>) -> Vec<u8> {
+    let mut fields = vec![];
+    if let Some(content_type) = content_type {
+        fields.extend_from_slice(&1u32.to_be_bytes());
+        fields.extend_from_slice(&(content_type.len() as u32).to_be_bytes());
+        fields.extend_from_slice(content_type);
+    }
+    let mut frame = 0u32.to_be_bytes().to_vec();
+    frame.extend_from_slice(&(4 + fields.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&control_type.to_be_bytes());
+    frame.extend(fields);
+    frame
+}
+
+fn data_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn message(id: u8) -> DNSMessage {
+    let mut dns_message = DNSMessage::new(Some(vec![id]), None, MessageType::CLIENT_QUERY);
+    dns_message.query_time = Some(Duration::from_secs(1_700_000_000));
+    dns_message.query_packet = Some(vec![id; 12]);
+    dns_message
+}
+
+fn payload(dns_message: &DNSMessage) -> Vec<u8> {
+    let mut payload = vec![];
+    dns_message.encode(&mut payload).unwrap();
+    payload
+}
+
+type Received = mpsc::Receiver<(ConnectionInfo, DNSMessage)>;
+type Errors = mpsc::Receiver<(ConnectionInfo, io::Error)>;
+
+fn start_collector(
+    builder: DNSTapCollectorBuilder,
+) -> (DNSTapCollector, SocketAddr, Received, Errors) {
+    let (tx, rx) = mpsc::channel();
+    let (error_tx, error_rx) = mpsc::channel();
+    let dnstap_pending_collector = builder
+        .tcp_socket_addr("127.0.0.1:0".parse().unwrap())
+        .listen()
+        .unwrap();
+    let addr = dnstap_pending_collector.tcp_local_addr().unwrap();
+    let dnstap_collector = dnstap_pending_collector
+        .error_handler(move |connection_info, e| {
+            let _ = error_tx.send((connection_info.clone(), e));
+        })
+        .start(move |connection_info, dns_message| {
+            let _ = tx.send((connection_info.clone(), dns_message));
+        })
+        .unwrap();
+    (dnstap_collector, addr, rx, error_rx)
+}
+
+#[test]
+fn collector_round_trip() {
+    let (dnstap_collector, addr, rx, error_rx) = start_collector(DNSTapCollectorBuilder::default());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut frames = control_frame(2, Some(CONTENT_TYPE.as_bytes()));
+    frames.extend(data_frame(&payload(&message(1))));
+    frames.extend(data_frame(b"\xff\xff"));
+    frames.extend(data_frame(&payload(&message(2))));
+    frames.extend(control_frame(3, None));
+    stream.write_all(&frames).unwrap();
+
+    let (connection_info, dns_message) = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(dns_message, message(1));
+    assert_eq!(
+        connection_info.peer_address,
+        PeerAddress::Tcp(stream.local_addr().unwrap())
+    );
+    assert!(!connection_info.bidirectional);
+    let (error_connection_info, e) = error_rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(error_connection_info, connection_info);
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(rx.recv_timeout(TIMEOUT).unwrap().1, message(2));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(&control_frame(2, Some(b"protobuf:other")))
+        .unwrap();
+    let (connection_info, e) = error_rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(
+        connection_info.peer_address,
+        PeerAddress::Tcp(stream.local_addr().unwrap())
+    );
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+    dnstap_collector.shutdown().unwrap();
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn shutdown_drains_received_messages() {
+    let (dnstap_collector, addr, rx, _error_rx) =
+        start_collector(DNSTapCollectorBuilder::default());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut frames = control_frame(2, Some(CONTENT_TYPE.as_bytes()));
+    frames.extend(data_frame(&payload(&message(0))));
+    stream.write_all(&frames).unwrap();
+    assert_eq!(rx.recv_timeout(TIMEOUT).unwrap().1, message(0));
+
+    let mut frames = vec![];
+    for id in 1..=100 {
+        frames.extend(data_frame(&payload(&message(id))));
+    }
+    stream.write_all(&frames).unwrap();
+    dnstap_collector.shutdown().unwrap();
+    let received: Vec<_> = rx.try_iter().map(|(_, dns_message)| dns_message).collect();
+    assert_eq!(received, (1..=100).map(message).collect::<Vec<_>>());
+}
+
+/// Returns `true` if the collector closed `stream`.
+fn is_closed(stream: &mut TcpStream) -> bool {
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    matches!(stream.read(&mut [0u8; 1]), Ok(0))
+}
+
+#[test]
+fn tcp_writer_to_tcp_collector() {
+    let (dnstap_collector, addr, rx, _error_rx) =
+        start_collector(DNSTapCollectorBuilder::default());
+
+    let dnstap_writer = DNSTapBuilder::default()
+        .tcp_socket_addr(addr)
+        .listen()
+        .unwrap()
+        .start()
+        .unwrap();
+    let sender = dnstap_writer.sender();
+    for id in 1..=10 {
+        sender.send(message(id)).unwrap();
+    }
+    for id in 1..=10 {
+        let (connection_info, dns_message) = rx.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(dns_message, message(id));
+        assert!(matches!(connection_info.peer_address, PeerAddress::Tcp(_)));
+        assert!(!connection_info.tls);
+    }
+    dnstap_collector.shutdown().unwrap();
+}
+
+#[test]
+fn max_connections() {
+    let (dnstap_collector, addr, rx, _error_rx) =
+        start_collector(DNSTapCollectorBuilder::default().max_connections(1));
+
+    let mut frames = control_frame(2, Some(CONTENT_TYPE.as_bytes()));
+    frames.extend(data_frame(&payload(&message(1))));
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&frames).unwrap();
+    assert_eq!(rx.recv_timeout(TIMEOUT).unwrap().1, message(1));
+
+    let mut refused_stream = TcpStream::connect(addr).unwrap();
+    assert!(is_closed(&mut refused_stream));
+
+    stream.write_all(&control_frame(3, None)).unwrap();
+    drop(stream);
+    // The connection slot is released once the connection task has exited.
+    let accepted = (0..50).any(|_| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let _ = stream.write_all(&frames);
+        if rx.recv_timeout(Duration::from_millis(100)).is_ok() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+        false
+    });
+    assert!(accepted);
+    dnstap_collector.shutdown().unwrap();
+}
+
+#[test]
+fn idle_timeout() {
+    let (dnstap_collector, addr, rx, error_rx) =
+        start_collector(DNSTapCollectorBuilder::default().idle_timeout(Duration::from_millis(200)));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut frames = control_frame(2, Some(CONTENT_TYPE.as_bytes()));
+    frames.extend(data_frame(&payload(&message(1))));
+    stream.write_all(&frames).unwrap();
+    assert_eq!(rx.recv_timeout(TIMEOUT).unwrap().1, message(1));
+
+    let (_, e) = error_rx.recv_timeout(TIMEOUT).unwrap();
+    assert!(matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    ));
+    assert!(is_closed(&mut stream));
+    dnstap_collector.shutdown().unwrap();
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn tls_collector() {
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+    use std::convert::TryFrom;
+
+    let certified_key = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+    let cert_der = certified_key.cert.der().clone();
+    let key_der = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(
+        certified_key.signing_key.serialize_der(),
+    ));
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let tls_config = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert_der.clone()], key_der)
+        .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let dnstap_pending_collector = DNSTapCollectorBuilder::default()
+        .tls_socket_addr("127.0.0.1:0".parse().unwrap())
+        .tls_config(Arc::new(tls_config))
+        .listen()
+        .unwrap();
+    let addr = dnstap_pending_collector.tls_local_addr().unwrap();
+    let dnstap_collector = dnstap_pending_collector
+        .start(move |connection_info, dns_message| {
+            let _ = tx.send((connection_info.clone(), dns_message));
+        })
+        .unwrap();
+
+    let mut root_store = rustls::RootCertStore::empty();
+    root_store.add(cert_der).unwrap();
+    let client_config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    let server_name = ServerName::try_from("localhost").unwrap();
+    let tls_connection =
+        rustls::ClientConnection::new(Arc::new(client_config), server_name).unwrap();
+    let mut tls_stream =
+        rustls::StreamOwned::new(tls_connection, TcpStream::connect(addr).unwrap());
+    let mut frames = control_frame(2, Some(CONTENT_TYPE.as_bytes()));
+    frames.extend(data_frame(&payload(&message(1))));
+    frames.extend(data_frame(&payload(&message(2))));
+    frames.extend(control_frame(3, None));
+    tls_stream.write_all(&frames).unwrap();
+    tls_stream.flush().unwrap();
+
+    for id in 1..=2 {
+        let (connection_info, dns_message) = rx.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(dns_message, message(id));
+        assert!(connection_info.tls);
+        assert_eq!(connection_info.peer_certificate, None);
+    }
+    dnstap_collector.shutdown().unwrap();
+}
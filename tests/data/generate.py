@@ -0,0 +1,343 @@
+#!/usr/bin/env python3
+"""Generates the reference dnstap capture used by tests/reference_capture.rs.
+
+The capture is built with a standalone encoder that follows dnstap.proto and the
+Frame Streams specification directly, without using this crate or any protobuf
+library, so that encoding mistakes in the crate cannot leak into the expected data.
+Fields are written in field number order, like the protobuf-c and Go encoders used by
+unbound, Knot and the reference dnstap tool.
+
+Usage: python3 tests/data/generate.py
+"""
+
+import os
+import struct
+
+CONTENT_TYPE = b"protobuf:dnstap.Dnstap"
+FSTRM_CONTROL_START = 2
+FSTRM_CONTROL_STOP = 3
+FSTRM_CONTROL_FIELD_CONTENT_TYPE = 1
+
+# Message.Type
+AUTH_QUERY, AUTH_RESPONSE = 1, 2
+RESOLVER_QUERY, RESOLVER_RESPONSE = 3, 4
+CLIENT_QUERY, CLIENT_RESPONSE = 5, 6
+FORWARDER_QUERY, FORWARDER_RESPONSE = 7, 8
+STUB_QUERY, STUB_RESPONSE = 9, 10
+TOOL_QUERY, TOOL_RESPONSE = 11, 12
+UPDATE_QUERY, UPDATE_RESPONSE = 13, 14
+# SocketFamily
+INET, INET6 = 1, 2
+# SocketProtocol
+UDP, TCP, DOT, DOH = 1, 2, 3, 4
+# HttpProtocol
+HTTP1, HTTP2, HTTP3 = 1, 2, 3
+# Policy.Match
+QNAME, CLIENT_IP, RESPONSE_IP, NS_NAME, NS_IP = 1, 2, 3, 4, 5
+# Policy.Action
+NXDOMAIN, NODATA, PASS, DROP, TRUNCATE, LOCAL_DATA = 1, 2, 3, 4, 5, 6
+
+
+def varint(n):
+    out = bytearray()
+    while True:
+        b = n & 0x7F
+        n >>= 7
+        if n:
+            out.append(b | 0x80)
+        else:
+            out.append(b)
+            return bytes(out)
+
+
+def field_varint(number, value):
+    return varint(number << 3) + varint(value)
+
+
+def field_bytes(number, value):
+    return varint(number << 3 | 2) + varint(len(value)) + value
+
+
+def field_fixed32(number, value):
+    return varint(number << 3 | 5) + struct.pack("<I", value)
+
+
+def policy(fields):
+    """Encodes a dnstap Policy. `fields` maps field names to values."""
+    out = b""
+    if "type" in fields:
+        out += field_bytes(1, fields["type"])
+    if "rule" in fields:
+        out += field_bytes(2, fields["rule"])
+    if "action" in fields:
+        out += field_varint(3, fields["action"])
+    if "match" in fields:
+        out += field_varint(4, fields["match"])
+    if "value" in fields:
+        out += field_bytes(5, fields["value"])
+    return out
+
+
+def message(fields):
+    """Encodes a dnstap Message. `fields` maps field names to values."""
+    out = b""
+    out += field_varint(1, fields["type"])
+    if "socket_family" in fields:
+        out += field_varint(2, fields["socket_family"])
+    if "socket_protocol" in fields:
+        out += field_varint(3, fields["socket_protocol"])
+    if "query_address" in fields:
+        out += field_bytes(4, fields["query_address"])
+    if "response_address" in fields:
+        out += field_bytes(5, fields["response_address"])
+    if "query_port" in fields:
+        out += field_varint(6, fields["query_port"])
+    if "response_port" in fields:
+        out += field_varint(7, fields["response_port"])
+    if "query_time" in fields:
+        sec, nsec = fields["query_time"]
+        out += field_varint(8, sec) + field_fixed32(9, nsec)
+    if "query_message" in fields:
+        out += field_bytes(10, fields["query_message"])
+    if "query_zone" in fields:
+        out += field_bytes(11, fields["query_zone"])
+    if "response_time" in fields:
+        sec, nsec = fields["response_time"]
+        out += field_varint(12, sec) + field_fixed32(13, nsec)
+    if "response_message" in fields:
+        out += field_bytes(14, fields["response_message"])
+    if "policy" in fields:
+        out += field_bytes(15, policy(fields["policy"]))
+    if "http_protocol" in fields:
+        out += field_varint(16, fields["http_protocol"])
+    return out
+
+
+def dnstap(identity, version, fields, extra=None):
+    out = b""
+    if identity is not None:
+        out += field_bytes(1, identity)
+    if version is not None:
+        out += field_bytes(2, version)
+    if extra is not None:
+        out += field_bytes(3, extra)
+    out += field_bytes(14, message(fields))
+    out += field_varint(15, 1)  # MESSAGE
+    return out
+
+
+def control_frame(control_type, content_type=None):
+    payload = struct.pack(">I", control_type)
+    if content_type is not None:
+        payload += struct.pack(">II", FSTRM_CONTROL_FIELD_CONTENT_TYPE, len(content_type))
+        payload += content_type
+    return struct.pack(">II", 0, len(payload)) + payload
+
+
+def data_frame(payload):
+    return struct.pack(">I", len(payload)) + payload
+
+
+def name(n):
+    return b"".join(bytes([len(l)]) + l.encode() for l in n.rstrip(".").split(".")) + b"\0"
+
+
+def dns_query(qid, qname, qtype):
+    header = struct.pack(">HHHHHH", qid, 0x0100, 1, 0, 0, 1)
+    opt = b"\0" + struct.pack(">HHIH", 41, 1232, 0, 0)
+    return header + name(qname) + struct.pack(">HH", qtype, 1) + opt
+
+
+def dns_response(qid, qname, qtype, rdata):
+    header = struct.pack(">HHHHHH", qid, 0x8180, 1, 1, 0, 0)
+    answer = b"\xc0\x0c" + struct.pack(">HHIH", qtype, 1, 300, len(rdata)) + rdata
+    return header + name(qname) + struct.pack(">HH", qtype, 1) + answer
+
+
+def dns_update(qid, zone, response=False):
+    flags = (0x8000 if response else 0) | (5 << 11)
+    header = struct.pack(">HHHHHH", qid, flags, 1, 0, 0 if response else 1, 0)
+    zone_section = name(zone) + struct.pack(">HH", 6, 1)
+    if response:
+        return header + zone_section
+    update = b"\x03www\xc0\x0c" + struct.pack(">HHIH", 1, 1, 300, 4) + bytes([192, 0, 2, 1])
+    return header + zone_section + update
+
+
+V4_CLIENT = bytes([192, 0, 2, 10])
+V6_CLIENT = bytes.fromhex("20010db8000000000000000000000010")
+V4_SERVER = bytes([198, 51, 100, 53])
+V6_SERVER = bytes.fromhex("20010db8000000000000000000000053")
+
+MESSAGES = [
+    # AuthQuery
+    dnstap(b"ns1.example", b"knot 3.3", {
+        "type": AUTH_QUERY,
+        "socket_family": INET,
+        "socket_protocol": UDP,
+        "query_address": V4_CLIENT,
+        "query_port": 53001,
+        "query_time": (1700000000, 123456789),
+        "query_message": dns_query(0x1234, "www.example.com", 1),
+    }, extra=b"pop=ams1;cluster=b"),
+    # AuthResponse
+    dnstap(b"ns1.example", b"knot 3.3", {
+        "type": AUTH_RESPONSE,
+        "socket_family": INET6,
+        "socket_protocol": TCP,
+        "query_address": V6_CLIENT,
+        "query_port": 41000,
+        "query_time": (1700000001, 5),
+        "response_time": (1700000001, 2000005),
+        "response_message": dns_response(0x1234, "www.example.com", 28,
+                                         bytes.fromhex("20010db8000000000000000000000080")),
+    }),
+    # ResolverQuery
+    dnstap(b"resolver.example", b"unbound 1.19", {
+        "type": RESOLVER_QUERY,
+        "socket_family": INET,
+        "socket_protocol": UDP,
+        "response_address": V4_SERVER,
+        "response_port": 53,
+        "query_time": (1700000002, 999999999),
+        "query_message": dns_query(0xbeef, "example.com", 2),
+        "query_zone": name("com"),
+    }),
+    # ResolverResponse
+    dnstap(b"resolver.example", b"unbound 1.19", {
+        "type": RESOLVER_RESPONSE,
+        "socket_family": INET6,
+        "socket_protocol": TCP,
+        "response_address": V6_SERVER,
+        "response_port": 53,
+        "query_time": (1700000003, 0),
+        "query_zone": name("example.com"),
+        "response_time": (1700000003, 250000000),
+        "response_message": dns_response(0xbeef, "example.com", 1, bytes([203, 0, 113, 1])),
+    }),
+    # ClientQuery
+    dnstap(None, None, {
+        "type": CLIENT_QUERY,
+        "socket_family": INET6,
+        "socket_protocol": DOH,
+        "query_time": (1700000004, 1),
+        "query_message": dns_query(0x0001, "example.org", 16),
+        "http_protocol": HTTP2,
+    }),
+    # ClientResponse
+    dnstap(b"resolver.example", None, {
+        "type": CLIENT_RESPONSE,
+        "socket_family": INET,
+        "socket_protocol": DOT,
+        "query_time": (1700000004, 900000000),
+        "response_time": (1700000005, 42),
+        "response_message": dns_response(0x0001, "example.org", 16, b"\x05hello"),
+        "policy": {
+            "type": b"RPZ",
+            "rule": name("example.org.rpz.example"),
+            "action": LOCAL_DATA,
+            "match": QNAME,
+            "value": name("example.org"),
+        },
+    }),
+    # ForwarderQuery
+    dnstap(b"forwarder.example", b"dnsdist 1.9", {
+        "type": FORWARDER_QUERY,
+        "socket_family": INET,
+        "socket_protocol": DOT,
+        "response_address": bytes([203, 0, 113, 53]),
+        "response_port": 853,
+        "query_time": (1700000006, 600),
+        "query_message": dns_query(0x4242, "www.example.net", 1),
+        "query_zone": name("example.net"),
+    }),
+    # ForwarderResponse
+    dnstap(b"forwarder.example", b"dnsdist 1.9", {
+        "type": FORWARDER_RESPONSE,
+        "socket_family": INET,
+        "socket_protocol": DOT,
+        "response_address": bytes([203, 0, 113, 53]),
+        "response_port": 853,
+        "query_time": (1700000006, 600),
+        "query_zone": name("example.net"),
+        "response_time": (1700000006, 700),
+        "response_message": dns_response(0x4242, "www.example.net", 1, bytes([192, 0, 2, 80])),
+    }),
+    # StubQuery
+    dnstap(None, None, {
+        "type": STUB_QUERY,
+        "socket_family": INET6,
+        "socket_protocol": UDP,
+        "response_address": V6_SERVER,
+        "response_port": 53,
+        "query_time": (1700000007, 0),
+        "query_message": dns_query(0x0707, "example.com", 15),
+    }),
+    # StubResponse
+    dnstap(None, None, {
+        "type": STUB_RESPONSE,
+        "socket_family": INET6,
+        "socket_protocol": UDP,
+        "response_address": V6_SERVER,
+        "response_port": 53,
+        "query_time": (1700000007, 0),
+        "response_time": (1700000007, 1000),
+        "response_message": dns_response(0x0707, "example.com", 15, b"\x00\x0a\x00"),
+    }),
+    # ToolQuery
+    dnstap(b"kdig", b"3.3.0", {
+        "type": TOOL_QUERY,
+        "socket_family": INET,
+        "socket_protocol": TCP,
+        "response_address": V4_SERVER,
+        "response_port": 5353,
+        "query_time": (1700000008, 8),
+        "query_message": dns_query(0x0808, "example.com", 6),
+    }),
+    # ToolResponse
+    dnstap(b"kdig", b"3.3.0", {
+        "type": TOOL_RESPONSE,
+        "socket_family": INET,
+        "socket_protocol": TCP,
+        "response_address": V4_SERVER,
+        "response_port": 5353,
+        "query_time": (1700000008, 8),
+        "response_time": (1700000008, 88),
+        "response_message": dns_response(0x0808, "example.com", 16, b"\x02ok"),
+    }),
+    # UpdateQuery
+    dnstap(b"ns1.example", b"knot 3.3", {
+        "type": UPDATE_QUERY,
+        "socket_family": INET,
+        "socket_protocol": TCP,
+        "query_address": V4_CLIENT,
+        "query_port": 49152,
+        "query_time": (1700000009, 9),
+        "query_message": dns_update(0x0909, "example.com"),
+    }),
+    # UpdateResponse
+    dnstap(b"ns1.example", b"knot 3.3", {
+        "type": UPDATE_RESPONSE,
+        "socket_family": INET,
+        "socket_protocol": TCP,
+        "query_address": V4_CLIENT,
+        "query_port": 49152,
+        "query_time": (1700000009, 9),
+        "response_time": (1700000009, 2009),
+        "response_message": dns_update(0x0909, "example.com", response=True),
+    }),
+]
+
+
+def main():
+    out = control_frame(FSTRM_CONTROL_START, CONTENT_TYPE)
+    for payload in MESSAGES:
+        out += data_frame(payload)
+    out += control_frame(FSTRM_CONTROL_STOP)
+    path = os.path.join(os.path.dirname(os.path.abspath(__file__)), "reference.dnstap")
+    with open(path, "wb") as f:
+        f.write(out)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,71 @@
+//! Tests of the `dnstap-cat` tool.
+
+use std::process::Command;
+
+#[test]
+fn quiet_output_of_the_reference_capture() {
+    let output = Command::new(env!("CARGO_BIN_EXE_dnstap-cat"))
+        .args(["-r", "tests/data/reference.dnstap"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        include_str!("data/reference.quiet.txt")
+    );
+}
+
+/// The YAML output is modelled on `dnstap -y` in `golang-dnstap`. The expected output was
+/// written by hand, see `tests/data/README.md`.
+#[test]
+fn yaml_output_of_the_reference_capture() {
+    let output = Command::new(env!("CARGO_BIN_EXE_dnstap-cat"))
+        .args(["-y", "-r", "tests/data/reference.dnstap"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        include_str!("data/reference.yaml")
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_output_with_dns_fields() {
+    let json = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_dnstap-cat"))
+            .args(args)
+            .args(["-r", "tests/data/reference.dnstap"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let without_dns_fields = json(&["-j"]);
+    assert!(!without_dns_fields.contains("\"qname\":"));
+    let with_dns_fields = json(&["-j", "-d"]);
+    assert!(with_dns_fields.contains("\"qname\":\"www.example.com.\""));
+    assert_eq!(json(&["-d", "-j"]), with_dns_fields);
+
+    for args in [&["-d"][..], &["-d", "-y"], &["-j", "-d", "-q"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_dnstap-cat"))
+            .args(args)
+            .args(["-r", "tests/data/reference.dnstap"])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+}
+
+#[test]
+fn usage() {
+    let output = Command::new(env!("CARGO_BIN_EXE_dnstap-cat"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("Usage: dnstap-cat"));
+}
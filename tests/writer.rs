@@ -0,0 +1,244 @@
+//! Loopback tests of `DNSTapWriter`.
+
+// `Sender::send()` returns errors from the deprecated `mio::channel` module
+#![allow(deprecated)]
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use dnstap::*;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn dns_query(id: u16, qname: &str) -> Vec<u8> {
+    let mut packet = id.to_be_bytes().to_vec();
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in qname.trim_end_matches('.').split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.extend_from_slice(&[0, 0, 1, 0, 1]);
+    packet
+}
+
+fn query(id: u16) -> DNSMessage {
+    let mut dns_message = DNSMessage::new(None, None, MessageType::RESOLVER_QUERY);
+    dns_message.query_time = Some(Duration::from_secs(1_700_000_000));
+    dns_message.query_packet = Some(dns_query(id, "www.example.com"));
+    dns_message
+}
+
+fn collector() -> (SocketAddr, DNSTapCollector, mpsc::Receiver<DNSMessage>) {
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    let collector = DNSTapCollectorBuilder::default()
+        .tcp_socket_addr("127.0.0.1:0".parse().unwrap())
+        .listen()
+        .unwrap();
+    let addr = collector.tcp_local_addr().unwrap();
+    let collector = collector
+        .start(move |_, dns_message| tx.lock().unwrap().send(dns_message).unwrap())
+        .unwrap();
+    (addr, collector, rx)
+}
+
+fn assert_rejected(sender: &Sender, dns_message: DNSMessage, expected: &str) {
+    match sender.send(dns_message) {
+        Err(mio::channel::TrySendError::Io(e)) => {
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(e.to_string(), expected);
+        }
+        res => panic!("unexpected result: {:?}", res.map_err(|e| e.to_string())),
+    }
+}
+
+#[test]
+fn sender_rejects_messages_that_cannot_be_encoded() {
+    let (addr, _collector, rx) = collector();
+    let writer = DNSTapBuilder::default()
+        .tcp_socket_addr(addr)
+        .listen()
+        .unwrap()
+        .start()
+        .unwrap();
+    let sender = writer.sender();
+
+    let mut dns_message = query(1);
+    dns_message.bailiwick = Some("example..com".to_owned());
+    assert_rejected(&sender, dns_message, "Empty label");
+
+    let mut dns_message = query(2);
+    dns_message.query_address = Some("192.0.2.1".parse().unwrap());
+    dns_message.response_address = Some("2001:db8::53".parse().unwrap());
+    assert_rejected(
+        &sender,
+        dns_message,
+        "Response address does not match the socket family",
+    );
+
+    let mut dns_message = query(3);
+    dns_message.socket_protocol = Some(SocketProtocol::DOT);
+    dns_message.http_protocol = Some(HttpProtocol::HTTP2);
+    assert_rejected(
+        &sender,
+        dns_message,
+        "HTTP protocol set on a non-DoH message",
+    );
+
+    let mut dns_message = query(4);
+    dns_message.bailiwick = Some("example.com.".to_owned());
+    sender.send(dns_message).unwrap();
+    let dns_message = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(
+        dns_message.query_packet,
+        Some(dns_query(4, "www.example.com"))
+    );
+    assert_eq!(dns_message.bailiwick, Some("example.com.".to_owned()));
+    assert_eq!(writer.dropped_messages(), 0);
+}
+
+#[test]
+fn strict_sender_rejects_invalid_messages() {
+    let (addr, _collector, rx) = collector();
+    let writer = DNSTapBuilder::default()
+        .tcp_socket_addr(addr)
+        .strict(true)
+        .listen()
+        .unwrap()
+        .start()
+        .unwrap();
+    let sender = writer.sender();
+
+    let mut dns_message = query(1);
+    dns_message.query_port = Some(0);
+    assert_rejected(&sender, dns_message, "Invalid query port");
+
+    let mut dns_message = query(2);
+    dns_message.query_packet = None;
+    assert_rejected(&sender, dns_message, "Query message without a query packet");
+
+    assert_rejected(&sender, query(3), "Message without a socket family");
+
+    let mut dns_message = query(4);
+    dns_message.socket_protocol = Some(SocketProtocol::UDP);
+    dns_message.response_address = Some("192.0.2.53".parse().unwrap());
+    dns_message.response_port = Some(53);
+    assert_rejected(
+        &sender,
+        dns_message.clone(),
+        "Resolver or forwarder message without a bailiwick",
+    );
+    dns_message.bailiwick = Some("example.com.".to_owned());
+    sender.send(dns_message).unwrap();
+    let dns_message = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(
+        dns_message.query_packet,
+        Some(dns_query(4, "www.example.com"))
+    );
+
+    // Missing times are set by the clock before the message is checked
+    let mut dns_message = DNSMessage::new(None, None, MessageType::STUB_RESPONSE);
+    dns_message.socket_family = Some(SocketFamily::INET6);
+    dns_message.socket_protocol = Some(SocketProtocol::DOT);
+    dns_message.query_time = Some(Duration::from_secs(1_700_000_000));
+    dns_message.response_packet = Some(dns_query(5, "www.example.com"));
+    sender.send(dns_message).unwrap();
+    let dns_message = rx.recv_timeout(TIMEOUT).unwrap();
+    assert!(dns_message.response_time.is_some());
+    assert_eq!(writer.dropped_messages(), 0);
+}
+
+struct ManualClock(Mutex<SystemTime>);
+
+impl ManualClock {
+    fn set(&self, now: SystemTime) {
+        *self.0.lock().unwrap() = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[test]
+fn writer_timestamps_messages_with_its_clock() {
+    let (addr, _collector, rx) = collector();
+    let sent = UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+    let clock = Arc::new(ManualClock(Mutex::new(sent)));
+    let pending_writer = DNSTapBuilder::default()
+        .tcp_socket_addr(addr)
+        .clock(clock.clone())
+        .listen()
+        .unwrap();
+    let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_QUERY);
+    dns_message.query_packet = Some(dns_query(0x1234, "www.example.com"));
+    pending_writer.sender().send(dns_message).unwrap();
+    pending_writer.sender().send(query(0x1234)).unwrap();
+
+    // Messages are timestamped when they are sent, not when they are written
+    clock.set(sent + Duration::from_secs(60));
+    let writer = pending_writer.start().unwrap();
+    let mut dns_message = DNSMessage::new(None, None, MessageType::CLIENT_RESPONSE);
+    dns_message.response_packet = Some(dns_query(0x1234, "www.example.com"));
+    writer.sender().send(dns_message).unwrap();
+
+    let dns_message = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(dns_message.query_system_time(), Some(sent));
+    let dns_message = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(
+        dns_message.query_time,
+        Some(Duration::from_secs(1_700_000_000))
+    );
+    let dns_message = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(dns_message.query_time, None);
+    assert_eq!(
+        dns_message.response_system_time(),
+        Some(sent + Duration::from_secs(60))
+    );
+}
+
+#[test]
+fn writer_omits_dropped_packets() {
+    let (addr, _collector, rx) = collector();
+    let writer = DNSTapBuilder::default()
+        .tcp_socket_addr(addr)
+        .packet_minimization(PacketMinimization::Drop)
+        .listen()
+        .unwrap()
+        .start()
+        .unwrap();
+    let mut dns_message = DNSMessage::new(None, None, MessageType::RESOLVER_RESPONSE);
+    dns_message.query_address = Some("192.0.2.1".parse().unwrap());
+    dns_message.query_packet = Some(dns_query(0x1234, "www.example.com"));
+    dns_message.response_time = Some(Duration::from_secs(1_700_000_000));
+    dns_message.response_packet = Some(dns_query(0x1234, "www.example.com"));
+    writer.sender().send(dns_message).unwrap();
+    writer.sender().send(query(0x1234)).unwrap();
+
+    let dns_message = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(dns_message.message_type, MessageType::RESOLVER_RESPONSE);
+    assert_eq!(
+        dns_message.query_address,
+        Some("192.0.2.1".parse().unwrap())
+    );
+    assert_eq!(
+        dns_message.response_time,
+        Some(Duration::from_secs(1_700_000_000))
+    );
+    assert_eq!(dns_message.query_packet, None);
+    assert_eq!(dns_message.response_packet, None);
+    assert_eq!(dns_message.summary, None);
+
+    let dns_message = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(dns_message.message_type, MessageType::RESOLVER_QUERY);
+    assert_eq!(
+        dns_message.query_time,
+        Some(Duration::from_secs(1_700_000_000))
+    );
+    assert_eq!(dns_message.query_packet, None);
+    assert_eq!(writer.dropped_messages(), 0);
+}